@@ -0,0 +1,156 @@
+use axum::{
+  extract::{Path, Query, State},
+  http::{header::{AUTHORIZATION, CONTENT_TYPE}, HeaderMap, StatusCode},
+  response::{IntoResponse, Response},
+  routing::{get, post},
+  Json, Router
+};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::{
+  Pool, Sqlite
+};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tracing::error;
+
+use crate::database::Database;
+use crate::scheduler::{
+  ModuleControl, Modules
+};
+use crate::error::{
+  Error, Result
+};
+
+const DEFAULT_EVENTS: usize = 20;
+
+/// Everything the endpoints read from: the scheduler's modules and the same database pool the modules use.
+#[derive(Clone)]
+pub struct ApiState {
+  pub modules: Modules,
+  pub database_pool: Pool<Sqlite>,
+  /// Required as `Authorization: Bearer <token>` by everything that changes something.
+  pub token: Option<String>
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+  limit: Option<usize>
+}
+
+pub fn router(state: ApiState) -> Router {
+  Router::new()
+    .route("/health", get(health))
+    .route("/ready", get(ready))
+    .route("/metrics", get(metrics))
+    .route("/modules", get(modules))
+    .route("/modules/:key/events", get(events))
+    .route("/modules/:key/check", post(check))
+    .route("/modules/:key/pause", post(pause))
+    .route("/modules/:key/resume", post(resume))
+    .with_state(state)
+}
+
+/// Binds early, so that a taken port is reported like any other configuration error.
+pub async fn bind(address: &str) -> Result<TcpListener> {
+  TcpListener::bind(address).await.map_err(|e| Error::Config(format!("The api can't listen on {:?}: {}", address, e)))
+}
+
+pub async fn serve(listener: TcpListener, state: ApiState) {
+  if let Err(e) = axum::serve(listener, router(state)).await {
+    error!("The api stopped: {}", e);
+  }
+}
+
+/// Answered as `{"error": "..."}` with the status.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+  fn into_response(self) -> Response {
+    (self.0, Json(json!({ "error": self.1 }))).into_response()
+  }
+}
+
+type ApiResult = std::result::Result<Response, ApiError>;
+
+fn module(state: &ApiState, key: &str) -> std::result::Result<Arc<ModuleControl>, ApiError> {
+  state.modules.read().unwrap().get(key).cloned().ok_or_else(|| ApiError(StatusCode::NOT_FOUND, "No such module".to_string()))
+}
+
+fn authorize(state: &ApiState, headers: &HeaderMap) -> std::result::Result<(), ApiError> {
+  let Some(token) = &state.token else {
+    return Ok(());
+  };
+  let authorization = headers.get(AUTHORIZATION).and_then(|value| value.to_str().ok()).unwrap_or_default();
+  if authorization.strip_prefix("Bearer ") == Some(token.as_str()) {
+    Ok(())
+  } else {
+    Err(ApiError(StatusCode::UNAUTHORIZED, "Missing or wrong api token".to_string()))
+  }
+}
+
+fn module_json(key: &str, module: &ModuleControl) -> serde_json::Value {
+  json!({
+    "key": key,
+    "module_type": module.module_type.to_string(),
+    "id": module.id,
+    "interval_minutes": module.interval.as_secs() / 60,
+    "paused": module.is_paused(),
+    "status": module.status()
+  })
+}
+
+async fn health() -> Response {
+  Json(json!({ "status": "ok" })).into_response()
+}
+
+/// Ready once the database answers.
+async fn ready(State(state): State<ApiState>) -> ApiResult {
+  sqlx::query("SELECT 1").execute(&state.database_pool).await
+    .map_err(|e| ApiError(StatusCode::SERVICE_UNAVAILABLE, format!("The database isn't available: {}", e)))?;
+  Ok(Json(json!({ "status": "ready", "modules": state.modules.read().unwrap().len() })).into_response())
+}
+
+async fn metrics() -> Response {
+  ([(CONTENT_TYPE, "text/plain; version=0.0.4")], crate::metrics::render()).into_response()
+}
+
+async fn modules(State(state): State<ApiState>) -> Response {
+  let modules: Vec<serde_json::Value> = state.modules.read().unwrap().iter().map(|(key, module)| module_json(key, module)).collect();
+  Json(modules).into_response()
+}
+
+/// The newest delivered events first, `?limit=` of them (default: 20).
+async fn events(State(state): State<ApiState>, Path(key): Path<String>, Query(query): Query<EventsQuery>) -> ApiResult {
+  let module = module(&state, &key)?;
+  let internal = |e: Error| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+  let mut database = Database::use_pool(state.database_pool.clone()).await.map_err(internal)?;
+  let mut events = database.get_history_events(module.module_type.to_string(), &module.id).await.map_err(internal)?;
+  events.truncate(query.limit.unwrap_or(DEFAULT_EVENTS));
+  Ok(Json(events).into_response())
+}
+
+async fn check(State(state): State<ApiState>, Path(key): Path<String>, headers: HeaderMap) -> ApiResult {
+  authorize(&state, &headers)?;
+  let module = module(&state, &key)?;
+  if module.is_paused() {
+    return Err(ApiError(StatusCode::CONFLICT, "The module is paused".to_string()));
+  }
+  module.check_now();
+  Ok((StatusCode::ACCEPTED, Json(module_json(&key, &module))).into_response())
+}
+
+async fn pause(State(state): State<ApiState>, Path(key): Path<String>, headers: HeaderMap) -> ApiResult {
+  set_paused(state, key, headers, true)
+}
+
+async fn resume(State(state): State<ApiState>, Path(key): Path<String>, headers: HeaderMap) -> ApiResult {
+  set_paused(state, key, headers, false)
+}
+
+fn set_paused(state: ApiState, key: String, headers: HeaderMap, paused: bool) -> ApiResult {
+  authorize(&state, &headers)?;
+  let module = module(&state, &key)?;
+  module.pause(paused);
+  Ok(Json(module_json(&key, &module)).into_response())
+}
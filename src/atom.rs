@@ -0,0 +1,109 @@
+use quick_xml::escape::escape;
+use serde::Serialize;
+
+use crate::discord::unix_to_datetime;
+use crate::notifiers::Event;
+use crate::web::NyaaTorrent;
+
+/// One detected event as it's kept in the database, for the atom feeds and the api.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEvent {
+  /// Assigned by the database, 0 until the event has been stored.
+  pub id: i64,
+  /// `upload`, `new_comment`, `edited_comment`, `deleted_comment` or `series_alert`
+  pub kind: String,
+  pub domain: String,
+  pub torrent_id: u64,
+  pub torrent_title: String,
+  pub category: String,
+  pub size: String,
+  /// The uploader, or whoever wrote the comment.
+  pub username: Option<String>,
+  pub message: Option<String>,
+  pub old_message: Option<String>,
+  pub link: String,
+  pub timestamp: f64
+}
+
+impl HistoryEvent {
+  pub fn new(torrent: &NyaaTorrent, event: &Event<'_>) -> Self {
+    let mut history_event = HistoryEvent {
+      id: 0,
+      kind: event.name().to_string(),
+      domain: torrent.domain.clone(),
+      torrent_id: torrent.id,
+      torrent_title: torrent.title.clone(),
+      category: torrent.category.clone(),
+      size: torrent.size.clone(),
+      username: torrent.uploader.as_ref().map(|uploader| uploader.username.clone()),
+      message: None,
+      old_message: None,
+      link: format!("{}view/{}", torrent.domain, torrent.id),
+      timestamp: torrent.upload_date_timestamp
+    };
+    if let Event::SeriesAlert(alert) = event {
+      history_event.message = Some(alert.message());
+    }
+    if let Event::NewComment(comment) | Event::EditedComment(comment) | Event::DeletedComment(comment) = event {
+      history_event.username = Some(comment.user.username.clone());
+      history_event.message = Some(comment.message.clone());
+      history_event.old_message = comment.old_message.clone();
+      history_event.link = comment.direct_link.clone();
+      history_event.timestamp = match event {
+        Event::EditedComment(_) => comment.edited_timestamp.unwrap_or(comment.date_timestamp),
+        Event::DeletedComment(_) => chrono::Utc::now().timestamp() as f64,
+        _ => comment.date_timestamp
+      };
+    }
+    history_event
+  }
+
+  fn title(&self) -> String {
+    let user = self.username.clone().unwrap_or("Anonymous".to_string());
+    match self.kind.as_str() {
+      "upload" => format!("New upload: {}", self.torrent_title),
+      "edited_comment" => format!("{} edited a comment on {}", user, self.torrent_title),
+      "deleted_comment" => format!("{} deleted a comment on {}", user, self.torrent_title),
+      "series_alert" => format!("Series alert: {}", self.torrent_title),
+      _ => format!("{} commented on {}", user, self.torrent_title)
+    }
+  }
+
+  /// The entry's content as escaped html, edits show the old message struck through above the new one.
+  fn content(&self) -> String {
+    let paragraph = |text: &str| format!("<p>{}</p>", escape(text).replace('\n', "<br>"));
+    let mut html = match self.kind.as_str() {
+      "upload" => format!("<p>Category: {}<br>Size: {}</p><p><a href=\"{}download/{}.torrent\">Torrent-File</a></p>",
+        escape(&self.category), escape(&self.size), self.domain, self.torrent_id),
+      "deleted_comment" => format!("<p><del>{}</del></p>", escape(self.message.as_deref().unwrap_or_default())),
+      _ => paragraph(self.message.as_deref().unwrap_or_default())
+    };
+    if let Some(old_message) = self.old_message.as_deref().filter(|_| self.kind == "edited_comment") {
+      html = format!("<p><del>{}</del></p>{}", escape(old_message), html);
+    }
+    html
+  }
+}
+
+/// An atom document with the events in the given order, newest first is what readers expect.
+pub fn atom_feed(title: &str, feed_id: &str, events: &[HistoryEvent]) -> String {
+  let updated = events.iter().map(|event| event.timestamp).reduce(f64::max).map(unix_to_datetime).unwrap_or_else(chrono::Utc::now);
+  let mut feed = format!(
+    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n  <title>{}</title>\n  <id>{}</id>\n  <updated>{}</updated>\n  <generator>nyaa-notifications</generator>\n",
+    escape(title), escape(feed_id), updated.to_rfc3339()
+  );
+  for event in events {
+    feed.push_str(&format!(
+      "  <entry>\n    <title>{}</title>\n    <id>{}:{}</id>\n    <updated>{}</updated>\n    <link href=\"{}\"/>\n    <author><name>{}</name></author>\n    <category term=\"{}\"/>\n    <content type=\"html\">{}</content>\n  </entry>\n",
+      escape(&event.title()),
+      escape(feed_id), event.id,
+      unix_to_datetime(event.timestamp).to_rfc3339(),
+      escape(&event.link),
+      escape(event.username.as_deref().unwrap_or("Anonymous")),
+      escape(&event.category),
+      escape(&event.content())
+    ));
+  }
+  feed.push_str("</feed>\n");
+  feed
+}
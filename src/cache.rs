@@ -0,0 +1,165 @@
+use std::{
+  path::PathBuf, time::Duration
+};
+use serde::{
+  Deserialize, Serialize
+};
+use tracing::warn;
+
+/// Pages which haven't been needed for this long are removed on startup.
+pub const CACHE_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Decides how long a downloaded page can be used without asking nyaa again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PageKind {
+  Feed,
+  Torrent,
+  User
+}
+
+impl PageKind {
+  pub fn max_age(&self) -> Duration {
+    match self {
+      // Listings and comments are what we're looking for, they always have to be revalidated.
+      PageKind::Feed | PageKind::Torrent => Duration::ZERO,
+      // Only needed for the avatar, which hardly ever changes.
+      PageKind::User => Duration::from_secs(7 * 24 * 60 * 60)
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedPage {
+  pub url: String,
+  pub etag: Option<String>,
+  pub last_modified: Option<String>,
+  pub fetched_at: i64,
+  pub body: String
+}
+
+impl CachedPage {
+  pub fn is_fresh(&self, kind: PageKind) -> bool {
+    let age = chrono::Utc::now().timestamp() - self.fetched_at;
+    age >= 0 && (age as u64) < kind.max_age().as_secs()
+  }
+}
+
+/// Pages are stored as one json file per url, so that a restart doesn't have to download everything again.
+pub struct PageCache {
+  directory: PathBuf
+}
+
+impl PageCache {
+  pub fn new(directory: impl Into<PathBuf>) -> Self {
+    PageCache { directory: directory.into() }
+  }
+
+  fn path(&self, url: &str) -> PathBuf {
+    self.directory.join(format!("{:016x}.json", fnv1a(url)))
+  }
+
+  pub async fn get(&self, url: &str) -> Option<CachedPage> {
+    let file = tokio::fs::read(self.path(url)).await.ok()?;
+    let page = serde_json::from_slice::<CachedPage>(&file).ok()?;
+    // a hash collision would return someone else's page
+    (page.url == url).then_some(page)
+  }
+
+  /// A cache that can't be written only costs another download, so failures are just reported.
+  pub async fn insert(&self, page: &CachedPage) {
+    if let Err(e) = tokio::fs::create_dir_all(&self.directory).await {
+      warn!("Failed to create {:?}: {}", self.directory, e);
+      return;
+    }
+    let json = match serde_json::to_vec(page) {
+      Ok(json) => json,
+      Err(e) => {
+        warn!("Failed to serialize cached page {:?}: {}", page.url, e);
+        return;
+      }
+    };
+    // written next to the real file first, so that a crash never leaves half a page behind
+    let path = self.path(&page.url);
+    let temporary = path.with_extension("tmp");
+    if let Err(e) = async {
+      tokio::fs::write(&temporary, json).await?;
+      tokio::fs::rename(&temporary, &path).await
+    }.await {
+      warn!("Failed to write cached page {:?}: {}", page.url, e);
+    }
+  }
+
+  /// Removes pages which haven't been fetched or revalidated for `max_age`.
+  pub async fn prune(&self, max_age: Duration) {
+    let Ok(mut entries) = tokio::fs::read_dir(&self.directory).await else {
+      return;
+    };
+    let oldest = chrono::Utc::now().timestamp() - max_age.as_secs() as i64;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+      let stale = match tokio::fs::read(entry.path()).await {
+        Ok(file) => serde_json::from_slice::<CachedPage>(&file).map_or(true, |page| page.fetched_at < oldest),
+        Err(_) => true
+      };
+      if stale {
+        let _ = tokio::fs::remove_file(entry.path()).await;
+      }
+    }
+  }
+}
+
+// std's hasher isn't guaranteed to be stable between releases, but the file names have to be.
+fn fnv1a(text: &str) -> u64 {
+  text.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn page(url: &str, fetched_at: i64) -> CachedPage {
+    CachedPage { url: url.to_string(), etag: Some("\"1\"".to_string()), last_modified: None, fetched_at, body: "<html></html>".to_string() }
+  }
+
+  fn scratch_cache(name: &str) -> PageCache {
+    let directory = std::env::temp_dir().join(format!("nyaa-notifications-cache-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    PageCache::new(directory)
+  }
+
+  #[test]
+  fn only_user_pages_stay_fresh() {
+    assert_eq!(PageKind::Feed.max_age(), Duration::ZERO);
+    assert_eq!(PageKind::Torrent.max_age(), Duration::ZERO);
+    assert_eq!(PageKind::User.max_age(), Duration::from_secs(7 * 24 * 60 * 60));
+    let now = chrono::Utc::now().timestamp();
+    assert!(page("https://nyaa.si/user/subsplease", now - 60).is_fresh(PageKind::User));
+    assert!(!page("https://nyaa.si/user/subsplease", now - 8 * 24 * 60 * 60).is_fresh(PageKind::User));
+    assert!(!page("https://nyaa.si/view/1720000", now).is_fresh(PageKind::Torrent));
+  }
+
+  #[tokio::test]
+  async fn pages_are_kept_by_url() {
+    let cache = scratch_cache("round-trip");
+    let stored = page("https://nyaa.si/view/1720000", 1720000000);
+    assert!(cache.get(&stored.url).await.is_none());
+    cache.insert(&stored).await;
+    let read = cache.get(&stored.url).await.unwrap();
+    assert_eq!((read.url, read.etag, read.fetched_at, read.body), (stored.url, stored.etag, stored.fetched_at, stored.body));
+    assert!(cache.get("https://nyaa.si/view/1720001").await.is_none());
+    let _ = std::fs::remove_dir_all(&cache.directory);
+  }
+
+  #[tokio::test]
+  async fn prune_removes_old_and_unreadable_pages() {
+    let cache = scratch_cache("prune");
+    let now = chrono::Utc::now().timestamp();
+    cache.insert(&page("https://nyaa.si/view/1", now - 60)).await;
+    cache.insert(&page("https://nyaa.si/view/2", now - 40 * 24 * 60 * 60)).await;
+    std::fs::write(cache.directory.join("broken.json"), "{").unwrap();
+    cache.prune(CACHE_RETENTION).await;
+    assert!(cache.get("https://nyaa.si/view/1").await.is_some());
+    assert!(cache.get("https://nyaa.si/view/2").await.is_none());
+    assert!(!cache.directory.join("broken.json").exists());
+    let _ = std::fs::remove_dir_all(&cache.directory);
+  }
+}
@@ -3,7 +3,7 @@ use serenity::all::{
 };
 
 pub async fn run(options: &[CommandDataOption], ctx: &Context) -> String {
-  let discord_activity_type = match &options.get(0).unwrap().value {
+  let discord_activity_type = match &options.first().unwrap().value {
     CommandDataOptionValue::String(text) => text,
     _ => {
       panic!("Discord returned invalid command options.")
@@ -4,11 +4,14 @@ use serenity::all::{
 use sqlx::{
   Pool, Sqlite
 };
+use tracing::{
+  error, info
+};
 
 use crate::database::Database;
 
 pub async fn run(options: &[CommandDataOption], discord_bot_id: &String, database_pool: Pool<Sqlite>) -> String {
-  let channel_id = match options.get(0).unwrap().value {
+  let channel_id = match options.first().unwrap().value {
     CommandDataOptionValue::Channel(integer) => integer.get(),
     _ => {
       panic!("Discord returned invalid command options.")
@@ -38,19 +41,31 @@ pub async fn run(options: &[CommandDataOption], discord_bot_id: &String, databas
       panic!("Discord returned invalid command options.")
     }
   };
-  let pinged_role: String = match &options.get(5) {
-    Some(arg) => {
-      match &arg.value {
-        CommandDataOptionValue::Role(role) => role.get().to_string(),
-        _ => {
-          panic!("Discord returned invalid command options.")
-        }
-      }
+  // optional options are only sent when they're set, so they have to be looked up by name
+  let optional = |name: &str| options.iter().find(|option| option.name == name).map(|option| &option.value);
+  let pinged_role: String = match optional("pinged-role") {
+    Some(CommandDataOptionValue::Role(role)) => role.get().to_string(),
+    Some(_) => {
+      panic!("Discord returned invalid command options.")
     },
     None => {
       "0".to_string()
     }
   };
+  let regex = |name: &str| match optional(name) {
+    Some(CommandDataOptionValue::String(text)) => Some(text.trim().to_string()),
+    Some(_) => {
+      panic!("Discord returned invalid command options.")
+    },
+    None => None
+  };
+  let title_include = regex("title-include");
+  let title_exclude = regex("title-exclude");
+  for pattern in title_include.iter().chain(&title_exclude) {
+    if let Err(e) = regex::Regex::new(pattern) {
+      return format!("`{}` isn't a valid regex: {}", pattern, e);
+    }
+  }
 
   let mut database: Database;
   if let Ok(database_) = Database::use_pool(database_pool).await {
@@ -59,15 +74,25 @@ pub async fn run(options: &[CommandDataOption], discord_bot_id: &String, databas
     return "Failed to connect to database".to_string();
   }
 
-  if database.discord_channel_exists(discord_bot_id, channel_id).await {
-    return "This discord channel has already been configured, please make sure to `/reset` it before creating new settings.".to_string();
+  match database.discord_channel_exists(discord_bot_id, channel_id).await {
+    Ok(false) => {},
+    Ok(true) => {
+      return "This discord channel has already been configured, please make sure to `/reset` it before creating new settings.".to_string();
+    },
+    Err(e) => {
+      error!("{}", e);
+      return "Failed to read the channel configuration.".to_string();
+    }
   }
   
   let urls: Vec<String> = url_input.split(',').map(|str| str.trim().to_string()).collect();
 
-  println!("[INF] {:?} configured with {:?} | {} {} {}", channel_id, urls, uploads, comments, complete);
+  info!("{:?} configured with {:?} | {} {} {} | {:?} {:?}", channel_id, urls, uploads, comments, complete, title_include, title_exclude);
   
-  database.add_discord_channel(discord_bot_id, channel_id, urls, (comments, uploads, complete), pinged_role.clone()).await;
+  if let Err(e) = database.add_discord_channel(discord_bot_id, channel_id, urls, (comments, uploads, complete), pinged_role.clone(), (title_include, title_exclude)).await {
+    error!("{}", e);
+    return "Failed to save the channel configuration.".to_string();
+  }
   "Channel successfully configured.".to_string()
 }
 
@@ -125,5 +150,21 @@ pub fn register() -> CreateCommand {
       )
       .required(false)
     )
+    .add_option(
+      CreateCommandOption::new(
+        CommandOptionType::String,
+        "title-include",
+        "Only torrents with a title matching this regex (f.e.: `Frieren.*1080p`)"
+      )
+      .required(false)
+    )
+    .add_option(
+      CreateCommandOption::new(
+        CommandOptionType::String,
+        "title-exclude",
+        "No torrents with a title matching this regex (f.e.: `(?i)batch`)"
+      )
+      .required(false)
+    )
     .default_member_permissions(Permissions::ADMINISTRATOR)
 }
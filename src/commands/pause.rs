@@ -4,11 +4,12 @@ use serenity::all::{
 use sqlx::{
   Sqlite, Pool
 };
+use tracing::error;
 
 use crate::database::Database;
 
 pub async fn run(options: &[CommandDataOption], discord_bot_id: &String, database_pool: Pool<Sqlite>) -> String {
-  let channel_id = match options.get(0).unwrap().value {
+  let channel_id = match options.first().unwrap().value {
     CommandDataOptionValue::Integer(integer) => integer as u64,
     _ => {
       panic!("Discord returned invalid command options.")
@@ -22,13 +23,23 @@ pub async fn run(options: &[CommandDataOption], discord_bot_id: &String, databas
     return "Failed to connect to database".to_string();
   }
 
-  let check = database.get_discord_channels(discord_bot_id).await;
-  if check.is_empty() {
+  let check = match database.get_discord_channels(discord_bot_id).await {
+    Ok(channels) => channels,
+    Err(e) => {
+      error!("{}", e);
+      return "Failed to read the channel configuration.".to_string();
+    }
+  };
+  let mode = if let Some(module) = check.iter().find(|module| module.discord_channel_id == Some(channel_id)) {
+    !module.active
+  } else {
     return "This discord channel has not been configured yet. Type `/create` to set it up.".to_string();
-  }
-  let mode = !check.iter().find(|module| module.discord_channel_id.unwrap() == channel_id).unwrap().active;
+  };
 
-  database.pause_discord_channel(discord_bot_id, channel_id, mode).await;
+  if let Err(e) = database.pause_discord_channel(discord_bot_id, channel_id, mode).await {
+    error!("{}", e);
+    return "Failed to update the channel configuration.".to_string();
+  }
   if !mode {
     "Successfully paused your channel.".to_string()
   } else {
@@ -4,11 +4,14 @@ use serenity::all::{
 use sqlx::{
   Sqlite, Pool
 };
+use tracing::{
+  error, info
+};
 
 use crate::database::Database;
 
 pub async fn run(options: &[CommandDataOption], discord_bot_id: &String, database_pool: Pool<Sqlite>) -> String {
-	let channel_id = match options.get(0).unwrap().value {
+	let channel_id = match options.first().unwrap().value {
     CommandDataOptionValue::Integer(integer) => integer as u64,
     _ => {
       panic!("Discord returned invalid command options.")
@@ -22,13 +25,23 @@ pub async fn run(options: &[CommandDataOption], discord_bot_id: &String, databas
     return "Failed to connect to database".to_string();
   }
 
-  if ! database.discord_channel_exists(discord_bot_id, channel_id).await {
-    return "This discord channel has never been configured. See `/create`.".to_string();
+  match database.discord_channel_exists(discord_bot_id, channel_id).await {
+    Ok(true) => {},
+    Ok(false) => {
+      return "This discord channel has never been configured. See `/create`.".to_string();
+    },
+    Err(e) => {
+      error!("{}", e);
+      return "Failed to read the channel configuration.".to_string();
+    }
   }
 
-  database.remove_discord_channel(discord_bot_id, channel_id).await;
+  if let Err(e) = database.remove_discord_channel(discord_bot_id, channel_id).await {
+    error!("{}", e);
+    return "Failed to remove the channel configuration.".to_string();
+  }
 
-	println!("Configuration removed for {:?}", channel_id);
+	info!("Configuration removed for {:?}", channel_id);
 	"Channel configuration successfully removed.".to_string()
 }
 
@@ -1,27 +1,48 @@
 use std::{
+  collections::BTreeMap,
   fs::{
     File, self
   },
-  path::Path,
   io::Write
 };
 use serde::Deserialize;
 
-use crate::{
-  NYAA_FOLDER_PATH, NYAA_CONFIG_PATH, database::Database
+use crate::paths;
+use crate::filter::Filter;
+use crate::error::{
+  Error, Result
 };
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
   pub update_interval: u64,
+  pub nyaa_requests_per_minute: Option<u32>,
+  /// Where the status and control api listens, it's off without one.
+  pub api_address: Option<String>,
+  pub api_token: Option<String>,
   pub module: Vec<ModuleConfig>
 }
 
-#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq, Hash)]
 pub enum ModuleType {
+  #[default]
   Email,
   Gotify,
-  Discord
+  Discord,
+  Webhook,
+  Telegram,
+  Matrix,
+  Ntfy,
+  Slack,
+  Pushover,
+  Atom
+}
+
+impl ModuleType {
+  /// Modules which show the uploader's name and avatar, new uploads are only announced once both are known.
+  pub fn shows_uploader(&self) -> bool {
+    matches!(self, ModuleType::Discord | ModuleType::Telegram | ModuleType::Slack)
+  }
 }
 
 impl std::fmt::Display for ModuleType {
@@ -35,19 +56,102 @@ impl std::fmt::Display for ModuleType {
       },
       ModuleType::Discord => {
         write!(f, "Discord")
+      },
+      ModuleType::Webhook => {
+        write!(f, "Webhook")
+      },
+      ModuleType::Telegram => {
+        write!(f, "Telegram")
+      },
+      ModuleType::Matrix => {
+        write!(f, "Matrix")
+      },
+      ModuleType::Ntfy => {
+        write!(f, "Ntfy")
+      },
+      ModuleType::Slack => {
+        write!(f, "Slack")
+      },
+      ModuleType::Pushover => {
+        write!(f, "Pushover")
+      },
+      ModuleType::Atom => {
+        write!(f, "Atom")
       }
     }
   }
 }
 
-#[derive(Debug, Clone, Deserialize)]
+/// How a feed is read: `Auto` prefers the rss feed and falls back to the html listing if it fails.
+/// The rss feed only contains the first page, so `retrieve_all_pages` always uses the html listing unless `Rss` is forced.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+pub enum FeedMode {
+  Rss,
+  Html,
+  #[default]
+  Auto
+}
+
+/// Either just the url, or a table if the feed needs its own settings.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum FeedConfig {
+  Url(String),
+  Table {
+    url: String,
+    mode: Option<FeedMode>,
+    #[serde(flatten)]
+    rules: Box<Rules>
+  }
+}
+
+impl ModuleConfig {
+  /// Whether new uploads need their torrent page for the submitter, to show it or to filter on it.
+  pub fn needs_uploader(&self) -> bool {
+    self.module_type.shows_uploader() || self.rules.skip_anonymous == Some(true) ||
+    self.feeds.iter().flatten().any(|feed| feed.rules().is_some_and(|rules| rules.skip_anonymous == Some(true)))
+  }
+}
+
+impl FeedConfig {
+  pub fn url(&self) -> &str {
+    match self {
+      FeedConfig::Url(url) | FeedConfig::Table { url, .. } => url
+    }
+  }
+
+  pub fn mode(&self) -> Option<FeedMode> {
+    match self {
+      FeedConfig::Url(_) => None,
+      FeedConfig::Table { mode, .. } => *mode
+    }
+  }
+
+  /// The feed's own rules, which apply on top of the module's.
+  pub fn rules(&self) -> Option<&Rules> {
+    match self {
+      FeedConfig::Url(_) => None,
+      FeedConfig::Table { rules, .. } => Some(rules.as_ref())
+    }
+  }
+}
+
+/// `Default` is an inactive email module without any settings, for filling in the rest of a module built in code.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
 pub struct ModuleConfig {
   pub module_type: ModuleType,
   pub active: bool,
-  pub feeds: Option<Vec<String>>,
+  pub update_interval: Option<u64>,
+  pub feeds: Option<Vec<FeedConfig>>,
+  pub feed_mode: Option<FeedMode>,
   pub comments: Option<bool>,
   pub uploads: Option<bool>,
   pub retrieve_all_pages: Option<bool>,
+  pub priorities: Option<Priorities>,
+  #[serde(flatten)]
+  pub rules: Rules,
+  /// Sends an alert along with uploads which skip an episode or repeat one, see `series::track`.
+  pub series_alerts: Option<bool>,
   pub smtp_username: Option<String>,
   pub smtp_password: Option<String>,
   pub smtp_domain: Option<String>,
@@ -63,45 +167,187 @@ pub struct ModuleConfig {
   pub discord_channel_id: Option<u64>,
   pub discord_pinged_role: Option<u64>,
   pub discord_bot_activity_type: Option<String>,
-  pub discord_bot_activity_text: Option<String>
+  pub discord_bot_activity_text: Option<String>,
+  pub webhook_url: Option<String>,
+  pub webhook_headers: Option<BTreeMap<String, String>>,
+  pub webhook_secret: Option<String>,
+  pub webhook_template: Option<String>,
+  pub telegram_token: Option<String>,
+  pub telegram_chat_ids: Option<Vec<ChatId>>,
+  pub telegram_api_url: Option<String>,
+  pub matrix_homeserver: Option<String>,
+  pub matrix_access_token: Option<String>,
+  pub matrix_room_id: Option<String>,
+  pub matrix_redact_deleted: Option<bool>,
+  pub ntfy_server: Option<String>,
+  pub ntfy_topic: Option<String>,
+  pub ntfy_token: Option<String>,
+  pub ntfy_tags: Option<Vec<String>>,
+  pub slack_webhook_url: Option<String>,
+  pub pushover_token: Option<String>,
+  pub pushover_user: Option<String>,
+  pub pushover_device: Option<String>,
+  pub pushover_api_url: Option<String>,
+  pub atom_path: Option<String>,
+  pub atom_title: Option<String>,
+  pub atom_max_entries: Option<u32>
+}
+
+/// What a torrent has to look like to be notified about, set on a module or on one of its feeds.
+/// Titles have to match one of the `title_include` regexes (if there are any) and none of the `title_exclude` ones.
+/// `categories` takes ids like `1_2`, where `1_0` stands for every subcategory of `1`, or the names nyaa shows.
+/// Sizes are written like nyaa writes them, `200 MiB` or `4 GiB`.
+/// New uploads short of `min_seeders`, `min_leechers` or `min_completed` are held back and looked at again on each poll,
+/// until they reach them or are `swarm_wait_hours` old, at which point they're dropped.
+/// `trusted_only`, `skip_remakes` and `skip_anonymous` go by the colour of the row and the submitter.
+/// `release_groups`, `resolutions`, `codecs` and `sources` go by what `Release::parse` makes of the title.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct Rules {
+  pub title_include: Option<Vec<String>>,
+  pub title_exclude: Option<Vec<String>>,
+  pub categories: Option<Vec<String>>,
+  pub min_size: Option<String>,
+  pub max_size: Option<String>,
+  pub min_seeders: Option<u64>,
+  pub min_leechers: Option<u64>,
+  pub min_completed: Option<u64>,
+  pub swarm_wait_hours: Option<u64>,
+  pub trusted_only: Option<bool>,
+  pub skip_remakes: Option<bool>,
+  pub skip_anonymous: Option<bool>,
+  pub release_groups: Option<Vec<String>>,
+  pub resolutions: Option<Vec<String>>,
+  pub codecs: Option<Vec<String>>,
+  pub sources: Option<Vec<String>>
+}
+
+/// Priorities per event for the services which have them (Gotify: 0-10, ntfy: 1-5, Pushover: -2-2).
+/// Events left out get the service's default, or for Gotify the older `gotify_upload_priority`/`gotify_comment_priority`.
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+pub struct Priorities {
+  pub upload: Option<i32>,
+  pub new_comment: Option<i32>,
+  pub edited_comment: Option<i32>,
+  pub deleted_comment: Option<i32>,
+  pub series_alert: Option<i32>
+}
+
+/// Telegram chats are either numeric ids or `@channelname`s.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ChatId {
+  Id(i64),
+  Name(String)
+}
+
+impl std::fmt::Display for ChatId {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ChatId::Id(id) => {
+        write!(f, "{}", id)
+      },
+      ChatId::Name(name) => {
+        write!(f, "{}", name)
+      }
+    }
+  }
 }
 
 impl Config {
-  pub fn new() -> Result<Self, ()> {
-    if Path::is_dir(Path::new(&NYAA_FOLDER_PATH.to_string())) &&
-    Path::is_file(Path::new(&NYAA_CONFIG_PATH.to_string())) {
-      if let Ok(file) = &fs::read_to_string(Path::new(&NYAA_CONFIG_PATH.to_string())) {
-        match toml::from_str::<Config>(file) {
-          Ok(config) => {
-            if config.module.iter().all(|module| !module.active) {
-              println!("[INF] None of the modules have been activated.\nPlease edit {}.", *NYAA_CONFIG_PATH);
-              return Err(());
-            }
-
-            if config.module.iter().any(|module| module.module_type == ModuleType::Discord) &&
-            config.module.iter().filter(|module| module.active && (module.module_type == ModuleType::Discord)).count() > 1 {
-              eprintln!("[ERR] More than one discord module is activated. Serenity only allows one client per instance!");
-              return Err(());
-            }
-
-            if config.module.iter().any(|module| module.active && (module.module_type == ModuleType::Discord) && module.discord_token.is_none()) {
-              eprintln!("[ERR] Your discord module doesn't have a discord token set.\nPlease ");
-              return Err(());
-            }
-
-            return Ok(Config {
-              update_interval: config.update_interval,
-              module: config.module
-            });
-          }
-          Err(e) => {
-            eprintln!("Failed to read {}.\n{}", *NYAA_CONFIG_PATH, e);
+  /// Reads and checks the configuration at `paths().config`, writing a template there if there is none.
+  pub fn new() -> Result<Self> {
+    let config_path = &paths().config;
+    let shown = config_path.display();
+    if config_path.is_file() {
+      let file = fs::read_to_string(config_path)
+        .map_err(|e| Error::io(&format!("reading {}", shown), e))?;
+      let config = toml::from_str::<Config>(&file)
+        .map_err(|e| Error::Config(format!("Failed to read {}.\n{}", shown, e)))?;
+
+      if config.update_interval == 0 || config.module.iter().any(|module| module.update_interval == Some(0)) {
+        return Err(Error::Config(format!("update_interval has to be at least one minute.\nPlease edit {}.", shown)));
+      }
+
+      if config.module.iter().all(|module| !module.active) {
+        return Err(Error::Config(format!("None of the modules have been activated.\nPlease edit {}.", shown)));
+      }
+
+      if config.module.iter().any(|module| module.module_type == ModuleType::Discord) &&
+      config.module.iter().filter(|module| module.active && (module.module_type == ModuleType::Discord)).count() > 1 {
+        return Err(Error::Config("More than one discord module is activated. Serenity only allows one client per instance!".to_string()));
+      }
+
+      if config.module.iter().any(|module| module.active && (module.module_type == ModuleType::Discord) && module.discord_token.is_none()) {
+        return Err(Error::Config(format!("Your discord module doesn't have a discord token set.\nPlease edit {}.", shown)));
+      }
+
+      for module in config.module.iter().filter(|module| module.active && (module.module_type == ModuleType::Webhook)) {
+        if module.webhook_url.is_none() {
+          return Err(Error::Config(format!("Your webhook module doesn't have a webhook_url set.\nPlease edit {}.", shown)));
+        }
+        if let Some(template) = &module.webhook_template {
+          serde_json::from_str::<serde_json::Value>(template)
+            .map_err(|e| Error::Config(format!("The webhook_template isn't valid JSON: {}\nPlease edit {}.", e, shown)))?;
+        }
+      }
+
+      if config.module.iter().any(|module| module.active && (module.module_type == ModuleType::Telegram) &&
+      (module.telegram_token.is_none() || module.telegram_chat_ids.as_ref().is_none_or(|chats| chats.is_empty()))) {
+        return Err(Error::Config(format!("Your telegram module needs a telegram_token and at least one telegram_chat_ids entry.\nPlease edit {}.", shown)));
+      }
+
+      if config.module.iter().any(|module| module.active && (module.module_type == ModuleType::Matrix) &&
+      (module.matrix_homeserver.is_none() || module.matrix_access_token.is_none() || module.matrix_room_id.is_none())) {
+        return Err(Error::Config(format!("Your matrix module needs a matrix_homeserver, matrix_access_token and matrix_room_id.\nPlease edit {}.", shown)));
+      }
+
+      for module in config.module.iter().filter(|module| module.active && (module.module_type == ModuleType::Ntfy)) {
+        if module.ntfy_topic.is_none() {
+          return Err(Error::Config(format!("Your ntfy module doesn't have a ntfy_topic set.\nPlease edit {}.", shown)));
+        }
+      }
+
+      if config.module.iter().any(|module| module.active && (module.module_type == ModuleType::Slack) && module.slack_webhook_url.is_none()) {
+        return Err(Error::Config(format!("Your slack module doesn't have a slack_webhook_url set.\nPlease edit {}.", shown)));
+      }
+
+      if config.module.iter().any(|module| module.active && (module.module_type == ModuleType::Pushover) &&
+      (module.pushover_token.is_none() || module.pushover_user.is_none())) {
+        return Err(Error::Config(format!("Your pushover module needs a pushover_token and a pushover_user.\nPlease edit {}.", shown)));
+      }
+
+      let atom_paths: Vec<String> = config.module.iter().filter(|module| module.active && (module.module_type == ModuleType::Atom))
+        .map(crate::notifiers::atom_path).collect();
+      if atom_paths.iter().enumerate().any(|(index, path)| atom_paths[..index].contains(path)) {
+        return Err(Error::Config(format!("Every Atom module needs its own atom_path.\nPlease edit {}.", shown)));
+      }
+
+      for module in config.module.iter().filter(|module| module.active) {
+        let range = match module.module_type {
+          ModuleType::Gotify => 0..=10,
+          ModuleType::Ntfy => 1..=5,
+          ModuleType::Pushover => -2..=2,
+          _ => continue
+        };
+        if let Some(priorities) = &module.priorities {
+          if [priorities.upload, priorities.new_comment, priorities.edited_comment, priorities.deleted_comment, priorities.series_alert].iter().flatten().any(|priority| !range.contains(priority)) {
+            return Err(Error::Config(format!("{} priorities go from {} to {}.\nPlease edit {}.", module.module_type, range.start(), range.end(), shown)));
           }
         }
       }
-      Err(())
+
+      for module in &config.module {
+        if let Err(Error::Config(reason)) = Filter::new(module) {
+          return Err(Error::Config(format!("{}\nPlease edit {}.", reason, shown)));
+        }
+      }
+
+      Ok(config)
     } else {
       let template: &str = r#"update_interval = 5 # minutes
+nyaa_requests_per_minute = 30 # be nice to nyaa
+# api_address = "127.0.0.1:8080" # status and control api, off unless set
+# api_token = "<TOKEN>" # needed to trigger, pause and resume modules through the api
 
 [[module]]
 active = false
@@ -120,14 +366,23 @@ smtp_recipients = ["example@mail.com", "example1@mail.com"]
 [[module]]
 active = false
 module_type = "Gotify"
-feeds = ["https://nyaa.si/"]
+update_interval = 15 # overrides the global interval for this module
+feed_mode = "Auto" # "Rss", "Html" or "Auto"
+feeds = ["https://nyaa.si/", { url = "https://nyaa.si/?q=horriblesubs", mode = "Html", title_exclude = ["(?i)batch"] }]
 comments = false
 uploads = false
 retrieve_all_pages = true
+title_include = ["1080p"] # regexes on the torrent title, title_exclude works the other way round
+categories = ["1_2"] # ids like "1_2" ("1_0" for all anime) or names like "Anime - Raw"
+min_size = "200 MiB"
+max_size = "4 GiB"
+min_seeders = 20 # new uploads are held back until they have this many seeders, for up to swarm_wait_hours (6)
+skip_remakes = true # trusted_only and skip_anonymous work the same way
+resolutions = ["1080p"] # like release_groups, codecs ("H.264", "HEVC", "AV1") and sources ("WEB", "BD", "DVD", "TV")
+series_alerts = true # sends an alert along with uploads which skip an episode or repeat one
 gotify_domain = "<GOTIFY-SERVER>"
 gotify_token = "<GOTIFY-TOKEN>"
-gotify_comment_priority = 1
-gotify_upload_priority = 10
+priorities = { upload = 10, new_comment = 5, edited_comment = 3, deleted_comment = 1, series_alert = 8 }
 
 [[module]]
 active = false
@@ -136,34 +391,97 @@ discord_token = "<DISCORD-BOT-TOKEN>"
 discord_bot_id = "just something variable to name the database"
 discord_bot_activity_type = "listening"
 discord_bot_activity_text = "spinning hard-drives."
+
+[[module]]
+active = false
+module_type = "Webhook"
+feeds = ["https://nyaa.si/user/subsplease"]
+comments = true
+uploads = true
+retrieve_all_pages = false
+webhook_url = "https://example.com/hooks/nyaa"
+webhook_headers = { Authorization = "Bearer <TOKEN>" }
+webhook_secret = "<SECRET>" # signs the body, see X-Nyaa-Signature-256
+# Placeholders like {torrent.title} or {comment.message} are filled in, leave it out to get every field.
+webhook_template = '{"text": "{event}: {torrent.title}", "link": "{torrent.url}"}'
+
+[[module]]
+active = false
+module_type = "Telegram"
+feeds = ["https://nyaa.si/user/subsplease"]
+comments = true
+uploads = true
+retrieve_all_pages = false
+telegram_token = "<BOT-TOKEN>"
+telegram_chat_ids = [123456789, "@my_channel"]
+
+[[module]]
+active = false
+module_type = "Matrix"
+feeds = ["https://nyaa.si/user/subsplease"]
+comments = true
+uploads = true
+retrieve_all_pages = false
+matrix_homeserver = "https://matrix.example.com"
+matrix_access_token = "<ACCESS-TOKEN>"
+matrix_room_id = "!roomid:example.com"
+matrix_redact_deleted = false # removes the notification of a deleted comment instead of striking it through
+
+[[module]]
+active = false
+module_type = "Ntfy"
+feeds = ["https://nyaa.si/user/subsplease"]
+comments = true
+uploads = true
+retrieve_all_pages = false
+ntfy_server = "https://ntfy.sh" # or your own
+ntfy_topic = "<TOPIC>"
+ntfy_token = "<ACCESS-TOKEN>" # only needed for protected topics
+ntfy_tags = ["anime"]
+priorities = { upload = 4, new_comment = 3, edited_comment = 2, deleted_comment = 2 }
+
+[[module]]
+active = false
+module_type = "Slack" # also works with Mattermost, Rocket.Chat, ...
+feeds = ["https://nyaa.si/user/subsplease"]
+comments = true
+uploads = true
+retrieve_all_pages = false
+slack_webhook_url = "https://hooks.slack.com/services/<...>"
+
+[[module]]
+active = false
+module_type = "Pushover"
+feeds = ["https://nyaa.si/user/subsplease"]
+comments = true
+uploads = true
+retrieve_all_pages = false
+pushover_token = "<APPLICATION-TOKEN>"
+pushover_user = "<USER-KEY>"
+pushover_device = "phone" # leave out to send to all devices
+priorities = { upload = 0, new_comment = -1, edited_comment = -1, deleted_comment = -2 }
+
+[[module]]
+active = false
+module_type = "Atom"
+feeds = ["https://nyaa.si/user/subsplease", "https://nyaa.si/user/Erai-raws"]
+comments = true
+uploads = true
+retrieve_all_pages = false
+# atom_path = "/var/www/feed.atom" # feed.atom in the data directory unless set, every Atom module needs its own file
+atom_title = "Nyaa notifications"
+atom_max_entries = 100
 "#;
 
-      if Path::is_dir(Path::new(&NYAA_FOLDER_PATH.to_string())) ||
-      std::fs::create_dir(NYAA_FOLDER_PATH.to_string()).is_ok() {
-        if let Ok(mut config_file) = File::create(NYAA_CONFIG_PATH.to_string()) {
-          if config_file.write_all(template.as_bytes()).is_err() {
-            eprintln!("Failed to write to {}", *NYAA_CONFIG_PATH);
-          } else {
-            eprintln!("Please edit {}.", *NYAA_CONFIG_PATH)
-          }
-        } else {
-          eprintln!("Failed to create ./{}/config.toml", *NYAA_FOLDER_PATH);
-        }
-      } else {
-        eprintln!("Failed to create ./{}", *NYAA_FOLDER_PATH);
+      if let Some(directory) = config_path.parent().filter(|directory| !directory.as_os_str().is_empty()) {
+        fs::create_dir_all(directory).map_err(|e| Error::io(&format!("creating {}", directory.display()), e))?;
       }
-      
-      Err(())
-    }
-  }
+      let mut config_file = File::create(config_path)
+        .map_err(|e| Error::io(&format!("creating {}", shown), e))?;
+      config_file.write_all(template.as_bytes())
+        .map_err(|e| Error::io(&format!("writing {}", shown), e))?;
 
-  pub async fn refresh_discord_modules(&mut self, database: &mut Database, discord_bot_id: String) {
-    for (index, module) in self.module.clone().iter().enumerate().rev() {
-      if module.active && module.discord_token.is_none() && (module.module_type == ModuleType::Discord) {
-        self.module.remove(index);
-      }
+      Err(Error::Config(format!("A new configuration template has been created.\nPlease edit {}.", shown)))
     }
-
-    self.module.append(&mut database.get_discord_channels(&discord_bot_id).await);
   }
 }
@@ -1,81 +1,288 @@
+use std::collections::HashMap;
 use sqlx::{
-  sqlite, Pool, Sqlite, Row
+  sqlite::{self, SqliteRow}, Pool, Sqlite, Row, SqliteConnection, TypeInfo, ValueRef
+};
+use tracing::{
+  info, warn
 };
 
-use crate::NYAA_DATABASE_PATH;
+use crate::paths;
+use crate::atom::HistoryEvent;
+use crate::release::Release;
 use crate::discord::unix_to_datetime;
 use crate::web::{
-  NyaaTorrent, NyaaComment, NyaaUpdate, NyaaCommentUpdateType
+  NyaaTorrent, NyaaComment, NyaaUser, NyaaUpdate, NyaaCommentUpdateType
 };
 use crate::config::{
-  ModuleConfig, ModuleType
+  FeedConfig, ModuleConfig, ModuleType, Rules
+};
+use crate::error::{
+  DatabaseContext, Error, Result
 };
 
 pub struct Database {
   pub database: Pool<Sqlite>
 }
 
+// Every entry is one schema version; the database remembers the last applied one in `PRAGMA user_version`.
+// Never edit an entry that has already been released, append a new one instead.
+const SCHEMA_VERSION: i64 = 8;
+
+/// Everything the database keeps, as `export` writes it out.
+const TABLES: [&str; 6] = ["subscriptions", "torrents", "deliveries", "comments", "sent_messages", "events"];
+
+const SCHEMA_V1: &[&str] = &[
+  r#"CREATE TABLE subscriptions (
+    key TEXT PRIMARY KEY,
+    module_type TEXT NOT NULL,
+    discord_bot_id TEXT,
+    discord_channel_id INTEGER,
+    feeds TEXT NOT NULL DEFAULT '',
+    active INTEGER NOT NULL DEFAULT 1,
+    comments INTEGER NOT NULL DEFAULT 0,
+    uploads INTEGER NOT NULL DEFAULT 0,
+    retrieve_all_pages INTEGER NOT NULL DEFAULT 0,
+    pinged_role INTEGER NOT NULL DEFAULT 0,
+    indexed INTEGER NOT NULL DEFAULT 0
+  )"#,
+  r#"CREATE TABLE torrents (
+    domain TEXT NOT NULL,
+    id INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    category TEXT NOT NULL,
+    size TEXT NOT NULL,
+    magnet_link TEXT NOT NULL,
+    upload_date_str TEXT NOT NULL,
+    upload_date_timestamp REAL NOT NULL,
+    seeders INTEGER NOT NULL,
+    leechers INTEGER NOT NULL,
+    completed INTEGER NOT NULL,
+    PRIMARY KEY (domain, id)
+  )"#,
+  r#"CREATE TABLE deliveries (
+    subscription TEXT NOT NULL,
+    domain TEXT NOT NULL,
+    torrent_id INTEGER NOT NULL,
+    comments_amount INTEGER NOT NULL,
+    PRIMARY KEY (subscription, domain, torrent_id)
+  )"#,
+  r#"CREATE TABLE comments (
+    subscription TEXT NOT NULL,
+    domain TEXT NOT NULL,
+    torrent_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    user_anonymous INTEGER NOT NULL,
+    user_role TEXT NOT NULL,
+    username TEXT NOT NULL,
+    user_avatar TEXT,
+    user_banned INTEGER NOT NULL,
+    message TEXT NOT NULL,
+    old_message TEXT,
+    uploader INTEGER NOT NULL,
+    date_timestamp REAL NOT NULL,
+    edited_timestamp REAL,
+    old_edited_timestamp REAL,
+    direct_link TEXT NOT NULL,
+    update_type TEXT NOT NULL,
+    PRIMARY KEY (subscription, domain, torrent_id, position)
+  )"#
+];
+
+const SCHEMA_V2: &[&str] = &[
+  r#"CREATE TABLE sent_messages (
+    subscription TEXT NOT NULL,
+    domain TEXT NOT NULL,
+    torrent_id INTEGER NOT NULL,
+    comment TEXT NOT NULL,
+    message_id TEXT NOT NULL,
+    PRIMARY KEY (subscription, domain, torrent_id, comment)
+  )"#
+];
+
+const SCHEMA_V3: &[&str] = &[
+  r#"CREATE TABLE events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    subscription TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    domain TEXT NOT NULL,
+    torrent_id INTEGER NOT NULL,
+    torrent_title TEXT NOT NULL,
+    category TEXT NOT NULL,
+    size TEXT NOT NULL,
+    username TEXT,
+    message TEXT,
+    old_message TEXT,
+    link TEXT NOT NULL,
+    timestamp REAL NOT NULL
+  )"#,
+  "CREATE INDEX events_by_subscription ON events (subscription, id)"
+];
+
+const SCHEMA_V4: &[&str] = &[
+  "ALTER TABLE subscriptions ADD COLUMN title_include TEXT",
+  "ALTER TABLE subscriptions ADD COLUMN title_exclude TEXT"
+];
+
+const SCHEMA_V5: &[&str] = &[
+  "ALTER TABLE torrents ADD COLUMN trusted INTEGER NOT NULL DEFAULT 0",
+  "ALTER TABLE torrents ADD COLUMN remake INTEGER NOT NULL DEFAULT 0"
+];
+
+/// The release name's parts, all but the version.
+const SCHEMA_V6: &[&str] = &[
+  "ALTER TABLE torrents ADD COLUMN release_group TEXT",
+  "ALTER TABLE torrents ADD COLUMN series TEXT",
+  "ALTER TABLE torrents ADD COLUMN season INTEGER",
+  "ALTER TABLE torrents ADD COLUMN episode INTEGER",
+  "ALTER TABLE torrents ADD COLUMN last_episode INTEGER",
+  "ALTER TABLE torrents ADD COLUMN resolution TEXT",
+  "ALTER TABLE torrents ADD COLUMN codec TEXT",
+  "ALTER TABLE torrents ADD COLUMN source TEXT",
+  "ALTER TABLE torrents ADD COLUMN crc TEXT"
+];
+
+/// The release version, the `2` of `07v2`.
+/// `migrate_release_names` then parses the titles already stored to fill in this column and the ones of version 6.
+const SCHEMA_V7: &[&str] = &[
+  "ALTER TABLE torrents ADD COLUMN version INTEGER"
+];
+
+/// Sent messages belong to the module which sent them, the chat or room they went to is their `target`.
+/// The rows before were kept per chat or room, they can't be told apart by module and are dropped.
+const SCHEMA_V8: &[&str] = &[
+  "DROP TABLE sent_messages",
+  r#"CREATE TABLE sent_messages (
+    subscription TEXT NOT NULL,
+    target TEXT NOT NULL,
+    domain TEXT NOT NULL,
+    torrent_id INTEGER NOT NULL,
+    comment TEXT NOT NULL,
+    message_id TEXT NOT NULL,
+    PRIMARY KEY (subscription, target, domain, torrent_id, comment)
+  )"#
+];
+
 impl Database {
-  pub async fn new() -> Result<Self, ()> {
-    if let Ok(database) = sqlite::SqlitePoolOptions::new()
-      .max_connections(10)
-      .connect_with(
-        sqlite::SqliteConnectOptions::new()
-          .filename(NYAA_DATABASE_PATH.to_string())
-          .create_if_missing(true)
-        )
-    .await {
-      return Ok(Database {database});
+  pub async fn new() -> Result<Self> {
+    let path = paths().database();
+    if let Some(directory) = path.parent() {
+      // the configuration may live somewhere else, so the data directory might not exist yet
+      std::fs::create_dir_all(directory).map_err(|e| Error::io(&format!("creating {}", directory.display()), e))?;
     }
-    eprintln!("Failed to connect or create {}.", *NYAA_DATABASE_PATH);
-    Err(())
+    Database::open(&path.to_string_lossy()).await
+  }
+
+  /// Opens (or creates) the database at `path` and brings it up to the current schema.
+  pub async fn open(path: &str) -> Result<Self> {
+    let options = sqlite::SqliteConnectOptions::new()
+      .filename(path)
+      .create_if_missing(true);
+    Database::connect(options, path).await
   }
 
-  pub async fn check_database_connection(&mut self) -> Result<(), ()> {
+  async fn connect(options: sqlite::SqliteConnectOptions, path: &str) -> Result<Self> {
+    let database = sqlite::SqlitePoolOptions::new()
+      .max_connections(10)
+      .connect_with(options)
+    .await.context(&format!("opening {}", path))?;
+    let database = Database {database};
+    database.migrate().await.context(&format!("migrating to schema version {}", SCHEMA_VERSION))?;
+    Ok(database)
+  }
+
+  pub async fn check_database_connection(&mut self) -> Result<()> {
     if self.database.is_closed() {
-      if let Ok(database) = Database::new().await {
-        self.database = database.database;
-        return Ok(());
-      }
-      Err(())
-    } else {
-      Ok(())
+      let options = (*self.database.connect_options()).clone();
+      let path = options.clone().get_filename().to_string_lossy().to_string();
+      self.database = Database::connect(options, &path).await?.database;
     }
+    Ok(())
   }
 
-  pub async fn use_pool(database_pool: Pool<Sqlite>) -> Result<Self, ()> {
+  pub async fn use_pool(database_pool: Pool<Sqlite>) -> Result<Self> {
     Ok(Database { database: database_pool })
   }
 
-  pub async fn data_table_exists(&mut self, database_type: String, database_id: &String) -> bool {
-    let table_name = format!("_{}_{}", database_type, database_id);
-    if ! sqlx::query(format!("SELECT * FROM sqlite_master WHERE type = 'table' AND tbl_name = '{}'", table_name).as_str())
-    .fetch_all(&self.database).await.unwrap().is_empty() {
-      true
+  async fn migrate(&self) -> std::result::Result<(), sqlx::Error> {
+    let version: i64 = sqlx::query_scalar("PRAGMA user_version").fetch_one(&self.database).await?;
+    for target in (version + 1)..=SCHEMA_VERSION {
+      info!("Migrating database to schema version {}.", target);
+      let mut transaction = self.database.begin().await?;
+      match target {
+        1 => {
+          for statement in SCHEMA_V1 {
+            sqlx::query(statement).execute(&mut *transaction).await?;
+          }
+          migrate_legacy_tables(&mut transaction).await?;
+        },
+        2 => {
+          for statement in SCHEMA_V2 {
+            sqlx::query(statement).execute(&mut *transaction).await?;
+          }
+        },
+        3 => {
+          for statement in SCHEMA_V3 {
+            sqlx::query(statement).execute(&mut *transaction).await?;
+          }
+        },
+        4 => {
+          for statement in SCHEMA_V4 {
+            sqlx::query(statement).execute(&mut *transaction).await?;
+          }
+        },
+        5 => {
+          for statement in SCHEMA_V5 {
+            sqlx::query(statement).execute(&mut *transaction).await?;
+          }
+        },
+        6 => {
+          for statement in SCHEMA_V6 {
+            sqlx::query(statement).execute(&mut *transaction).await?;
+          }
+        },
+        7 => {
+          for statement in SCHEMA_V7 {
+            sqlx::query(statement).execute(&mut *transaction).await?;
+          }
+          migrate_release_names(&mut transaction).await?;
+        },
+        8 => {
+          for statement in SCHEMA_V8 {
+            sqlx::query(statement).execute(&mut *transaction).await?;
+          }
+        },
+        _ => unreachable!()
+      }
+      // PRAGMA statements can't take bound parameters, target is our own integer.
+      sqlx::query(&format!("PRAGMA user_version = {}", target)).execute(&mut *transaction).await?;
+      transaction.commit().await?;
+    }
+    Ok(())
+  }
+
+  /// Returns whether the subscription has already been indexed.
+  /// The first call for a subscription registers it, the caller is then expected to index the current feeds.
+  pub async fn subscription_indexed(&mut self, database_type: String, database_id: &String) -> Result<bool> {
+    let key = subscription_key(&database_type, database_id);
+    let indexed: Option<bool> = sqlx::query_scalar("SELECT indexed FROM subscriptions WHERE key = ?")
+      .bind(&key)
+    .fetch_optional(&self.database).await.context("reading subscriptions")?;
+    if indexed == Some(true) {
+      Ok(true)
     } else {
-      println!("[INF] Creating new table {:?}", table_name);
-      sqlx::query(format!(r#"CREATE TABLE {:?} (
-        ID INTEGER,
-        Domain TEXT NOT NULL,
-        Title TEXT NOT NULL,
-        Category TEXT NOT NULL,
-        Size TEXT NOT NULL,
-        Magnet_Link TEXT NOT NULL,
-        Upload_Date_Str TEXT NOT NULL,
-        Upload_Date_Timestamp INTEGER,
-        Seeders INTEGER,
-        Leechers INTEGER,
-        Completed INTEGER,
-        Comments_Amount INTEGER,
-        Comments TEXT
-      )"#, table_name).as_str()).execute(&self.database).await.unwrap();
-      false
+      info!("Indexing new subscription {:?}", key);
+      sqlx::query(r#"INSERT INTO subscriptions (key, module_type, indexed) VALUES (?, ?, 1)
+        ON CONFLICT (key) DO UPDATE SET indexed = 1"#)
+        .bind(&key)
+        .bind(database_type)
+      .execute(&self.database).await.context("registering subscription")?;
+      Ok(false)
     }
   }
 
-  pub async fn update_db_table(&mut self, database_type: String, database_id: &String, mut update: NyaaUpdate) {
-    let table_name = format!("_{}_{}", database_type, database_id);
-    
+  pub async fn update_db_table(&mut self, database_type: String, database_id: &String, mut update: NyaaUpdate) -> Result<()> {
+    let key = subscription_key(&database_type, database_id);
+
     for comment in update.torrent.comments.iter_mut() {
       if unix_to_datetime(comment.date_timestamp)+chrono::Duration::hours(1) <= chrono::Utc::now()-chrono::Duration::minutes(1) {
         comment.update_type = NyaaCommentUpdateType::UNDECIDED;
@@ -83,194 +290,657 @@ impl Database {
       // Make it so that comments can't be re-checked when they've already aged more than one hour.
     }
 
-    if update.new_upload {
-      sqlx::query(format!(r#"INSERT INTO {:?} (ID, Domain, Title, Category, Size, Magnet_Link, Upload_Date_Str, Upload_Date_Timestamp, Seeders, Leechers, Completed, Comments_Amount, Comments)
-        VALUES ({:?}, {:?}, (?), {:?}, {:?}, {:?}, {:?}, {:?}, {:?}, {:?}, {:?}, {:?}, (?))"#,
-        table_name,
-        update.torrent.id,
-        update.torrent.domain,
-        update.torrent.category,
-        update.torrent.size,
-        update.torrent.magnet_link,
-        update.torrent.upload_date_str,
-        update.torrent.upload_date_timestamp,
-        update.torrent.seeders,
-        update.torrent.leechers,
-        update.torrent.completed,
-        update.torrent.comments_amount).as_str()).bind(update.torrent.title).bind(serde_json::to_string(&update.torrent.comments).unwrap())
-      .execute(&self.database).await.unwrap();
-    } else {
-      sqlx::query(format!(r#"UPDATE {:?} SET
-      Domain = {:?}, Title = (?), Category = {:?}, Size = {:?}, Magnet_Link = {:?}, Upload_Date_Str = {:?},
-      Upload_Date_Timestamp = {:?}, Seeders = {:?}, Leechers = {:?}, Completed = {:?}, Comments_Amount = {:?}, Comments = (?) WHERE ID = {:?}"#,
-        table_name,
-        update.torrent.domain,
-        update.torrent.category,
-        update.torrent.size,
-        update.torrent.magnet_link,
-        update.torrent.upload_date_str,
-        update.torrent.upload_date_timestamp,
-        update.torrent.seeders,
-        update.torrent.leechers,
-        update.torrent.completed,
-        update.torrent.comments_amount,
-        update.torrent.id).as_str()).bind(update.torrent.title).bind(serde_json::to_string(&update.torrent.comments).unwrap())
-      .execute(&self.database).await.unwrap();
-    }
+    let torrent = update.torrent;
+    let mut transaction = self.database.begin().await.context("starting transaction")?;
+    sqlx::query(r#"INSERT INTO torrents (domain, id, title, category, size, magnet_link, upload_date_str, upload_date_timestamp, seeders, leechers, completed, trusted, remake)
+      VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+      ON CONFLICT (domain, id) DO UPDATE SET
+      title = excluded.title, category = excluded.category, size = excluded.size, magnet_link = excluded.magnet_link,
+      upload_date_str = excluded.upload_date_str, upload_date_timestamp = excluded.upload_date_timestamp,
+      seeders = excluded.seeders, leechers = excluded.leechers, completed = excluded.completed,
+      trusted = excluded.trusted, remake = excluded.remake"#)
+      .bind(&torrent.domain)
+      .bind(torrent.id as i64)
+      .bind(&torrent.title)
+      .bind(&torrent.category)
+      .bind(&torrent.size)
+      .bind(&torrent.magnet_link)
+      .bind(&torrent.upload_date_str)
+      .bind(torrent.upload_date_timestamp)
+      .bind(torrent.seeders as i64)
+      .bind(torrent.leechers as i64)
+      .bind(torrent.completed as i64)
+      .bind(torrent.trusted)
+      .bind(torrent.remake)
+    .execute(&mut *transaction).await.context("storing torrent")?;
+    store_release(&mut transaction, &torrent.domain, torrent.id, &torrent.release).await.context("storing release name")?;
+
+    sqlx::query(r#"INSERT INTO deliveries (subscription, domain, torrent_id, comments_amount) VALUES (?, ?, ?, ?)
+      ON CONFLICT (subscription, domain, torrent_id) DO UPDATE SET comments_amount = excluded.comments_amount"#)
+      .bind(&key)
+      .bind(&torrent.domain)
+      .bind(torrent.id as i64)
+      .bind(torrent.comments_amount as i64)
+    .execute(&mut *transaction).await.context("storing delivery")?;
+
+    replace_comments(&mut transaction, &key, &torrent.domain, torrent.id, &torrent.comments).await.context("storing comments")?;
+    transaction.commit().await.context("committing torrent update")
   }
 
-  pub async fn discord_channel_exists(&mut self, discord_bot_id: &String, discord_channel_id: u64) -> bool {
-    if self.discord_table_exists(discord_bot_id).await {
-      for module in self.get_discord_channels(discord_bot_id).await {
-        if module.discord_channel_id.unwrap() == discord_channel_id {
-          return true;
-        }
-      }
+  pub async fn discord_channel_exists(&mut self, discord_bot_id: &String, discord_channel_id: u64) -> Result<bool> {
+    Ok(sqlx::query("SELECT 1 FROM subscriptions WHERE discord_bot_id = ? AND discord_channel_id = ?")
+      .bind(discord_bot_id)
+      .bind(discord_channel_id as i64)
+    .fetch_optional(&self.database).await.context("reading discord channels")?.is_some())
+  }
+
+  pub async fn add_discord_channel(&mut self, discord_bot_id: &String, discord_channel_id: u64, urls: Vec<String>, collapsed_choice: (bool, bool, bool), pinged_role: String, title_rules: (Option<String>, Option<String>)) -> Result<()> {
+    let key = discord_subscription_key(discord_bot_id, discord_channel_id);
+    let (comments, uploads, retrieve_all_pages) = collapsed_choice;
+    let (title_include, title_exclude) = title_rules;
+
+    sqlx::query(r#"INSERT INTO subscriptions (key, module_type, discord_bot_id, discord_channel_id, feeds, active, comments, uploads, retrieve_all_pages, pinged_role, title_include, title_exclude)
+      VALUES (?, ?, ?, ?, ?, 1, ?, ?, ?, ?, ?, ?)"#)
+      .bind(key)
+      .bind(ModuleType::Discord.to_string())
+      .bind(discord_bot_id)
+      .bind(discord_channel_id as i64)
+      .bind(urls.join(","))
+      .bind(comments)
+      .bind(uploads)
+      .bind(retrieve_all_pages)
+      .bind(pinged_role.parse::<u64>().unwrap_or(0) as i64)
+      .bind(title_include)
+      .bind(title_exclude)
+    .execute(&self.database).await.context("adding discord channel")?;
+    Ok(())
+  }
+
+  pub async fn remove_discord_channel(&mut self, discord_bot_id: &String, discord_channel_id: u64) -> Result<()> {
+    let key = discord_subscription_key(discord_bot_id, discord_channel_id);
+
+    let mut transaction = self.database.begin().await.context("starting transaction")?;
+    for statement in [
+      "DELETE FROM subscriptions WHERE key = ?",
+      "DELETE FROM deliveries WHERE subscription = ?",
+      "DELETE FROM comments WHERE subscription = ?",
+      "DELETE FROM events WHERE subscription = ?"
+    ] {
+      sqlx::query(statement).bind(&key).execute(&mut *transaction).await.context("removing discord channel")?;
     }
-    false
+    transaction.commit().await.context("committing discord channel removal")
   }
 
-  pub async fn discord_table_exists(&mut self, discord_bot_id: &String) -> bool {
-    let table_name = format!("_{}_{}", ModuleType::Discord, discord_bot_id);
-    if ! sqlx::query(format!("SELECT * FROM sqlite_master WHERE type = 'table' AND tbl_name = '{}'", table_name).as_str())
-    .fetch_all(&self.database).await.unwrap().is_empty() {
-      true
-    } else {
-      println!("[INF] Creating new table {:?}", table_name);
-      sqlx::query(format!(r#"CREATE TABLE {:?} (
-        Channel TEXT NOT NULL,
-        Feed TEXT NOT NULL,
-        Active INTEGER,
-        Comments INTEGER,
-        Uploads INTEGER,
-        Retrieve_All_Pages INTEGER,
-        Pinged_Role TEXT NOT NULL
-      )"#, table_name).as_str()).execute(&self.database).await.unwrap();
-      false
+  pub async fn pause_discord_channel(&mut self, discord_bot_id: &String, discord_channel_id: u64, mode: bool) -> Result<()> {
+    sqlx::query("UPDATE subscriptions SET active = ? WHERE discord_bot_id = ? AND discord_channel_id = ?")
+      .bind(mode)
+      .bind(discord_bot_id)
+      .bind(discord_channel_id as i64)
+    .execute(&self.database).await.context("pausing discord channel")?;
+    Ok(())
+  }
+
+  pub async fn get_discord_channels(&mut self, discord_bot_id: &String) -> Result<Vec<ModuleConfig>> {
+    let mut channels: Vec<ModuleConfig> = vec![];
+    let db = sqlx::query(r#"SELECT discord_channel_id, feeds, active, comments, uploads, retrieve_all_pages, pinged_role, title_include, title_exclude
+      FROM subscriptions WHERE discord_bot_id = ? AND discord_channel_id IS NOT NULL"#)
+      .bind(discord_bot_id)
+    .fetch_all(&self.database).await.context("reading discord channels")?;
+
+    for row in db {
+      let read = |e| Error::database("decoding discord channel", e);
+      let channel: u64 = row.try_get::<i64, _>("discord_channel_id").map_err(read)? as u64;
+      let feeds_string_list: String = row.try_get("feeds").map_err(read)?;
+      let active: bool = row.try_get("active").map_err(read)?;
+      let comments: bool = row.try_get("comments").map_err(read)?;
+      let uploads: bool = row.try_get("uploads").map_err(read)?;
+      let retrieve_all_pages: bool = row.try_get("retrieve_all_pages").map_err(read)?;
+      let pinged_role: u64 = row.try_get::<i64, _>("pinged_role").map_err(read)? as u64;
+      let title_include: Option<String> = row.try_get("title_include").map_err(read)?;
+      let title_exclude: Option<String> = row.try_get("title_exclude").map_err(read)?;
+      let feeds: Vec<FeedConfig> = feeds_string_list.split(',').map(|str| FeedConfig::Url(str.to_string())).collect();
+
+      channels.append(&mut vec![ModuleConfig {
+        module_type: ModuleType::Discord,
+        active,
+        feeds: Some(feeds),
+        comments: Some(comments),
+        uploads: Some(uploads),
+        retrieve_all_pages: Some(retrieve_all_pages),
+        rules: Rules {
+          title_include: title_include.map(|regex| vec![regex]),
+          title_exclude: title_exclude.map(|regex| vec![regex]),
+          ..Default::default()
+        },
+        discord_channel_id: Some(channel),
+        discord_bot_id: Some(discord_bot_id.to_string()),
+        discord_pinged_role: Some(pinged_role),
+        ..Default::default()
+      }]);
     }
+    Ok(channels)
   }
 
-  pub async fn add_discord_channel(&mut self, discord_bot_id: &String, discord_channel_id: u64, urls: Vec<String>, collapsed_choice: (bool, bool, bool), pinged_role: String) {
-    let index_table_name = format!("_{}_{}", ModuleType::Discord, discord_bot_id);
-    let url_string = {
-      let mut str: String = String::new();
-      for url in urls {
-        str.push_str(&(url+","));
-      }
-      str = str.trim_end_matches(',').to_string();
-      str
-    };
-    let comments_u32 = collapsed_choice.0 as u32;
-    let uploads_u32 = collapsed_choice.1 as u32;
-    let retrieve_all_pages_u32 = collapsed_choice.2 as u32;
-
-    sqlx::query(format!(r#"INSERT INTO {:?} (Channel, Feed, Active, Comments, Uploads, Retrieve_All_Pages, Pinged_Role)
-    VALUES({}, {:?}, {}, {}, {}, {}, {})"#, index_table_name, discord_channel_id, url_string, 1, comments_u32, uploads_u32, retrieve_all_pages_u32, pinged_role).as_str()).execute(&self.database).await.unwrap();
+  /// Remembers the id a service gave the notification about a comment, so that it can be edited or deleted later.
+  /// `target` is where the message went, like a chat or a room, as a module can send to several.
+  #[allow(clippy::too_many_arguments)]
+  pub async fn remember_sent_message(&mut self, database_type: String, database_id: &String, target: &String, domain: &String, torrent_id: u64, comment: &String, message_id: &String) -> Result<()> {
+    sqlx::query("INSERT OR REPLACE INTO sent_messages (subscription, target, domain, torrent_id, comment, message_id) VALUES (?, ?, ?, ?, ?, ?)")
+      .bind(subscription_key(&database_type, database_id))
+      .bind(target)
+      .bind(domain)
+      .bind(torrent_id as i64)
+      .bind(comment)
+      .bind(message_id)
+    .execute(&self.database).await.context("storing sent message")?;
+    Ok(())
   }
 
-  pub async fn remove_discord_channel(&mut self, discord_bot_id: &String, discord_channel_id: u64) {
-    let index_table_name = format!("_{}_{}", ModuleType::Discord, discord_bot_id);
-    let channel_table_name = format!("_{}_{}_{}", ModuleType::Discord, discord_bot_id, discord_channel_id);
+  pub async fn get_sent_message(&mut self, database_type: String, database_id: &String, target: &String, domain: &String, torrent_id: u64, comment: &String) -> Result<Option<String>> {
+    sqlx::query_scalar("SELECT message_id FROM sent_messages WHERE subscription = ? AND target = ? AND domain = ? AND torrent_id = ? AND comment = ?")
+      .bind(subscription_key(&database_type, database_id))
+      .bind(target)
+      .bind(domain)
+      .bind(torrent_id as i64)
+      .bind(comment)
+    .fetch_optional(&self.database).await.context("reading sent message")
+  }
 
-    sqlx::query(format!(r#"DELETE FROM {:?} WHERE Channel = {:?}"#, index_table_name, discord_channel_id).as_str()).execute(&self.database).await.unwrap();
-    sqlx::query(format!(r#"DROP TABLE {:?}"#, channel_table_name).as_str()).execute(&self.database).await.unwrap();
+  pub async fn forget_sent_message(&mut self, database_type: String, database_id: &String, target: &String, domain: &String, torrent_id: u64, comment: &String) -> Result<()> {
+    sqlx::query("DELETE FROM sent_messages WHERE subscription = ? AND target = ? AND domain = ? AND torrent_id = ? AND comment = ?")
+      .bind(subscription_key(&database_type, database_id))
+      .bind(target)
+      .bind(domain)
+      .bind(torrent_id as i64)
+      .bind(comment)
+    .execute(&self.database).await.context("removing sent message")?;
+    Ok(())
   }
 
-  pub async fn pause_discord_channel(&mut self, discord_bot_id: &String, discord_channel_id: u64, mode: bool) {
-    let index_table_name = format!("_{}_{}", ModuleType::Discord, discord_bot_id);
-    let switch = mode as u32;
+  /// Adds the event to the subscription's history and forgets all but the newest `keep` ones.
+  pub async fn add_history_event(&mut self, database_type: String, database_id: &String, event: &HistoryEvent, keep: u32) -> Result<()> {
+    let key = subscription_key(&database_type, database_id);
+    let mut transaction = self.database.begin().await.context("starting history update")?;
+    sqlx::query(r#"INSERT INTO events (subscription, kind, domain, torrent_id, torrent_title, category, size, username, message, old_message, link, timestamp)
+      VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
+      .bind(&key)
+      .bind(&event.kind)
+      .bind(&event.domain)
+      .bind(event.torrent_id as i64)
+      .bind(&event.torrent_title)
+      .bind(&event.category)
+      .bind(&event.size)
+      .bind(&event.username)
+      .bind(&event.message)
+      .bind(&event.old_message)
+      .bind(&event.link)
+      .bind(event.timestamp)
+    .execute(&mut *transaction).await.context("storing history event")?;
+    sqlx::query("DELETE FROM events WHERE subscription = ? AND id NOT IN (SELECT id FROM events WHERE subscription = ? ORDER BY id DESC LIMIT ?)")
+      .bind(&key)
+      .bind(&key)
+      .bind(keep as i64)
+    .execute(&mut *transaction).await.context("pruning history")?;
+    transaction.commit().await.context("committing history update")
+  }
 
-    sqlx::query(format!(r#"UPDATE {:?} SET Active = {} WHERE Channel = {:?}"#,
-      index_table_name, switch, discord_channel_id).as_str()).execute(&self.database
-    ).await.unwrap();
+  /// The subscription's history, newest first.
+  pub async fn get_history_events(&mut self, database_type: String, database_id: &String) -> Result<Vec<HistoryEvent>> {
+    let rows = sqlx::query(r#"SELECT id, kind, domain, torrent_id, torrent_title, category, size, username, message, old_message, link, timestamp
+      FROM events WHERE subscription = ? ORDER BY id DESC"#)
+      .bind(subscription_key(&database_type, database_id))
+    .fetch_all(&self.database).await.context("reading history")?;
+    rows.into_iter().map(|row| {
+      let read = |e| Error::database("decoding history event", e);
+      Ok(HistoryEvent {
+        id: row.try_get("id").map_err(read)?,
+        kind: row.try_get("kind").map_err(read)?,
+        domain: row.try_get("domain").map_err(read)?,
+        torrent_id: row.try_get::<i64, _>("torrent_id").map_err(read)? as u64,
+        torrent_title: row.try_get("torrent_title").map_err(read)?,
+        category: row.try_get("category").map_err(read)?,
+        size: row.try_get("size").map_err(read)?,
+        username: row.try_get("username").map_err(read)?,
+        message: row.try_get("message").map_err(read)?,
+        old_message: row.try_get("old_message").map_err(read)?,
+        link: row.try_get("link").map_err(read)?,
+        timestamp: row.try_get("timestamp").map_err(read)?
+      })
+    }).collect()
   }
 
-  pub async fn get_discord_channels(&mut self, discord_bot_id: &String) -> Vec<ModuleConfig> {
-    let table_name = format!("_{}_{}", ModuleType::Discord, discord_bot_id);
-    let mut channels: Vec<ModuleConfig> = vec![];
-    if self.discord_table_exists(discord_bot_id).await {
-      let db = sqlx::query(format!(r#"SELECT * FROM {:?}"#, table_name).as_str()).fetch_all(&self.database).await.unwrap();
-
-      for row in db {
-        let channel_str: String = row.get(0);
-        let channel: u64 = channel_str.parse().unwrap();
-        let feeds_string_list: String = row.get(1);
-        let active: bool = row.get(2);
-        let comments: bool = row.get(3);
-        let uploads: bool = row.get(4);
-        let retrieve_all_pages: bool = row.get(5);
-        let pinged_role_str: String = row.get(6);
-        let pinged_role = pinged_role_str.parse::<u64>().unwrap();
-        let feeds: Vec<String> = feeds_string_list.split(',').map(|str| str.to_string()).collect();
-
-        channels.append(&mut vec![ModuleConfig {
-          module_type: ModuleType::Discord,
-          active,
-          feeds: Some(feeds),
-          comments: Some(comments),
-          uploads: Some(uploads),
-          retrieve_all_pages: Some(retrieve_all_pages),
-          discord_channel_id: Some(channel),
-          smtp_username: None,
-          smtp_password: None,
-          smtp_domain: None,
-          smtp_port: None,
-          smtp_subject: None,
-          smtp_recipients: None,
-          gotify_domain: None,
-          gotify_token: None,
-          gotify_comment_priority: None,
-          gotify_upload_priority: None,
-          discord_token: None,
-          discord_bot_id: Some(discord_bot_id.to_string()),
-          discord_pinged_role: Some(pinged_role),
-          discord_bot_activity_type: None,
-          discord_bot_activity_text: None
-        }]);
-      }
+  /// Forgets what has been stored for the subscription, so that its feeds are indexed again on the next check without notifying.
+  /// A discord channel keeps its configuration. Returns whether the subscription was known at all.
+  pub async fn reset_subscription(&mut self, database_type: String, database_id: &String) -> Result<bool> {
+    let key = subscription_key(&database_type, database_id);
+    let mut transaction = self.database.begin().await.context("starting transaction")?;
+    let known = sqlx::query("UPDATE subscriptions SET indexed = 0 WHERE key = ?")
+      .bind(&key)
+    .execute(&mut *transaction).await.context("resetting subscription")?.rows_affected() > 0;
+    for statement in [
+      "DELETE FROM deliveries WHERE subscription = ?",
+      "DELETE FROM comments WHERE subscription = ?",
+      "DELETE FROM sent_messages WHERE subscription = ?",
+      "DELETE FROM events WHERE subscription = ?"
+    ] {
+      sqlx::query(statement).bind(&key).execute(&mut *transaction).await.context("resetting subscription")?;
+    }
+    transaction.commit().await.context("committing subscription reset")?;
+    Ok(known)
+  }
+
+  /// Everything stored, as `{"schema_version": <version>, "tables": {"<table>": [{"<column>": <value>, ...}, ...], ...}}`.
+  pub async fn export(&mut self) -> Result<serde_json::Value> {
+    let mut tables = serde_json::Map::new();
+    for table in TABLES {
+      let columns = self.columns(table).await?;
+      let pairs: Vec<String> = columns.iter().map(|column| format!("'{}', \"{}\"", column, column)).collect();
+      // sqlite builds the json itself, so that every value keeps its type
+      let rows: String = sqlx::query_scalar(&format!("SELECT json_group_array(json_object({})) FROM \"{}\"", pairs.join(", "), table))
+      .fetch_one(&self.database).await.context(&format!("exporting {}", table))?;
+      let rows = serde_json::from_str(&rows).map_err(|e| Error::parse("database export", e))?;
+      tables.insert(table.to_string(), rows);
     }
-    channels
+    Ok(serde_json::json!({ "schema_version": SCHEMA_VERSION, "tables": tables }))
   }
 
-  pub async fn get_torrents_from_db(&mut self, database_type: String, database_id: &String) -> Vec<NyaaTorrent> {
-    let table_name = format!("_{}_{}", database_type, database_id);
-    let db = sqlx::query(format!(r#"SELECT * FROM {:?}"#, table_name).as_str()).fetch_all(&self.database).await.unwrap();
+  /// Replaces everything stored with what `export` wrote, which has to come from the same schema version.
+  pub async fn import(&mut self, export: &serde_json::Value) -> Result<()> {
+    if export["schema_version"].as_i64() != Some(SCHEMA_VERSION) {
+      return Err(Error::Data(format!("The export has schema version {}, this database has version {}.", export["schema_version"], SCHEMA_VERSION)));
+    }
+    let mut columns = vec![];
+    for table in TABLES {
+      columns.push(self.columns(table).await?);
+    }
+    let mut transaction = self.database.begin().await.context("starting import")?;
+    for table in TABLES {
+      sqlx::query(&format!("DELETE FROM \"{}\"", table)).execute(&mut *transaction).await.context(&format!("emptying {}", table))?;
+    }
+    for (table, columns) in TABLES.iter().zip(columns) {
+      let rows = export["tables"][table].as_array()
+        .ok_or_else(|| Error::Data(format!("The export is missing the {} table.", table)))?;
+      let names: Vec<String> = columns.iter().map(|column| format!("\"{}\"", column)).collect();
+      let values: Vec<String> = columns.iter().map(|column| format!("json_extract(value, '$.{}')", column)).collect();
+      sqlx::query(&format!("INSERT INTO \"{}\" ({}) SELECT {} FROM json_each(?)", table, names.join(", "), values.join(", ")))
+        .bind(serde_json::Value::Array(rows.clone()).to_string())
+      .execute(&mut *transaction).await.context(&format!("importing {}", table))?;
+    }
+    transaction.commit().await.context("committing import")
+  }
+
+  async fn columns(&self, table: &str) -> Result<Vec<String>> {
+    let rows = sqlx::query(&format!("PRAGMA table_info(\"{}\")", table)).fetch_all(&self.database).await.context("reading table columns")?;
+    rows.iter().map(|row| row.try_get("name").map_err(|e| Error::database("reading table columns", e))).collect()
+  }
+
+  pub async fn get_torrents_from_db(&mut self, database_type: String, database_id: &String) -> Result<Vec<NyaaTorrent>> {
+    let key = subscription_key(&database_type, database_id);
+    let mut comments = self.get_comments_from_db(&key).await?;
+    let db = sqlx::query(r#"SELECT t.domain, t.id, t.title, t.category, t.size, t.magnet_link, t.upload_date_str, t.upload_date_timestamp,
+      t.seeders, t.leechers, t.completed, t.trusted, t.remake, d.comments_amount,
+      t.release_group, t.series, t.season, t.episode, t.last_episode, t.version, t.resolution, t.codec, t.source, t.crc
+      FROM deliveries d JOIN torrents t ON t.domain = d.domain AND t.id = d.torrent_id
+      WHERE d.subscription = ?"#)
+      .bind(&key)
+    .fetch_all(&self.database).await.context("reading torrents")?;
     let mut torrents: Vec<NyaaTorrent> = vec![];
 
     for row in db {
-      let id: u64 = row.get_unchecked::<f64, _>(0) as u64;
-      let domain: String = row.get(1);
-      let title_encoded: &String = &row.get(2);
-      let title_decoded = html_escape::decode_html_entities(title_encoded);
-      let title = title_decoded.replace(r#"\\""#, r#"\""#).to_string();
-      let category: String = row.get(3);
-      let size: String = row.get(4);
-      let magnet_link: String = row.get(5);
-      let upload_date_str: String = row.get(6);
-      let upload_date_timestamp: f64 = row.get_unchecked::<f64, _>(7);
-      let seeders: u64 = row.get_unchecked::<f64, _>(8) as u64;
-      let leechers: u64 = row.get_unchecked::<f64, _>(9) as u64;
-      let completed: u64 = row.get_unchecked::<f64, _>(10) as u64;
-      let comments_amount: u64 = row.get_unchecked::<f64, _>(11) as u64;
-      let comments: Vec<NyaaComment> = serde_json::from_str(&row.get_unchecked::<String, _>(12)).unwrap();
-      torrents.append(&mut vec![NyaaTorrent {
-        uploader: None,
-        id,
-        domain,
-        title,
-        category,
-        size,
-        magnet_link,
-        upload_date_str,
-        upload_date_timestamp,
-        seeders,
-        leechers,
-        completed,
-        comments_amount,
-        comments
-      }]);
+      match torrent_from_row(&row) {
+        Ok(mut torrent) => {
+          torrent.comments = comments.remove(&(torrent.domain.clone(), torrent.id)).unwrap_or_default();
+          torrents.append(&mut vec![torrent]);
+        },
+        Err(e) => {
+          warn!("Skipping unreadable torrent of {:?}: {}", key, e);
+        }
+      }
+    }
+
+    Ok(torrents)
+  }
+
+  /// The releases stored for the subscription with the same series, group and season.
+  pub async fn get_series_releases(&mut self, database_type: String, database_id: &String, release: &Release) -> Result<Vec<Release>> {
+    let key = subscription_key(&database_type, database_id);
+    let rows = sqlx::query(r#"SELECT t.release_group, t.series, t.season, t.episode, t.last_episode, t.version, t.resolution, t.codec, t.source, t.crc
+      FROM deliveries d JOIN torrents t ON t.domain = d.domain AND t.id = d.torrent_id
+      WHERE d.subscription = ? AND t.series = ? AND t.release_group IS ? AND t.season IS ? AND t.episode IS NOT NULL"#)
+      .bind(&key)
+      .bind(&release.series)
+      .bind(&release.group)
+      .bind(release.season)
+    .fetch_all(&self.database).await.context("reading series releases")?;
+    rows.iter().map(release_from_row).collect()
+  }
+
+  async fn get_comments_from_db(&mut self, key: &String) -> Result<HashMap<(String, u64), Vec<NyaaComment>>> {
+    let db = sqlx::query(r#"SELECT * FROM comments WHERE subscription = ? ORDER BY domain, torrent_id, position"#)
+      .bind(key)
+    .fetch_all(&self.database).await.context("reading comments")?;
+    let mut comments: HashMap<(String, u64), Vec<NyaaComment>> = HashMap::new();
+
+    for row in db {
+      match comment_from_row(&row) {
+        Ok((domain, torrent_id, comment)) => {
+          comments.entry((domain, torrent_id)).or_default().append(&mut vec![comment]);
+        },
+        Err(e) => {
+          warn!("Skipping unreadable comment of {:?}: {}", key, e);
+        }
+      }
     }
 
-    torrents
+    Ok(comments)
+  }
+}
+
+fn torrent_from_row(row: &SqliteRow) -> Result<NyaaTorrent> {
+  let read = |e| Error::database("decoding torrent", e);
+  Ok(NyaaTorrent {
+    uploader: None,
+    id: row.try_get::<i64, _>("id").map_err(read)? as u64,
+    domain: row.try_get("domain").map_err(read)?,
+    title: row.try_get("title").map_err(read)?,
+    release: release_from_row(row)?,
+    category: row.try_get("category").map_err(read)?,
+    size: row.try_get("size").map_err(read)?,
+    magnet_link: row.try_get("magnet_link").map_err(read)?,
+    upload_date_str: row.try_get("upload_date_str").map_err(read)?,
+    upload_date_timestamp: row.try_get("upload_date_timestamp").map_err(read)?,
+    seeders: row.try_get::<i64, _>("seeders").map_err(read)? as u64,
+    leechers: row.try_get::<i64, _>("leechers").map_err(read)? as u64,
+    completed: row.try_get::<i64, _>("completed").map_err(read)? as u64,
+    trusted: row.try_get("trusted").map_err(read)?,
+    remake: row.try_get("remake").map_err(read)?,
+    comments_amount: row.try_get::<i64, _>("comments_amount").map_err(read)? as u64,
+    comments: vec![]
+  })
+}
+
+fn release_from_row(row: &SqliteRow) -> Result<Release> {
+  let read = |e| Error::database("decoding release name", e);
+  Ok(Release {
+    group: row.try_get("release_group").map_err(read)?,
+    series: row.try_get("series").map_err(read)?,
+    season: row.try_get("season").map_err(read)?,
+    episode: row.try_get("episode").map_err(read)?,
+    last_episode: row.try_get("last_episode").map_err(read)?,
+    version: row.try_get("version").map_err(read)?,
+    resolution: row.try_get("resolution").map_err(read)?,
+    codec: row.try_get("codec").map_err(read)?,
+    source: row.try_get("source").map_err(read)?,
+    crc: row.try_get("crc").map_err(read)?
+  })
+}
+
+fn comment_from_row(row: &SqliteRow) -> Result<(String, u64, NyaaComment)> {
+  let read = |e| Error::database("decoding comment", e);
+  let update_type: String = row.try_get("update_type").map_err(read)?;
+  Ok((row.try_get("domain").map_err(read)?, row.try_get::<i64, _>("torrent_id").map_err(read)? as u64, NyaaComment {
+    user: NyaaUser {
+      anonymous: row.try_get("user_anonymous").map_err(read)?,
+      role: row.try_get("user_role").map_err(read)?,
+      username: row.try_get("username").map_err(read)?,
+      avatar: row.try_get("user_avatar").map_err(read)?,
+      banned: row.try_get("user_banned").map_err(read)?
+    },
+    message: row.try_get("message").map_err(read)?,
+    old_message: row.try_get("old_message").map_err(read)?,
+    uploader: row.try_get("uploader").map_err(read)?,
+    date_timestamp: row.try_get("date_timestamp").map_err(read)?,
+    edited_timestamp: row.try_get("edited_timestamp").map_err(read)?,
+    old_edited_timestamp: row.try_get("old_edited_timestamp").map_err(read)?,
+    direct_link: row.try_get("direct_link").map_err(read)?,
+    update_type: update_type.parse().map_err(|e: String| Error::parse("comment update type", e))?
+  }))
+}
+
+fn subscription_key(database_type: &String, database_id: &String) -> String {
+  format!("{}_{}", database_type, database_id)
+}
+
+fn discord_subscription_key(discord_bot_id: &String, discord_channel_id: u64) -> String {
+  subscription_key(&ModuleType::Discord.to_string(), &format!("{}_{}", discord_bot_id, discord_channel_id))
+}
+
+async fn replace_comments(connection: &mut SqliteConnection, key: &String, domain: &String, torrent_id: u64, comments: &[NyaaComment]) -> std::result::Result<(), sqlx::Error> {
+  sqlx::query("DELETE FROM comments WHERE subscription = ? AND domain = ? AND torrent_id = ?")
+    .bind(key)
+    .bind(domain)
+    .bind(torrent_id as i64)
+  .execute(&mut *connection).await?;
+
+  for (position, comment) in comments.iter().enumerate() {
+    sqlx::query(r#"INSERT INTO comments (subscription, domain, torrent_id, position, user_anonymous, user_role, username, user_avatar, user_banned,
+      message, old_message, uploader, date_timestamp, edited_timestamp, old_edited_timestamp, direct_link, update_type)
+      VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
+      .bind(key)
+      .bind(domain)
+      .bind(torrent_id as i64)
+      .bind(position as i64)
+      .bind(comment.user.anonymous)
+      .bind(&comment.user.role)
+      .bind(&comment.user.username)
+      .bind(&comment.user.avatar)
+      .bind(comment.user.banned)
+      .bind(&comment.message)
+      .bind(&comment.old_message)
+      .bind(comment.uploader)
+      .bind(comment.date_timestamp)
+      .bind(comment.edited_timestamp)
+      .bind(comment.old_edited_timestamp)
+      .bind(&comment.direct_link)
+      .bind(comment.update_type.to_string())
+    .execute(&mut *connection).await?;
+  }
+  Ok(())
+}
+
+fn quote_identifier(identifier: &str) -> String {
+  format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// Writes the release name's parts into the stored torrent's columns.
+async fn store_release(connection: &mut SqliteConnection, domain: &str, id: u64, release: &Release) -> std::result::Result<(), sqlx::Error> {
+  sqlx::query(r#"UPDATE torrents SET release_group = ?, series = ?, season = ?, episode = ?, last_episode = ?, version = ?,
+    resolution = ?, codec = ?, source = ?, crc = ? WHERE domain = ? AND id = ?"#)
+    .bind(&release.group)
+    .bind(&release.series)
+    .bind(release.season)
+    .bind(release.episode)
+    .bind(release.last_episode)
+    .bind(release.version)
+    .bind(&release.resolution)
+    .bind(&release.codec)
+    .bind(&release.source)
+    .bind(&release.crc)
+    .bind(domain)
+    .bind(id as i64)
+  .execute(&mut *connection).await?;
+  Ok(())
+}
+
+/// Parses the titles of the torrents stored before release names were.
+async fn migrate_release_names(connection: &mut SqliteConnection) -> std::result::Result<(), sqlx::Error> {
+  let rows = sqlx::query("SELECT domain, id, title FROM torrents").fetch_all(&mut *connection).await?;
+  for row in rows {
+    let domain: String = row.try_get("domain")?;
+    let id: i64 = row.try_get("id")?;
+    let title: String = row.try_get("title")?;
+    store_release(&mut *connection, &domain, id as u64, &Release::parse(&title)).await?;
+  }
+  Ok(())
+}
+
+/// A row of a schema version 0 channel table.
+struct LegacyChannel {
+  channel: u64,
+  feeds: String,
+  active: bool,
+  comments: bool,
+  uploads: bool,
+  retrieve_all_pages: bool,
+  pinged_role: u64
+}
+
+fn legacy_channel(row: &SqliteRow) -> std::result::Result<LegacyChannel, sqlx::Error> {
+  let channel: String = row.try_get(0)?;
+  Ok(LegacyChannel {
+    channel: channel.parse().map_err(|e| sqlx::Error::ColumnDecode { index: "0".to_string(), source: Box::new(e) })?,
+    feeds: row.try_get(1)?,
+    active: row.try_get(2)?,
+    comments: row.try_get(3)?,
+    uploads: row.try_get(4)?,
+    retrieve_all_pages: row.try_get(5)?,
+    // an empty role pings nobody
+    pinged_role: row.try_get::<String, _>(6)?.parse().unwrap_or(0)
+  })
+}
+
+/// A row of a schema version 0 data table.
+struct LegacyTorrent {
+  id: u64,
+  domain: String,
+  title: String,
+  category: String,
+  size: String,
+  magnet_link: String,
+  upload_date_str: String,
+  upload_date_timestamp: f64,
+  seeders: u64,
+  leechers: u64,
+  completed: u64,
+  comments_amount: u64,
+  comments: Vec<NyaaComment>
+}
+
+fn legacy_torrent(row: &SqliteRow) -> std::result::Result<LegacyTorrent, sqlx::Error> {
+  let title: String = row.try_get(2)?;
+  let title = html_escape::decode_html_entities(&title).replace(r#"\\""#, r#"\""#);
+  let comments: Option<String> = row.try_get(12)?;
+  Ok(LegacyTorrent {
+    id: legacy_number(row, 0)? as u64,
+    domain: row.try_get(1)?,
+    title,
+    category: row.try_get(3)?,
+    size: row.try_get(4)?,
+    magnet_link: row.try_get(5)?,
+    upload_date_str: row.try_get(6)?,
+    upload_date_timestamp: legacy_number(row, 7)?,
+    seeders: legacy_number(row, 8)? as u64,
+    leechers: legacy_number(row, 9)? as u64,
+    completed: legacy_number(row, 10)? as u64,
+    comments_amount: legacy_number(row, 11)? as u64,
+    comments: comments.and_then(|comments| serde_json::from_str(&comments).ok()).unwrap_or_default()
+  })
+}
+
+/// Version 0 bound its numbers as floats into INTEGER columns, so both storage classes are read as one.
+fn legacy_number(row: &SqliteRow, index: usize) -> std::result::Result<f64, sqlx::Error> {
+  let value = row.try_get_raw(index)?;
+  let storage = if value.is_null() { "NULL".to_string() } else { value.type_info().name().to_string() };
+  if storage != "INTEGER" && storage != "REAL" {
+    return Err(sqlx::Error::ColumnDecode { index: index.to_string(), source: format!("expected a number, found {}", storage).into() });
+  }
+  row.try_get_unchecked(index)
+}
+
+/// Moves the per-module tables of schema version 0 (`_Email_0`, `_Discord_<bot>`, `_Discord_<bot>_<channel>`, ...)
+/// into the normalized tables, so that nothing that has already been seen gets announced again.
+async fn migrate_legacy_tables(connection: &mut SqliteConnection) -> std::result::Result<(), sqlx::Error> {
+  let tables: Vec<String> = sqlx::query_scalar(r#"SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE '\_%' ESCAPE '\'"#)
+  .fetch_all(&mut *connection).await?;
+
+  let mut channel_tables: Vec<String> = vec![];
+  let mut data_tables: Vec<String> = vec![];
+  for table in tables {
+    // Table names can't be bound, they are taken straight from sqlite_master though.
+    let columns: Vec<String> = sqlx::query(&format!("PRAGMA table_info({})", quote_identifier(&table)))
+    .fetch_all(&mut *connection).await?.iter().map(|row| row.try_get::<String, _>("name")).collect::<std::result::Result<_, _>>()?;
+    if columns.iter().any(|column| column == "Channel") {
+      channel_tables.append(&mut vec![table]);
+    } else if columns.iter().any(|column| column == "ID") {
+      data_tables.append(&mut vec![table]);
+    }
+  }
+
+  // Channel tables go first, so that the channel data tables find their discord settings
+  for table in channel_tables {
+    let discord_bot_id = table.trim_start_matches(&format!("_{}_", ModuleType::Discord)).to_string();
+    info!("Migrating discord channels of {:?}", discord_bot_id);
+    let rows = sqlx::query(&format!("SELECT * FROM {}", quote_identifier(&table))).fetch_all(&mut *connection).await?;
+    for row in rows {
+      let channel = match legacy_channel(&row) {
+        Ok(channel) => channel,
+        Err(e) => {
+          warn!("Skipping unreadable row of {:?}: {}", table, e);
+          continue;
+        }
+      };
+      sqlx::query(r#"INSERT OR IGNORE INTO subscriptions (key, module_type, discord_bot_id, discord_channel_id, feeds, active, comments, uploads, retrieve_all_pages, pinged_role)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
+        .bind(discord_subscription_key(&discord_bot_id, channel.channel))
+        .bind(ModuleType::Discord.to_string())
+        .bind(&discord_bot_id)
+        .bind(channel.channel as i64)
+        .bind(channel.feeds)
+        .bind(channel.active)
+        .bind(channel.comments)
+        .bind(channel.uploads)
+        .bind(channel.retrieve_all_pages)
+        .bind(channel.pinged_role as i64)
+      .execute(&mut *connection).await?;
+    }
+    sqlx::query(&format!("DROP TABLE {}", quote_identifier(&table))).execute(&mut *connection).await?;
+  }
+
+  for table in data_tables {
+    let key = table.trim_start_matches('_').to_string();
+    let module_type = key.split('_').next().unwrap_or_default().to_string();
+    info!("Migrating table {:?}", table);
+    sqlx::query(r#"INSERT INTO subscriptions (key, module_type, indexed) VALUES (?, ?, 1)
+      ON CONFLICT (key) DO UPDATE SET indexed = 1"#)
+      .bind(&key)
+      .bind(&module_type)
+    .execute(&mut *connection).await?;
+
+    let rows = sqlx::query(&format!("SELECT * FROM {}", quote_identifier(&table))).fetch_all(&mut *connection).await?;
+    for row in rows {
+      let torrent = match legacy_torrent(&row) {
+        Ok(torrent) => torrent,
+        Err(e) => {
+          warn!("Skipping unreadable row of {:?}: {}", table, e);
+          continue;
+        }
+      };
+
+      sqlx::query(r#"INSERT OR REPLACE INTO torrents (domain, id, title, category, size, magnet_link, upload_date_str, upload_date_timestamp, seeders, leechers, completed)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#)
+        .bind(&torrent.domain)
+        .bind(torrent.id as i64)
+        .bind(&torrent.title)
+        .bind(&torrent.category)
+        .bind(&torrent.size)
+        .bind(&torrent.magnet_link)
+        .bind(&torrent.upload_date_str)
+        .bind(torrent.upload_date_timestamp)
+        .bind(torrent.seeders as i64)
+        .bind(torrent.leechers as i64)
+        .bind(torrent.completed as i64)
+      .execute(&mut *connection).await?;
+
+      sqlx::query("INSERT OR REPLACE INTO deliveries (subscription, domain, torrent_id, comments_amount) VALUES (?, ?, ?, ?)")
+        .bind(&key)
+        .bind(&torrent.domain)
+        .bind(torrent.id as i64)
+        .bind(torrent.comments_amount as i64)
+      .execute(&mut *connection).await?;
+
+      replace_comments(&mut *connection, &key, &torrent.domain, torrent.id, &torrent.comments).await?;
+    }
+    sqlx::query(&format!("DROP TABLE {}", quote_identifier(&table))).execute(&mut *connection).await?;
   }
+  Ok(())
 }
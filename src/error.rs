@@ -0,0 +1,128 @@
+use std::process::ExitCode;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+  Config(String),
+  Http { url: String, reason: String },
+  Parse { context: String, reason: String },
+  Database { context: String, source: sqlx::Error },
+  Io { context: String, source: std::io::Error },
+  /// Input which is well-formed but doesn't fit, like an export of another schema version.
+  Data(String),
+  Smtp(String),
+  Gotify(String),
+  Discord(String),
+  Webhook(String),
+  Telegram(String),
+  Matrix(String),
+  Ntfy(String),
+  Slack(String),
+  Pushover(String)
+}
+
+impl Error {
+  pub fn http(url: &str, reason: impl ToString) -> Self {
+    Error::Http { url: url.to_string(), reason: reason.to_string() }
+  }
+
+  pub fn parse(context: &str, reason: impl ToString) -> Self {
+    Error::Parse { context: context.to_string(), reason: reason.to_string() }
+  }
+
+  pub fn database(context: &str, source: sqlx::Error) -> Self {
+    Error::Database { context: context.to_string(), source }
+  }
+
+  pub fn io(context: &str, source: std::io::Error) -> Self {
+    Error::Io { context: context.to_string(), source }
+  }
+
+  /// Errors which are worth trying again on the next check, without giving up on the module.
+  pub fn is_transient(&self) -> bool {
+    matches!(self, Error::Http { .. } | Error::Smtp(_) | Error::Gotify(_) | Error::Discord(_) | Error::Webhook(_) | Error::Telegram(_) | Error::Matrix(_) | Error::Ntfy(_) | Error::Slack(_) | Error::Pushover(_))
+  }
+
+  /// Exit codes loosely follow sysexits.h, so that service managers can tell a broken config from a broken disk.
+  pub fn exit_code(&self) -> ExitCode {
+    match self {
+      Error::Config(_) => ExitCode::from(78),
+      Error::Database { .. } | Error::Io { .. } => ExitCode::from(74),
+      Error::Http { .. } | Error::Smtp(_) | Error::Gotify(_) | Error::Discord(_) | Error::Webhook(_) | Error::Telegram(_) | Error::Matrix(_) | Error::Ntfy(_) | Error::Slack(_) | Error::Pushover(_) => ExitCode::from(69),
+      Error::Parse { .. } | Error::Data(_) => ExitCode::from(65)
+    }
+  }
+}
+
+impl std::fmt::Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::Config(reason) => {
+        write!(f, "Configuration error: {}", reason)
+      },
+      Error::Http { url, reason } => {
+        write!(f, "Request to {:?} failed: {}", url, reason)
+      },
+      Error::Parse { context, reason } => {
+        write!(f, "Failed to parse {}: {}", context, reason)
+      },
+      Error::Database { context, source } => {
+        write!(f, "Database error while {}: {}", context, source)
+      },
+      Error::Io { context, source } => {
+        write!(f, "I/O error while {}: {}", context, source)
+      },
+      Error::Data(reason) => {
+        write!(f, "Invalid data: {}", reason)
+      },
+      Error::Smtp(reason) => {
+        write!(f, "SMTP error: {}", reason)
+      },
+      Error::Gotify(reason) => {
+        write!(f, "Gotify error: {}", reason)
+      },
+      Error::Discord(reason) => {
+        write!(f, "Discord error: {}", reason)
+      },
+      Error::Webhook(reason) => {
+        write!(f, "Webhook error: {}", reason)
+      },
+      Error::Telegram(reason) => {
+        write!(f, "Telegram error: {}", reason)
+      },
+      Error::Matrix(reason) => {
+        write!(f, "Matrix error: {}", reason)
+      },
+      Error::Ntfy(reason) => {
+        write!(f, "Ntfy error: {}", reason)
+      },
+      Error::Slack(reason) => {
+        write!(f, "Slack error: {}", reason)
+      },
+      Error::Pushover(reason) => {
+        write!(f, "Pushover error: {}", reason)
+      }
+    }
+  }
+}
+
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Error::Database { source, .. } => Some(source),
+      Error::Io { source, .. } => Some(source),
+      _ => None
+    }
+  }
+}
+
+pub trait DatabaseContext<T> {
+  fn context(self, context: &str) -> Result<T>;
+}
+
+impl<T> DatabaseContext<T> for std::result::Result<T, sqlx::Error> {
+  fn context(self, context: &str) -> Result<T> {
+    self.map_err(|source| Error::database(context, source))
+  }
+}
@@ -0,0 +1,312 @@
+use regex::Regex;
+use std::collections::HashMap;
+use tracing::debug;
+
+use crate::config::{
+  ModuleConfig, Rules
+};
+use crate::release::{
+  normalize_codec, normalize_resolution, normalize_source
+};
+use crate::web::{
+  NyaaCommentUpdateType, NyaaTorrent, NyaaUpdate
+};
+use crate::error::{
+  Error, Result
+};
+
+/// How long a new upload is waited on to reach the swarm rules when `swarm_wait_hours` isn't set.
+pub const DEFAULT_SWARM_WAIT_HOURS: u64 = 6;
+
+/// The categories of nyaa by id.
+const NYAA_CATEGORIES: [(&str, &str); 23] = [
+  ("1_0", "Anime"),
+  ("1_1", "Anime - Anime Music Video"),
+  ("1_2", "Anime - English-translated"),
+  ("1_3", "Anime - Non-English-translated"),
+  ("1_4", "Anime - Raw"),
+  ("2_0", "Audio"),
+  ("2_1", "Audio - Lossless"),
+  ("2_2", "Audio - Lossy"),
+  ("3_0", "Literature"),
+  ("3_1", "Literature - English-translated"),
+  ("3_2", "Literature - Non-English-translated"),
+  ("3_3", "Literature - Raw"),
+  ("4_0", "Live Action"),
+  ("4_1", "Live Action - English-translated"),
+  ("4_2", "Live Action - Idol/Promotional Video"),
+  ("4_3", "Live Action - Non-English-translated"),
+  ("4_4", "Live Action - Raw"),
+  ("5_0", "Pictures"),
+  ("5_1", "Pictures - Graphics"),
+  ("5_2", "Pictures - Photos"),
+  ("6_0", "Software"),
+  ("6_1", "Software - Applications"),
+  ("6_2", "Software - Games")
+];
+
+/// The categories of sukebei by id. The ids are nyaa's over again, the names don't overlap.
+const SUKEBEI_CATEGORIES: [(&str, &str); 9] = [
+  ("1_0", "Art"),
+  ("1_1", "Art - Anime"),
+  ("1_2", "Art - Doujinshi"),
+  ("1_3", "Art - Games"),
+  ("1_4", "Art - Manga"),
+  ("1_5", "Art - Pictures"),
+  ("2_0", "Real Life"),
+  ("2_1", "Real Life - Photobooks and Pictures"),
+  ("2_2", "Real Life - Videos")
+];
+
+/// What becomes of an update, from the mildest to the strictest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verdict {
+  Send,
+  /// A new upload which hasn't reached the swarm rules yet. It isn't stored, so it's new again on the next poll.
+  Wait,
+  /// Stored as seen without being sent.
+  Drop
+}
+
+/// The rules of a module and of each of its feeds, ready to be matched. A torrent has to pass both.
+pub struct Filter {
+  module: CompiledRules,
+  feeds: HashMap<String, CompiledRules>
+}
+
+struct CompiledRules {
+  title_include: Vec<Regex>,
+  title_exclude: Vec<Regex>,
+  categories: Vec<String>,
+  min_size: Option<u64>,
+  max_size: Option<u64>,
+  min_seeders: u64,
+  min_leechers: u64,
+  min_completed: u64,
+  swarm_wait: f64,
+  trusted_only: bool,
+  skip_remakes: bool,
+  skip_anonymous: bool,
+  release_groups: Vec<String>,
+  resolutions: Vec<String>,
+  codecs: Vec<String>,
+  sources: Vec<String>
+}
+
+impl CompiledRules {
+  fn new(rules: &Rules) -> Result<Self> {
+    let categories = rules.categories.clone().unwrap_or_default();
+    if let Some(unknown) = categories.iter().find(|category| !is_category_id(category) && !is_category_name(category)) {
+      return Err(Error::Config(format!("{:?} is neither a category id like \"1_2\" nor the name of a category", unknown)));
+    }
+    Ok(CompiledRules {
+      title_include: compile(rules.title_include.as_deref())?,
+      title_exclude: compile(rules.title_exclude.as_deref())?,
+      categories,
+      min_size: rules.min_size.as_deref().map(parse_size_rule).transpose()?,
+      max_size: rules.max_size.as_deref().map(parse_size_rule).transpose()?,
+      min_seeders: rules.min_seeders.unwrap_or(0),
+      min_leechers: rules.min_leechers.unwrap_or(0),
+      min_completed: rules.min_completed.unwrap_or(0),
+      swarm_wait: (rules.swarm_wait_hours.unwrap_or(DEFAULT_SWARM_WAIT_HOURS) * 3600) as f64,
+      trusted_only: rules.trusted_only.unwrap_or(false),
+      skip_remakes: rules.skip_remakes.unwrap_or(false),
+      skip_anonymous: rules.skip_anonymous.unwrap_or(false),
+      release_groups: rules.release_groups.clone().unwrap_or_default(),
+      resolutions: normalize("resolution", rules.resolutions.as_deref(), normalize_resolution)?,
+      codecs: normalize("codec", rules.codecs.as_deref(), normalize_codec)?,
+      sources: normalize("source", rules.sources.as_deref(), normalize_source)?
+    })
+  }
+
+  fn verdict(&self, torrent: &NyaaTorrent, new_upload: bool, now: f64) -> Verdict {
+    if !self.allows(torrent) {
+      Verdict::Drop
+    } else if !new_upload || self.swarm_is_healthy(torrent) {
+      Verdict::Send
+    } else if now - torrent.upload_date_timestamp < self.swarm_wait {
+      Verdict::Wait
+    } else {
+      Verdict::Drop
+    }
+  }
+
+  fn allows(&self, torrent: &NyaaTorrent) -> bool {
+    let size = parse_size(&torrent.size);
+    (self.title_include.is_empty() || self.title_include.iter().any(|regex| regex.is_match(&torrent.title))) &&
+    !self.title_exclude.iter().any(|regex| regex.is_match(&torrent.title)) &&
+    (self.categories.is_empty() || self.categories.iter().any(|category| category_matches(category, &torrent.domain, &torrent.category))) &&
+    self.min_size.is_none_or(|min| size.is_some_and(|size| size >= min)) &&
+    self.max_size.is_none_or(|max| size.is_some_and(|size| size <= max)) &&
+    (!self.trusted_only || torrent.trusted) &&
+    (!self.skip_remakes || !torrent.remake) &&
+    // the submitter is only known when the torrent page has been read
+    (!self.skip_anonymous || !torrent.uploader.as_ref().is_some_and(|uploader| uploader.anonymous)) &&
+    one_of(&self.release_groups, &torrent.release.group) &&
+    one_of(&self.resolutions, &torrent.release.resolution) &&
+    one_of(&self.codecs, &torrent.release.codec) &&
+    one_of(&self.sources, &torrent.release.source)
+  }
+
+  fn swarm_is_healthy(&self, torrent: &NyaaTorrent) -> bool {
+    torrent.seeders >= self.min_seeders && torrent.leechers >= self.min_leechers && torrent.completed >= self.min_completed
+  }
+}
+
+/// Titles which don't say are let through only when there's no rule.
+fn one_of(wanted: &[String], value: &Option<String>) -> bool {
+  wanted.is_empty() || value.as_ref().is_some_and(|value| wanted.iter().any(|wanted| wanted.eq_ignore_ascii_case(value)))
+}
+
+/// Rules are written like `x265` or `1080`, the parsed titles have `HEVC` and `1080p`.
+fn normalize(kind: &str, values: Option<&[String]>, normalizer: fn(&str) -> Option<String>) -> Result<Vec<String>> {
+  values.unwrap_or_default().iter()
+    .map(|value| normalizer(value).ok_or_else(|| Error::Config(format!("{:?} isn't a {} the release names are matched against", value, kind))))
+    .collect()
+}
+
+fn is_category_id(category: &str) -> bool {
+  category.split_once('_').is_some_and(|(main, sub)|
+    !main.is_empty() && !sub.is_empty() && main.chars().chain(sub.chars()).all(|c| c.is_ascii_digit()))
+}
+
+fn is_category_name(name: &str) -> bool {
+  NYAA_CATEGORIES.iter().chain(SUKEBEI_CATEGORIES.iter()).any(|(_, known)| known.eq_ignore_ascii_case(name.trim()))
+}
+
+/// The id of a category of the torrent's site, as a sukebei id means something else on nyaa.
+fn category_id(domain: &str, name: &str) -> Option<&'static str> {
+  let categories: &[(&str, &str)] = if domain.contains("sukebei.") {
+    &SUKEBEI_CATEGORIES
+  } else {
+    &NYAA_CATEGORIES
+  };
+  categories.iter().find(|(_, known)| known.eq_ignore_ascii_case(name.trim())).map(|(id, _)| *id)
+}
+
+/// A rule's category against the name of a torrent's. `1_0` takes in `1_1`, `1_2` and so on.
+fn category_matches(rule: &str, domain: &str, name: &str) -> bool {
+  if !is_category_id(rule) {
+    return rule.trim().eq_ignore_ascii_case(name.trim());
+  }
+  match (category_id(domain, name), rule.strip_suffix("_0")) {
+    (Some(id), Some(main)) => id.split_once('_').is_some_and(|(torrent_main, _)| torrent_main == main),
+    (Some(id), None) => id == rule,
+    (None, _) => false
+  }
+}
+
+/// The size in bytes of strings like `1.3 GiB` or `512 Bytes`, as nyaa shows them.
+pub fn parse_size(size: &str) -> Option<u64> {
+  let size = size.trim();
+  let split = size.find(|c: char| c.is_alphabetic()).unwrap_or(size.len());
+  let number: f64 = size[..split].trim().parse().ok()?;
+  let multiplier: f64 = match size[split..].trim().to_lowercase().as_str() {
+    "" | "b" | "byte" | "bytes" => 1.0,
+    "kib" => 1024.0,
+    "mib" => 1024f64.powi(2),
+    "gib" => 1024f64.powi(3),
+    "tib" => 1024f64.powi(4),
+    "kb" => 1e3,
+    "mb" => 1e6,
+    "gb" => 1e9,
+    "tb" => 1e12,
+    _ => return None
+  };
+  (number.is_finite() && number >= 0.0).then(|| (number * multiplier).round() as u64)
+}
+
+fn parse_size_rule(size: &str) -> Result<u64> {
+  parse_size(size).ok_or_else(|| Error::Config(format!("{:?} isn't a size, they're written like \"200 MiB\" or \"4 GiB\"", size)))
+}
+
+fn compile(patterns: Option<&[String]>) -> Result<Vec<Regex>> {
+  patterns.unwrap_or_default().iter()
+    .map(|pattern| Regex::new(pattern).map_err(|e| Error::Config(format!("{:?} isn't a valid regex: {}", pattern, e))))
+    .collect()
+}
+
+impl Filter {
+  pub fn new(module: &ModuleConfig) -> Result<Self> {
+    let mut feeds = HashMap::new();
+    for feed in module.feeds.iter().flatten() {
+      if let Some(rules) = feed.rules() {
+        feeds.insert(feed.url().to_string(), CompiledRules::new(rules)?);
+      }
+    }
+    Ok(Filter { module: CompiledRules::new(&module.rules)?, feeds })
+  }
+
+  /// The strictest of what the module's rules and the feed's rules make of the update.
+  pub fn verdict(&self, update: &NyaaUpdate) -> Verdict {
+    let now = chrono::Utc::now().timestamp() as f64;
+    let module = self.module.verdict(&update.torrent, update.new_upload, now);
+    let feed = self.feeds.get(&update.feed).map_or(Verdict::Send, |rules| rules.verdict(&update.torrent, update.new_upload, now));
+    module.max(feed)
+  }
+
+  /// Splits the updates into the ones to send and the ones which are filtered out.
+  /// Those waiting on their swarm are in neither.
+  pub fn split(&self, updates: Vec<NyaaUpdate>) -> (Vec<NyaaUpdate>, Vec<NyaaUpdate>) {
+    let (mut send, mut filtered) = (Vec::new(), Vec::new());
+    for update in updates {
+      match self.verdict(&update) {
+        Verdict::Send => send.push(update),
+        Verdict::Drop => filtered.push(update),
+        Verdict::Wait => debug!(torrent_id = update.torrent.id, "Waiting on the swarm of {:?}.", update.torrent.title)
+      }
+    }
+    (send, filtered)
+  }
+}
+
+/// What is stored for a filtered out update: everything in it counts as seen, and its comments are never looked at again.
+pub fn seen(mut update: NyaaUpdate) -> NyaaUpdate {
+  update.torrent.comments.retain(|comment| comment.update_type != NyaaCommentUpdateType::DELETED);
+  for comment in update.torrent.comments.iter_mut() {
+    comment.update_type = NyaaCommentUpdateType::UNDECIDED;
+  }
+  update
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn sizes_are_parsed_into_bytes() {
+    assert_eq!(parse_size("1.3 GiB"), Some(1395864371));
+    assert_eq!(parse_size("200 MiB"), Some(200 * 1024 * 1024));
+    assert_eq!(parse_size("512 Bytes"), Some(512));
+    assert_eq!(parse_size("4GB"), Some(4_000_000_000));
+    assert_eq!(parse_size("big"), None);
+    assert_eq!(parse_size("3 parsecs"), None);
+  }
+
+  #[test]
+  fn categories_match_by_id_and_name() {
+    let nyaa = "https://nyaa.si/";
+    let sukebei = "https://sukebei.nyaa.si/";
+    assert!(category_matches("1_2", nyaa, "Anime - English-translated"));
+    assert!(category_matches("1_0", nyaa, "Anime - Raw"));
+    assert!(!category_matches("1_4", nyaa, "Anime - English-translated"));
+    assert!(!category_matches("2_0", nyaa, "Anime - Raw"));
+    assert!(category_matches("anime - raw", nyaa, "Anime - Raw"));
+    assert!(category_matches("1_2", sukebei, "Art - Doujinshi"));
+    // ids are the torrent's site's
+    assert!(!category_matches("1_2", nyaa, "Art - Doujinshi"));
+    assert!(!category_matches("1_2", sukebei, "Anime - English-translated"));
+    assert!(!category_matches("1_2", nyaa, "Something new"));
+  }
+
+  #[test]
+  fn release_rules_are_written_loosely() {
+    let rules = CompiledRules::new(&Rules {
+      resolutions: Some(vec!["1080".to_string()]),
+      codecs: Some(vec!["x265".to_string()]),
+      ..Default::default()
+    }).unwrap();
+    assert_eq!((rules.resolutions, rules.codecs), (vec!["1080p".to_string()], vec!["HEVC".to_string()]));
+    assert!(CompiledRules::new(&Rules { sources: Some(vec!["VHS".to_string()]), ..Default::default() }).is_err());
+  }
+}
@@ -1,11 +1,19 @@
+use scraper::{
+  ElementRef, Html, Selector
+};
+
+use crate::release::Release;
 use crate::web::{
   NyaaTorrent, NyaaComment, NyaaUser
 };
+use crate::error::{
+  Error, Result
+};
 
-pub fn serialize_feed(html: String, domain: &str) -> Result<(Vec<NyaaTorrent>, bool), String> {
+pub fn serialize_feed(html: String, domain: &str) -> Result<(Vec<NyaaTorrent>, bool)> {
   let id_reg = regex::Regex::new(r"/download/([0-9]{5,})").unwrap();
   if ! html.starts_with(&"<!DOCTYPE html>".to_string()) {
-    return Err("This is not plaintext html code!".to_string());
+    return Err(Error::parse("feed", "This is not plaintext html code!"));
   }
   let lines = html.split('\n');
   let mut worthy_text: usize = 0;
@@ -17,7 +25,7 @@ pub fn serialize_feed(html: String, domain: &str) -> Result<(Vec<NyaaTorrent>, b
       worthy_text = index;
       break
     } else if line.trim() == "</html>" {
-      return Err("Couldn't split html code.".to_string())
+      return Err(Error::parse("feed", "Couldn't split html code."))
     }
   };
   let mut body: Vec<String> = [].to_vec();
@@ -44,10 +52,15 @@ pub fn serialize_feed(html: String, domain: &str) -> Result<(Vec<NyaaTorrent>, b
       let mut temp: String = String::new();
       let mut timestamp: String = String::new();
       let mut comments_found: bool = false;
+      let mut trusted: bool = false;
+      let mut remake: bool = false;
       for line in body_iterator.by_ref() {
         let x = line.trim();
-        // Gathering category string
-        if x.contains(r#"class="category-icon""#) {
+        // The row's colour: green for trusted, red for remakes
+        if x.starts_with("<tr") {
+          trusted = x.contains(r#"class="success""#);
+          remake = x.contains(r#"class="danger""#);
+        } else if x.contains(r#"class="category-icon""#) {
           let category_iterator = x.chars();
           for x in category_iterator {
             if x == '"' {
@@ -97,7 +110,8 @@ pub fn serialize_feed(html: String, domain: &str) -> Result<(Vec<NyaaTorrent>, b
           for (_, [id]) in id_reg.captures_iter(x).map(|c| c.extract()) {
             result.push(id);
           }
-          id = result.first().unwrap().parse::<u64>().unwrap();
+          id = result.first().ok_or_else(|| Error::parse("feed", "Download link without torrent id"))?
+            .parse::<u64>().map_err(|e| Error::parse("torrent id", e))?;
         } else if x.starts_with(r#"<a href="magnet:?xt"#) {
           let iterator = x.chars();
           for x in iterator {
@@ -130,17 +144,20 @@ pub fn serialize_feed(html: String, domain: &str) -> Result<(Vec<NyaaTorrent>, b
                 torrents.append(&mut [NyaaTorrent {
                   category: category.clone(),
                   title: html_escape::decode_html_entities(&title).to_string(),
-                  comments_amount: comments.parse::<u64>().unwrap(),
+                  release: Release::parse(&html_escape::decode_html_entities(&title)),
+                  comments_amount: comments.parse::<u64>().map_err(|e| Error::parse("comment amount", e))?,
                   magnet_link: magnet.clone(),
                   size: size.clone(),
                   id,
                   domain: domain.to_owned(),
                   uploader: None,
                   upload_date_str: date.clone(),
-                  seeders: seeders.parse::<u64>().unwrap(),
-                  leechers: leechers.parse::<u64>().unwrap(),
-                  completed: completed.parse::<u64>().unwrap(),
-                  upload_date_timestamp: timestamp.parse::<f64>().unwrap(),
+                  seeders: seeders.parse::<u64>().map_err(|e| Error::parse("seeders", e))?,
+                  leechers: leechers.parse::<u64>().map_err(|e| Error::parse("leechers", e))?,
+                  completed: completed.parse::<u64>().map_err(|e| Error::parse("completed", e))?,
+                  trusted,
+                  remake,
+                  upload_date_timestamp: timestamp.parse::<f64>().map_err(|e| Error::parse("upload timestamp", e))?,
                   comments: vec![]
                 }].to_vec());
                 category = String::new();
@@ -182,287 +199,210 @@ pub fn serialize_feed(html: String, domain: &str) -> Result<(Vec<NyaaTorrent>, b
   Ok((torrents, continuing))
 }
 
-pub fn serialize_torrent(html: &str, page_url: String, domain: &str) -> (Option<NyaaUser>, Vec<NyaaComment>) {
-  let mut short_domain = domain.to_owned();
-  short_domain.pop();
-  let lines = html.split('\n');
-  let mut worthy_text: usize = 0;
-  // Skipping trash like the menu and footer
-  for (index, line) in lines.clone().enumerate() {
-    if line.trim() == r#"<div id="comments" class="panel panel-default">"# {
-      // as shrimple as that
-      worthy_text = index;
-      break
+/// Reads the submitter and every comment from a torrent page.
+pub fn serialize_torrent(html: &str, page_url: String, domain: &str) -> Result<(NyaaUser, Vec<NyaaComment>)> {
+  let document = Html::parse_document(html);
+  let uploader = get_uploader_name(&document)?;
+  let mut comments: Vec<NyaaComment> = vec![];
+  for panel in document.select(&selector("div.comment-panel")) {
+    let id = panel.value().id().ok_or_else(|| Error::parse("comment", "Comment without an id"))?;
+    let user_link = panel.select(&selector(r#"a[href^="/user/"]"#)).next()
+      .ok_or_else(|| Error::parse("comment", format!("No user in comment {}", id)))?;
+    let role = user_link.value().attr("title").unwrap_or("User").to_string();
+    let uploader = panel.select(&selector("p")).next()
+      .is_some_and(|paragraph| text_of(paragraph).contains("(uploader)"));
+    let avatar = panel.select(&selector("img.avatar")).next()
+      .and_then(|image| image.value().attr("src"))
+      .map(|src| absolute_url(src, domain));
+
+    let mut date_timestamp: Option<f64> = None;
+    let mut edited_timestamp: Option<f64> = None;
+    for small in panel.select(&selector("small[data-timestamp]")) {
+      let timestamp = small.value().attr("data-timestamp").unwrap_or_default()
+        .parse::<f64>().map_err(|e| Error::parse("comment timestamp", e))?;
+      if text_of(small) == "(edited)" {
+        edited_timestamp = Some(timestamp);
+      } else {
+        date_timestamp = Some(timestamp);
+      }
     }
-  };
-  let mut body: Vec<String> = [].to_vec();
-  for line in lines.collect::<Vec<&str>>().split_at(worthy_text).1 {
-    body.append(&mut [line.to_string()].to_vec());
-  };
-  let mut comments: Vec<NyaaComment> = [].to_vec();
-  let mut body_iterator = body.iter();
-  let mut torrent_page_end: bool = false;
-  while let Some(line) = body_iterator.next() {
-    let x = line.trim();
-    if ! torrent_page_end && x.contains("comments") {
-      let mut username = String::new();
-      let mut user_role = String::new();
-      let mut banned = false;
-      let mut avatar = String::new();
-      let mut uploader = false;
-      let mut message = String::new();
-      let mut date_timestamp: f64 = 0.0;
-      let mut edited_timestamp: Option<f64> = None;
-      let mut direct_link: String = String::new();
-      for line in body_iterator.by_ref() {
-        let x = line.trim();
-        if x.contains("data-timestamp=") && ! x.contains(">(edited)</small>") {
-          let mut last_part = "";
-          for part in x.split('"') {
-            if last_part == "<a href=" {
-              direct_link = page_url.clone()+part;
-              break;
-            } else {
-              last_part = part;
-            }
-          }
-          let iterator = x.chars();
-          let mut date_timestamp_temp = String::new();
-          for ch in iterator {
-            date_timestamp_temp.push_str(ch.to_string().as_str());
-            if ch == '"' && date_timestamp_temp.ends_with(r#"data-timestamp=""#) {
-              date_timestamp_temp.clear()
-            } else if ch == '"' && ! date_timestamp_temp.starts_with('<') {
-              date_timestamp_temp.pop();
-              date_timestamp = date_timestamp_temp.parse::<f64>().unwrap();
-              break
-            }
-          }
-        } else if x.contains(r#">(edited)</small>"#) {
-          let mut last_part = "";
-          for part in x.split('"') {
-            if last_part.ends_with(" data-timestamp=") {
-              edited_timestamp = Some(part.parse::<f64>().unwrap());
-              break;
-            } else {
-              last_part = part;
-            }
-          }
-        } else if x.contains(r#"href="/user/"#) {
-          let characters = x.chars();
-          let mut last_part = "";
-          for part in x.split('\"') {
-            if last_part == " title=" {
-              user_role = part.to_string();
-              if user_role.contains("BANNED") {
-                banned = true;
-              }
-              break;
-            } else {
-              last_part = part;
-            }
-          }
-          for ch in characters {
-            if ch == '>' {
-              username.clear()
-            } else if ch == '<' && ! username.is_empty() {
-              break
-            } else {
-              username.push_str(ch.to_string().as_str());
-            }
-          }
-        } else if x.ends_with("(uploader)") {
-          uploader = true;
-        } else if x.starts_with(r#"<img class="avatar" src=""#) {
-          let mut last_part = "";
-          for part in x.split('"') {
-            if last_part == " src=" {
-              avatar = part.to_string();
-              break;
-            } else {
-              last_part = part;
-            }
-          }
-          if avatar.starts_with('/') {
-            avatar = short_domain.clone()+&avatar;
-          }
-        } else if x.contains(r#"comment-content"#) {
-          for part in x.split('>') {
-            if part.ends_with("</div") {
-              message = part.trim_end_matches("</div").to_string();
-              break;
-            }
-          }
-          if (message.contains("![](") || message.contains("![") && message.contains("](")) && message.contains(')') {
-            let mut remove_these: Vec<(usize, usize, usize, usize, usize)> = vec![];
-            let mut exclamation: bool = false;
-            let mut open_sq_br: bool = false;
-            let mut closed_sq_br: bool = false;
-            let mut open_ro_br: bool = false;
-            let mut values = (0, 0, 0, 0, 0);
-            
-            for (index, ch) in message.char_indices() {
-              if ch == '!' {
-                values.0 = index;
-                exclamation = true;
-              } else if ch == '[' && exclamation {
-                open_sq_br = true;
-                values.1 = index;
-              } else if ch == ']' && open_sq_br && exclamation {
-                closed_sq_br = true;
-                values.2 = index;
-              } else if ch == '(' && closed_sq_br && open_sq_br && exclamation {
-                values.3 = index;
-                open_ro_br = true;
-              } else if ch == ')' && open_ro_br {
-                exclamation = false;
-                open_sq_br = false;
-                closed_sq_br = false;
-                open_ro_br = false;
-                values.4 = index;
-                remove_these.append(&mut vec![(values)]);
-              } else if ! (open_ro_br || open_sq_br) {
-                exclamation = false;
-                open_sq_br = false;
-                closed_sq_br = false;
-                open_ro_br = false;
-              }
-            };
 
-            for (excl, start, mid, start2, end) in remove_these.iter().rev() {
-              message.remove(*end);
-              message.remove(*start2);
-              message.remove(*mid);
-              message.remove(*start);
-              message.remove(*excl);
-            }
-          }
+    let message = panel.select(&selector(".comment-content")).next()
+      .ok_or_else(|| Error::parse("comment", format!("No message in comment {}", id)))?
+    .text().collect::<String>();
 
-          comments.append(&mut [NyaaComment {
-            user: NyaaUser {
-              anonymous: false,
-              role: user_role.clone(),
-              username,
-              avatar: Some(avatar),
-              banned
-            },
-            message: html_escape::decode_html_entities(message.trim_end_matches(r#"</div"#)).to_string(),
-            old_message: None,
-            uploader,
-            date_timestamp,
-            edited_timestamp,
-            old_edited_timestamp: None,
-            direct_link: direct_link.clone(),
-            update_type: crate::web::NyaaCommentUpdateType::UNDECIDED
-          }].to_vec());
-          user_role = String::new();
-          username = String::new();
-          avatar = String::new();
-          banned = false;
-          message = String::new();
-          uploader = false;
-          date_timestamp = 0.0;
-          edited_timestamp = None;
-          direct_link = String::new();
-        }
-      };
-      torrent_page_end = true;
-    };
-  };
-  (get_uploader_name(html), comments)
+    comments.append(&mut vec![NyaaComment {
+      user: NyaaUser {
+        anonymous: false,
+        banned: role.contains("BANNED"),
+        role,
+        username: text_of(user_link),
+        avatar
+      },
+      message: strip_image_markdown(message.trim()),
+      old_message: None,
+      uploader,
+      date_timestamp: date_timestamp.ok_or_else(|| Error::parse("comment", format!("No date in comment {}", id)))?,
+      edited_timestamp,
+      old_edited_timestamp: None,
+      direct_link: format!("{}#{}", page_url, id),
+      update_type: crate::web::NyaaCommentUpdateType::UNDECIDED
+    }]);
+  }
+  Ok((uploader, comments))
 }
 
-pub fn get_uploader_name(html: &str) -> Option<NyaaUser> {
-  if ! html.starts_with("<!DOCTYPE html>") {
-    return None;
-  };
-  let lines = html.split('\n');
-  let mut uploader = String::new();
-  let mut role = String::new();
-  let mut banned = false;
-  let mut submitter_line: bool = false;
-  for line in lines {
-    if line.ends_with(r#"<div class="panel panel-success">"#) {
-      role = "Trusted".to_string();
-    } else if line.ends_with(r#"<div class="col-md-1">Submitter:</div>"#) {
-      submitter_line = true;
-      continue;
-    } else if submitter_line {
-      let mut last_part = "";
-      if line.contains(r#"href="/user/"#) {
-        for part in line.split('"') {
-          if last_part == " title=" {
-            role = part.to_string();
-            if role.contains("BANNED") {
-              banned = true;
-            }
-            last_part = ">.< -!- >~<";
-            continue;
-          } else if last_part == ">.< -!- >~<" {
-            uploader = part.trim_start_matches('>').trim_end_matches("</a>\t\t\t</div>").to_string();
-            break;
-          } else {
-            last_part = part;
-          }
-        }
-        if ! uploader.is_empty() {
-          break;
-        }
-      }
-    }
-    
-    if line.contains(r#"<div class="col-md-1">Seeders:</div>"#) {
-      return Some(NyaaUser {
-        anonymous: true,
+fn get_uploader_name(document: &Html) -> Result<NyaaUser> {
+  let submitter = document.select(&selector("div.panel-body div.row > div"))
+    .find(|label| text_of(*label) == "Submitter:")
+    .and_then(|label| label.next_siblings().find_map(ElementRef::wrap))
+    .ok_or_else(|| Error::parse("torrent page", "No submitter found"))?;
+
+  match submitter.select(&selector(r#"a[href^="/user/"]"#)).next() {
+    Some(user_link) => {
+      let role = user_link.value().attr("title").unwrap_or("User").to_string();
+      Ok(NyaaUser {
+        anonymous: false,
+        banned: role.contains("BANNED"),
         role,
+        username: text_of(user_link),
+        avatar: None
+      })
+    },
+    None if text_of(submitter) == "Anonymous" => {
+      Ok(NyaaUser {
+        anonymous: true,
+        role: "User".to_string(),
         username: "Anonymous".to_string(),
         avatar: None,
         banned: false
-      });
+      })
+    },
+    None => {
+      Err(Error::parse("torrent page", format!("Unexpected submitter {:?}", text_of(submitter))))
     }
   }
+}
 
-  Some(NyaaUser {
-    anonymous: false,
-    username: uploader,
-    role,
-    avatar: None,
-    banned,
-  })
+/// The avatar is taken from the `og:image` meta tag of a user page.
+pub fn serialize_user_page(html: &str, domain: &str) -> Result<String> {
+  let document = Html::parse_document(html);
+  document.select(&selector(r#"meta[property="og:image"]"#)).next()
+    .and_then(|meta| meta.value().attr("content"))
+    .filter(|content| !content.is_empty())
+    .map(|content| absolute_url(content, domain))
+    .ok_or_else(|| Error::parse("user page", "No avatar found"))
 }
 
-pub fn serialize_user_page(html: &str, domain: &String) -> String {
-  if ! html.starts_with(&"<!DOCTYPE html>".to_string()) {
-    return "".to_string();
-  };
-  let lines = html.split('\n');
-  let mut worthy_text = "";
-  
-  for line in lines.clone() {
-    if line.contains(r#"<meta property="og:image" content=""#) {
-      worthy_text = line;
-      break
-    } else if line.trim() == "</html>" {
-      return "".to_string();
-    }
-  };
-  
-  let mut avatar: String = String::new();
-  let mut delim = 0;
-  for ch in worthy_text.chars() {
-    if ch == '"' {
-      if delim == 3 {
-        continue;
-      }
-      delim += 1;
-      continue;
-    }
-    if delim == 3 && ch != '>' {
-      avatar.push(ch);
-    }
+fn selector(css: &str) -> Selector {
+  Selector::parse(css).expect("invalid css selector")
+}
+
+fn text_of(element: ElementRef) -> String {
+  element.text().collect::<String>().trim().to_string()
+}
+
+fn absolute_url(url: &str, domain: &str) -> String {
+  if url.starts_with('/') {
+    domain.trim_end_matches('/').to_string()+url
+  } else {
+    url.to_string()
   }
-  if avatar.starts_with('/') {
-    avatar = domain.to_owned()+&avatar;
+}
+
+// Images can't be displayed by most of the notification services, so `![alt](link)` is reduced to `alt link`.
+fn strip_image_markdown(message: &str) -> String {
+  let image = regex::Regex::new(r"!\[([^\]]*)\]\(([^)]*)\)").unwrap();
+  image.replace_all(message, |captures: &regex::Captures| {
+    [&captures[1], &captures[2]].iter().filter(|part| !part.is_empty()).cloned().collect::<Vec<&str>>().join(" ")
+  }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::web::NyaaCommentUpdateType;
+
+  const DOMAIN: &str = "https://nyaa.si/";
+  const LISTING: &str = include_str!("../tests/fixtures/listing.html");
+  const VIEW_COMMENTS: &str = include_str!("../tests/fixtures/view_comments.html");
+  const VIEW_ANONYMOUS: &str = include_str!("../tests/fixtures/view_anonymous.html");
+  const USER: &str = include_str!("../tests/fixtures/user.html");
+
+  #[test]
+  fn listing_row_status() {
+    let (torrents, _) = serialize_feed(LISTING.to_string(), DOMAIN).unwrap();
+    let status: Vec<(u64, bool, bool)> = torrents.iter().map(|torrent| (torrent.id, torrent.trusted, torrent.remake)).collect();
+    assert_eq!(status, vec![(1720002, true, false), (1720000, true, false), (1720001, false, true)]);
+  }
+
+  #[test]
+  fn torrent_page_uploader() {
+    let (uploader, _) = serialize_torrent(VIEW_COMMENTS, format!("{}view/1720000", DOMAIN), DOMAIN).unwrap();
+    assert_eq!(uploader, NyaaUser {
+      anonymous: false,
+      role: "Trusted".to_string(),
+      username: "subsplease".to_string(),
+      avatar: None,
+      banned: false
+    });
+  }
+
+  #[test]
+  fn torrent_page_comments() {
+    let (_, comments) = serialize_torrent(VIEW_COMMENTS, format!("{}view/1720000", DOMAIN), DOMAIN).unwrap();
+    assert_eq!(comments.len(), 3);
+
+    assert_eq!(comments[0].user.username, "himmel");
+    assert_eq!(comments[0].user.role, "User");
+    assert_eq!(comments[0].user.avatar.as_deref(), Some("https://nyaa.si/static/img/avatar/default.png"));
+    assert_eq!(comments[0].message, "Thanks for the release & the quick upload!");
+    assert_eq!(comments[0].date_timestamp, 1695964000.0);
+    assert_eq!(comments[0].edited_timestamp, None);
+    assert_eq!(comments[0].direct_link, "https://nyaa.si/view/1720000#com-1");
+    assert_eq!(comments[0].update_type, NyaaCommentUpdateType::UNDECIDED);
+    assert!(!comments[0].uploader);
+
+    assert!(comments[1].uploader);
+    assert_eq!(comments[1].user.avatar.as_deref(), Some("https://i.imgur.com/subsplease.png"));
+    assert_eq!(comments[1].message, "Batch is coming soon https://i.imgur.com/frieren.png");
+    assert_eq!(comments[1].date_timestamp, 1695965000.0);
+    assert_eq!(comments[1].edited_timestamp, Some(1695966000.0));
+
+    assert!(comments[2].user.banned);
+    assert_eq!(comments[2].user.role, "BANNED User");
+  }
+
+  #[test]
+  fn anonymous_torrent_page() {
+    let (uploader, comments) = serialize_torrent(VIEW_ANONYMOUS, format!("{}view/1730000", DOMAIN), DOMAIN).unwrap();
+    assert!(uploader.anonymous);
+    assert_eq!(uploader.username, "Anonymous");
+    assert!(comments.is_empty());
+  }
+
+  #[test]
+  fn torrent_page_without_submitter() {
+    let error = serialize_torrent(USER, format!("{}view/1", DOMAIN), DOMAIN).unwrap_err();
+    assert!(matches!(error, Error::Parse { .. }));
+  }
+
+  #[test]
+  fn user_page_avatar() {
+    assert_eq!(serialize_user_page(USER, DOMAIN).unwrap(), "https://i.imgur.com/subsplease.png");
+    let relative = USER.replace("https://i.imgur.com/subsplease.png\">\n\t\t<link", "/static/img/avatar/default.png\">\n\t\t<link");
+    assert_eq!(serialize_user_page(&relative, DOMAIN).unwrap(), "https://nyaa.si/static/img/avatar/default.png");
+  }
+
+  #[test]
+  fn user_page_without_avatar() {
+    let error = serialize_user_page("<!DOCTYPE html><html><head></head><body></body></html>", DOMAIN).unwrap_err();
+    assert!(matches!(error, Error::Parse { .. }));
+  }
+
+  #[test]
+  fn image_markdown() {
+    assert_eq!(strip_image_markdown("look ![](https://a/b.png)"), "look https://a/b.png");
+    assert_eq!(strip_image_markdown("![cat](https://a/c.png) and [link](https://a)"), "cat https://a/c.png and [link](https://a)");
   }
-  avatar
 }
@@ -0,0 +1,68 @@
+use std::{
+  path::{Path, PathBuf}, sync::OnceLock
+};
+
+mod commands;
+pub mod api;
+pub mod config;
+pub mod notifications;
+pub mod notifiers;
+pub mod database;
+pub mod web;
+pub mod discord;
+pub mod html;
+pub mod rss;
+pub mod atom;
+pub mod error;
+pub mod filter;
+pub mod release;
+pub mod limiter;
+pub mod logging;
+pub mod metrics;
+pub mod scheduler;
+pub mod series;
+pub mod cache;
+pub mod transport;
+
+pub const DEFAULT_DATA_DIR: &str = "./nyaa_notifications";
+
+static PATHS: OnceLock<Paths> = OnceLock::new();
+
+/// Where the configuration, database, page cache and default atom feed are kept.
+#[derive(Debug, Clone)]
+pub struct Paths {
+  pub data_dir: PathBuf,
+  pub config: PathBuf
+}
+
+impl Paths {
+  /// The configuration defaults to `config.toml` inside the data directory.
+  pub fn new(data_dir: &Path, config: Option<&Path>) -> Self {
+    Paths {
+      data_dir: data_dir.to_path_buf(),
+      config: config.map(Path::to_path_buf).unwrap_or(data_dir.join("config.toml"))
+    }
+  }
+
+  pub fn database(&self) -> PathBuf {
+    self.data_dir.join("nyaa-notifications.sqlite")
+  }
+
+  pub fn cache(&self) -> PathBuf {
+    self.data_dir.join("cache")
+  }
+
+  pub fn atom_feed(&self) -> PathBuf {
+    self.data_dir.join("feed.atom")
+  }
+}
+
+/// Has to happen before anything reads `paths()`, later calls are ignored.
+pub fn set_paths(paths: Paths) {
+  let _ = PATHS.set(paths);
+}
+
+/// The paths given on the command line, or everything in `./nyaa_notifications`.
+pub fn paths() -> &'static Paths {
+  PATHS.get_or_init(|| Paths::new(Path::new(DEFAULT_DATA_DIR), None))
+}
@@ -0,0 +1,127 @@
+use std::time::Duration;
+use rand::Rng;
+use tokio::{
+  sync::Mutex, time::Instant
+};
+
+/// Token bucket shared by every task that talks to the same host.
+/// Waiting callers are served in order, because the lock is held while sleeping for the next token.
+pub struct RateLimiter {
+  bucket: Mutex<Bucket>
+}
+
+struct Bucket {
+  tokens: f64,
+  capacity: f64,
+  per_second: f64,
+  last_refill: Instant
+}
+
+impl Bucket {
+  fn refill(&mut self) {
+    let now = Instant::now();
+    self.tokens = (self.tokens + now.duration_since(self.last_refill).as_secs_f64() * self.per_second).min(self.capacity);
+    self.last_refill = now;
+  }
+}
+
+impl RateLimiter {
+  pub fn new(requests_per_minute: u32, capacity: u32) -> Self {
+    let capacity = capacity.max(1) as f64;
+    RateLimiter {
+      bucket: Mutex::new(Bucket {
+        tokens: capacity,
+        capacity,
+        per_second: requests_per_minute.max(1) as f64 / 60.0,
+        last_refill: Instant::now()
+      })
+    }
+  }
+
+  pub async fn set_rate(&self, requests_per_minute: u32) {
+    let mut bucket = self.bucket.lock().await;
+    bucket.refill();
+    bucket.per_second = requests_per_minute.max(1) as f64 / 60.0;
+  }
+
+  pub async fn acquire(&self) {
+    let mut bucket = self.bucket.lock().await;
+    bucket.refill();
+    if bucket.tokens < 1.0 {
+      let missing = 1.0 - bucket.tokens;
+      tokio::time::sleep(Duration::from_secs_f64(missing / bucket.per_second)).await;
+      bucket.refill();
+    }
+    bucket.tokens -= 1.0;
+  }
+}
+
+/// Exponential backoff with bounded jitter: a random delay between `base` and `base * 2^attempt`, capped at `max`.
+pub fn backoff(attempt: u32, base: Duration, max: Duration) -> Duration {
+  let ceiling = base.saturating_mul(2u32.saturating_pow(attempt)).min(max);
+  if ceiling <= base {
+    return ceiling;
+  }
+  rand::thread_rng().gen_range(base..=ceiling)
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+  let value = value.trim();
+  if let Ok(seconds) = value.parse::<u64>() {
+    return Some(Duration::from_secs(seconds));
+  }
+  let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+  (date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn backoff_stays_within_its_bounds() {
+    let (base, max) = (Duration::from_secs(1), Duration::from_secs(30));
+    assert_eq!(backoff(0, base, max), base);
+    for attempt in 1..4 {
+      for _ in 0..100 {
+        let delay = backoff(attempt, base, max);
+        assert!(delay >= base && delay <= base * 2u32.pow(attempt), "{:?} after {} attempts", delay, attempt);
+      }
+    }
+    for _ in 0..100 {
+      assert!(backoff(10, base, max) <= max);
+    }
+    assert_eq!(backoff(u32::MAX, max, max), max);
+  }
+
+  #[test]
+  fn retry_after_in_seconds_and_as_a_date() {
+    assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+    let later = (chrono::Utc::now() + chrono::Duration::seconds(90)).format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+    let delay = parse_retry_after(&later).unwrap();
+    assert!(delay > Duration::from_secs(85) && delay <= Duration::from_secs(90), "{:?}", delay);
+    assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), None);
+    assert_eq!(parse_retry_after("soon"), None);
+  }
+
+  #[test]
+  fn buckets_refill_up_to_their_capacity() {
+    let mut bucket = Bucket { tokens: 0.0, capacity: 3.0, per_second: 1.0, last_refill: Instant::now() - Duration::from_secs(2) };
+    bucket.refill();
+    assert!(bucket.tokens >= 2.0 && bucket.tokens < 2.5, "{}", bucket.tokens);
+    bucket.last_refill = Instant::now() - Duration::from_secs(60);
+    bucket.refill();
+    assert_eq!(bucket.tokens, 3.0);
+  }
+
+  #[tokio::test]
+  async fn acquire_waits_for_the_next_token() {
+    let limiter = RateLimiter::new(600, 1);
+    let start = Instant::now();
+    limiter.acquire().await;
+    assert!(start.elapsed() < Duration::from_millis(50));
+    limiter.acquire().await;
+    assert!(start.elapsed() >= Duration::from_millis(90), "{:?}", start.elapsed());
+  }
+}
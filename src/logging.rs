@@ -0,0 +1,60 @@
+use std::str::FromStr;
+use tracing_subscriber::EnvFilter;
+
+use crate::error::{
+  Error, Result
+};
+
+/// Used when `RUST_LOG` isn't set: our own messages, and only the warnings of the libraries.
+const DEFAULT_FILTER: &str = "warn,nyaa_notifications=info";
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LogFormat {
+  /// One line per message, prefixed with the module and feed it's about.
+  #[default]
+  Human,
+  /// One json object per line, with the spans and fields as keys.
+  Json
+}
+
+impl FromStr for LogFormat {
+  type Err = Error;
+
+  fn from_str(format: &str) -> Result<Self> {
+    match format.to_lowercase().as_str() {
+      "human" | "text" => Ok(LogFormat::Human),
+      "json" => Ok(LogFormat::Json),
+      _ => Err(Error::Config(format!("Unknown log format {:?}, it can be \"human\" or \"json\"", format)))
+    }
+  }
+}
+
+/// Sends everything logged, including the `log` records of the libraries, to stderr.
+/// `RUST_LOG` works as it did with env_logger.
+pub fn init(format: LogFormat) {
+  let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+  let builder = tracing_subscriber::fmt()
+    .with_env_filter(filter)
+    .with_writer(std::io::stderr)
+    .with_target(false);
+  let result = match format {
+    LogFormat::Human => builder.try_init(),
+    LogFormat::Json => builder.json().with_current_span(true).with_span_list(true).try_init()
+  };
+  if let Err(e) = result {
+    eprintln!("Failed to set up logging: {}", e);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn log_formats_are_parsed() {
+    assert_eq!("human".parse::<LogFormat>().unwrap(), LogFormat::Human);
+    assert_eq!("JSON".parse::<LogFormat>().unwrap(), LogFormat::Json);
+    assert!(matches!("yaml".parse::<LogFormat>(), Err(Error::Config(_))));
+  }
+}
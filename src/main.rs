@@ -1,90 +1,230 @@
-use database::Database;
-use lazy_static::lazy_static;
-use log::debug;
+use clap::{
+  Parser, Subcommand
+};
 use std::{
-  process::ExitCode, thread, time::Duration
+  path::PathBuf, process::ExitCode
+};
+use tracing::{
+  debug, error, info
 };
 
-use web::Web;
-use config::{
-  Config, ModuleType
+use nyaa_notifications::api::{
+  self, ApiState
+};
+use nyaa_notifications::database::Database;
+use nyaa_notifications::web::{
+  NYAA_LIMITER, DEFAULT_NYAA_REQUESTS_PER_MINUTE
+};
+use nyaa_notifications::cache::{
+  PageCache, CACHE_RETENTION
+};
+use nyaa_notifications::config::{
+  Config, ModuleConfig
+};
+use nyaa_notifications::error::{
+  Error, Result
+};
+use nyaa_notifications::logging::{
+  self, LogFormat
+};
+use nyaa_notifications::notifications::Notifications;
+use nyaa_notifications::scheduler::{
+  configured_modules, module_key, Scheduler
+};
+use nyaa_notifications::{
+  paths, set_paths, Paths, DEFAULT_DATA_DIR
 };
-use notifications::Notifications;
 
-mod commands;
-pub mod config;
-pub mod notifications;
-pub mod database;
-pub mod web;
-pub mod discord;
-pub mod html;
+/// Monitors uploads and their comments on nyaa and sends notifications about them.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+  /// The configuration file [default: <DATA_DIR>/config.toml]
+  #[arg(long, global = true, env = "NYAA_CONFIG")]
+  config: Option<PathBuf>,
+  /// Where the database, the page cache and the default atom feed are kept
+  #[arg(long, global = true, env = "NYAA_DATA_DIR", default_value = DEFAULT_DATA_DIR)]
+  data_dir: PathBuf,
+  /// `human` or `json`
+  #[arg(long, global = true, env = "NYAA_LOG_FORMAT", default_value = "human")]
+  log_format: LogFormat,
+  #[command(subcommand)]
+  command: Option<Command>
+}
 
-lazy_static! {
-  static ref NYAA_FOLDER_PATH: &'static str = "./nyaa_notifications";
-  static ref NYAA_CONFIG_PATH: String = format!("{}/config.toml", *NYAA_FOLDER_PATH);
-  static ref NYAA_DATABASE_PATH: String = format!("{}/nyaa-notifications.sqlite", *NYAA_FOLDER_PATH);
+#[derive(Subcommand)]
+enum Command {
+  /// Polls every module until stopped, with the api if it's configured (the default)
+  Run,
+  /// Polls every module without the api
+  Check {
+    /// Checks every module a single time and exits, failing if one of the checks failed
+    #[arg(long)]
+    once: bool
+  },
+  /// Reads the configuration and reports what's wrong with it
+  ValidateConfig,
+  /// Shows every module and discord channel with the key the other commands take
+  ListModules,
+  /// Forgets what has been stored for a module, its feeds are then indexed again without notifying
+  ResetModule {
+    /// The module's key, like `Gotify_0`
+    id: String
+  },
+  /// Writes everything stored in the database as json
+  Export {
+    /// Written to stdout without one
+    file: Option<PathBuf>
+  },
+  /// Replaces everything stored in the database with an export
+  Import {
+    file: PathBuf
+  },
+  /// Sends a made-up upload and comment through a module's backend
+  TestNotify {
+    /// The module's key, like `Gotify_0`
+    module: String
+  }
 }
 
 #[tokio::main]
 async fn main() -> ExitCode {
-  env_logger::init();
+  let cli = Cli::parse();
+  logging::init(cli.log_format);
+  set_paths(Paths::new(&cli.data_dir, cli.config.as_deref()));
+  match run(cli.command.unwrap_or(Command::Run)).await {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(e) => {
+      error!("{}", e);
+      e.exit_code()
+    }
+  }
+}
+
+async fn run(command: Command) -> Result<()> {
+  match command {
+    Command::Run => poll(true).await,
+    Command::Check { once: false } => poll(false).await,
+    Command::Check { once: true } => check_once().await,
+    Command::ValidateConfig => {
+      let config = Config::new()?;
+      println!("{} is valid, {} of its {} modules are active.",
+        paths().config.display(), config.module.iter().filter(|module| module.active).count(), config.module.len());
+      Ok(())
+    },
+    Command::ListModules => {
+      let config = configure().await?;
+      let mut database = Database::new().await?;
+      for (id, module) in configured_modules(&config.module, &mut database).await? {
+        let feeds: Vec<&str> = module.feeds.iter().flatten().map(|feed| feed.url()).collect();
+        println!("{:<40} {:<8} every {:>4} min  {}", module_key(&module.module_type, &id), if module.active { "active" } else { "inactive" },
+          module.update_interval.unwrap_or(config.update_interval), feeds.join(" "));
+      }
+      Ok(())
+    },
+    Command::ResetModule { id: key } => {
+      // the key is the subscription's, so the configuration isn't needed to find it
+      let (module_type, id) = key.split_once('_')
+        .ok_or_else(|| Error::Config(format!("{:?} isn't a module key like \"Gotify_0\".", key)))?;
+      let mut database = Database::new().await?;
+      if !database.reset_subscription(module_type.to_string(), &id.to_string()).await? {
+        return Err(Error::Config(format!("Nothing is stored for {:?}, list-modules shows which modules there are.", key)));
+      }
+      println!("{} has been reset, its feeds will be indexed again on the next check.", key);
+      Ok(())
+    },
+    Command::Export { file } => {
+      let mut database = Database::new().await?;
+      let export = serde_json::to_string_pretty(&database.export().await?).map_err(|e| Error::parse("database export", e))?;
+      match file {
+        Some(file) => std::fs::write(&file, export).map_err(|e| Error::io(&format!("writing {}", file.display()), e)),
+        None => {
+          println!("{}", export);
+          Ok(())
+        }
+      }
+    },
+    Command::Import { file } => {
+      let export = std::fs::read_to_string(&file).map_err(|e| Error::io(&format!("reading {}", file.display()), e))?;
+      let export: serde_json::Value = serde_json::from_str(&export).map_err(|e| Error::parse("database export", e))?;
+      let mut database = Database::new().await?;
+      database.import(&export).await?;
+      println!("{} has been imported.", file.display());
+      Ok(())
+    },
+    Command::TestNotify { module: key } => {
+      let config = configure().await?;
+      let mut database = Database::new().await?;
+      let (id, module) = find_module(&config, &mut database, &key).await?;
+      let notifications = Notifications::new(config.module.clone(), &mut database).await?;
+      let mut failure = None;
+      for (event, result) in ["upload", "comment"].into_iter().zip(notifications.test_notify(&module, &id, &mut database).await?) {
+        match result {
+          Ok(()) => println!("Sent a test {} through {}.", event, key),
+          Err(e) => {
+            println!("Failed to send a test {} through {}: {}", event, key, e);
+            failure = failure.or(Some(e));
+          }
+        }
+      }
+      failure.map_or(Ok(()), Err)
+    }
+  }
+}
+
+/// The configuration, with the settings applied which every command reading it needs.
+/// Commands which only work on the database leave it alone, as reading it writes a template when there's none.
+async fn configure() -> Result<Config> {
   debug!("Reading configuration.");
-  let config_res = Config::new();
-  let mut config: Config;
-  if config_res.is_err() {
-    return ExitCode::FAILURE;
-  } else {
-    config = config_res.unwrap();
-  };
+  let config = Config::new()?;
+  NYAA_LIMITER.set_rate(config.nyaa_requests_per_minute.unwrap_or(DEFAULT_NYAA_REQUESTS_PER_MINUTE)).await;
+  Ok(config)
+}
 
+async fn poll(with_api: bool) -> Result<()> {
+  let config = configure().await?;
   debug!("Generating and opening database.");
-  let database_res = Database::new();
-  let mut database: Database;
-  if let Ok(database_) = database_res.await {
-    database = database_;
-  } else {
-    return ExitCode::FAILURE;
-  }
+  let mut database = Database::new().await?;
 
-  debug!("Initializing notifications class.");
-  let notifications_res = Notifications::new(config.module.clone(), &mut database).await;
-  let mut notifications: Notifications;
-  if let Ok(notifications_) = notifications_res {
-    notifications = notifications_;
-  } else {
-    return ExitCode::FAILURE;
-  }
+  debug!("Cleaning up the page cache.");
+  PageCache::new(paths().cache()).prune(CACHE_RETENTION).await;
 
-  loop {
-    let mut web = Web::default();
-    println!("Checking at: {}", chrono::Local::now());
+  debug!("Initializing notifications class.");
+  let notifications = Notifications::new(config.module.clone(), &mut database).await?;
 
-    debug!("Refreshing discord modules.");
-    for module in config.module.clone() {
-      if module.active && module.discord_token.is_some() && (module.module_type == ModuleType::Discord) {
-        config.refresh_discord_modules(&mut database, module.discord_bot_id.unwrap()).await;
-        break;
-      }
-    }
+  let scheduler = Scheduler::new(&config, notifications, &database);
+  if let Some(address) = config.api_address.as_ref().filter(|_| with_api) {
+    let listener = api::bind(address).await?;
+    info!("The api is listening on {}.", address);
+    tokio::spawn(api::serve(listener, ApiState {
+      modules: scheduler.modules(),
+      database_pool: database.database.clone(),
+      token: config.api_token.clone()
+    }));
+  }
+  scheduler.run(config.module.clone()).await
+}
 
-    for (index, module) in config.module.iter().enumerate() {
-      if module.active && module.discord_token.is_none() {
-        let id = if module.module_type == ModuleType::Discord {
-          module.discord_bot_id.clone().unwrap()+"_"+&module.discord_channel_id.unwrap().to_string()
-        } else {
-          index.to_string()
-        };
-        debug!("Getting updates from nyaa.");
-        let mut updates = web.get_updates(module, &id, &mut database).await;
-        updates.reverse();
-        debug!("Sending updates:\n{:?}", updates);
-        for update in notifications.process_updates(module, &mut database, updates).await {
-          database.update_db_table(module.module_type.to_string(), &id, update).await;
-        }
+async fn check_once() -> Result<()> {
+  let config = configure().await?;
+  let mut database = Database::new().await?;
+  let notifications = Notifications::new(config.module.clone(), &mut database).await?;
+  let results = Scheduler::new(&config, notifications, &database).check_once(config.module.clone()).await?;
+  let mut failure = None;
+  for (key, result) in results {
+    match result {
+      Ok(()) => println!("{}: checked", key),
+      Err(e) => {
+        println!("{}: {}", key, e);
+        failure = failure.or(Some(e));
       }
     }
-
-    println!("Waiting {} minutes...", config.clone().update_interval);
-    thread::sleep(Duration::from_secs(config.clone().update_interval * 60));
   }
+  failure.map_or(Ok(()), Err)
+}
+
+async fn find_module(config: &Config, database: &mut Database, key: &str) -> Result<(String, ModuleConfig)> {
+  configured_modules(&config.module, database).await?.into_iter()
+    .find(|(id, module)| module_key(&module.module_type, id) == key)
+    .ok_or_else(|| Error::Config(format!("There is no module {:?}, list-modules shows which there are.", key)))
 }
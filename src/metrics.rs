@@ -0,0 +1,153 @@
+use lazy_static::lazy_static;
+use std::{
+  collections::BTreeMap, fmt::Write, sync::Mutex
+};
+
+lazy_static! {
+  pub static ref NYAA_REQUESTS: Counter = Counter::new("nyaa_requests_total", "Requests to nyaa by status code, `error` if there was no answer.", &["status"]);
+  pub static ref NYAA_RETRIES: Counter = Counter::new("nyaa_request_retries_total", "Requests to nyaa which were tried again.", &[]);
+  pub static ref PARSE_FAILURES: Counter = Counter::new("nyaa_parse_failures_total", "Pages which couldn't be parsed, by parser.", &["parser"]);
+  pub static ref UPDATES_DETECTED: Counter = Counter::new("nyaa_updates_detected_total", "New uploads (UPLOAD) and comment changes found while polling.", &["type"]);
+  pub static ref DELIVERIES: Counter = Counter::new("nyaa_deliveries_total", "Events sent to a notification backend, by outcome.", &["backend", "outcome"]);
+  pub static ref DISCORD_CHANNELS_PAUSED: Counter = Counter::new("nyaa_discord_channels_paused_total", "Discord channels paused because the bot couldn't reach them.", &[]);
+  pub static ref POLL_DURATION: Histogram = Histogram::new("nyaa_poll_duration_seconds", "How long a module's check took, from fetching to storing.", &["module_type"],
+    &[1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1200.0, 1800.0]);
+}
+
+/// Everything above in the prometheus text format.
+pub fn render() -> String {
+  let mut text = String::new();
+  for counter in [&*NYAA_REQUESTS, &*NYAA_RETRIES, &*PARSE_FAILURES, &*UPDATES_DETECTED, &*DELIVERIES, &*DISCORD_CHANNELS_PAUSED] {
+    counter.render(&mut text);
+  }
+  POLL_DURATION.render(&mut text);
+  text
+}
+
+pub struct Counter {
+  name: &'static str,
+  help: &'static str,
+  labels: &'static [&'static str],
+  values: Mutex<BTreeMap<Vec<String>, u64>>
+}
+
+impl Counter {
+  fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+    let mut values = BTreeMap::new();
+    // counters without labels are shown from the start, the others once they have a value
+    if labels.is_empty() {
+      values.insert(vec![], 0);
+    }
+    Counter { name, help, labels, values: Mutex::new(values) }
+  }
+
+  /// Takes one value per label, in the order they were declared in.
+  pub fn inc(&self, label_values: &[&str]) {
+    self.add(label_values, 1);
+  }
+
+  pub fn add(&self, label_values: &[&str], amount: u64) {
+    let key = label_values.iter().map(|value| value.to_string()).collect();
+    *self.values.lock().unwrap().entry(key).or_default() += amount;
+  }
+
+  fn render(&self, text: &mut String) {
+    let _ = writeln!(text, "# HELP {} {}\n# TYPE {} counter", self.name, self.help, self.name);
+    for (label_values, value) in self.values.lock().unwrap().iter() {
+      let _ = writeln!(text, "{}{} {}", self.name, label_set(self.labels, label_values, None), value);
+    }
+  }
+}
+
+pub struct Histogram {
+  name: &'static str,
+  help: &'static str,
+  labels: &'static [&'static str],
+  buckets: &'static [f64],
+  values: Mutex<BTreeMap<Vec<String>, Observations>>
+}
+
+#[derive(Default)]
+struct Observations {
+  /// Per bucket, not yet cumulative.
+  buckets: Vec<u64>,
+  sum: f64,
+  count: u64
+}
+
+impl Histogram {
+  fn new(name: &'static str, help: &'static str, labels: &'static [&'static str], buckets: &'static [f64]) -> Self {
+    Histogram { name, help, labels, buckets, values: Mutex::default() }
+  }
+
+  pub fn observe(&self, label_values: &[&str], value: f64) {
+    let key = label_values.iter().map(|value| value.to_string()).collect();
+    let mut values = self.values.lock().unwrap();
+    let observations = values.entry(key).or_insert_with(|| Observations { buckets: vec![0; self.buckets.len()], ..Default::default() });
+    if let Some(bucket) = self.buckets.iter().position(|bound| value <= *bound) {
+      observations.buckets[bucket] += 1;
+    }
+    observations.sum += value;
+    observations.count += 1;
+  }
+
+  fn render(&self, text: &mut String) {
+    let _ = writeln!(text, "# HELP {} {}\n# TYPE {} histogram", self.name, self.help, self.name);
+    for (label_values, observations) in self.values.lock().unwrap().iter() {
+      let mut cumulative = 0;
+      for (bound, count) in self.buckets.iter().zip(&observations.buckets) {
+        cumulative += count;
+        let _ = writeln!(text, "{}_bucket{} {}", self.name, label_set(self.labels, label_values, Some(&bound.to_string())), cumulative);
+      }
+      let _ = writeln!(text, "{}_bucket{} {}", self.name, label_set(self.labels, label_values, Some("+Inf")), observations.count);
+      let _ = writeln!(text, "{}_sum{} {}", self.name, label_set(self.labels, label_values, None), observations.sum);
+      let _ = writeln!(text, "{}_count{} {}", self.name, label_set(self.labels, label_values, None), observations.count);
+    }
+  }
+}
+
+/// `{name="value",...}`, with the histogram's `le` last. Empty if there's nothing to put in.
+fn label_set(labels: &[&str], values: &[String], le: Option<&str>) -> String {
+  let mut pairs: Vec<String> = labels.iter().zip(values)
+    .map(|(label, value)| format!("{}=\"{}\"", label, value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")))
+    .collect();
+  if let Some(le) = le {
+    pairs.push(format!("le=\"{}\"", le));
+  }
+  if pairs.is_empty() {
+    String::new()
+  } else {
+    format!("{{{}}}", pairs.join(","))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn counters_and_histograms_render_as_prometheus_text() {
+    let counter = Counter::new("test_total", "A test.", &["backend", "outcome"]);
+    counter.inc(&["Gotify", "success"]);
+    counter.add(&["Gotify", "success"], 2);
+    counter.inc(&["Email", "fail\"ure"]);
+    let mut text = String::new();
+    counter.render(&mut text);
+    assert_eq!(text, "# HELP test_total A test.\n# TYPE test_total counter\n\
+      test_total{backend=\"Email\",outcome=\"fail\\\"ure\"} 1\n\
+      test_total{backend=\"Gotify\",outcome=\"success\"} 3\n");
+
+    let histogram = Histogram::new("test_seconds", "Durations.", &[], &[1.0, 10.0]);
+    histogram.observe(&[], 0.5);
+    histogram.observe(&[], 5.0);
+    histogram.observe(&[], 50.0);
+    let mut text = String::new();
+    histogram.render(&mut text);
+    assert_eq!(text, "# HELP test_seconds Durations.\n# TYPE test_seconds histogram\n\
+      test_seconds_bucket{le=\"1\"} 1\n\
+      test_seconds_bucket{le=\"10\"} 2\n\
+      test_seconds_bucket{le=\"+Inf\"} 3\n\
+      test_seconds_sum 55.5\n\
+      test_seconds_count 3\n");
+  }
+}
@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use tracing::warn;
+
+use crate::paths;
+use crate::atom::{
+  atom_feed, HistoryEvent
+};
+use crate::config::{
+  ModuleConfig, ModuleType
+};
+use crate::database::Database;
+use crate::notifiers::{
+  Event, Notifier
+};
+use crate::rss::percent_encode;
+use crate::web::NyaaTorrent;
+use crate::error::Result;
+
+const DEFAULT_MAX_ENTRIES: u32 = 100;
+
+/// Keeps a history of the module's events in the database and writes it out as an atom feed.
+/// An event counts as delivered once it's in the history, a file that couldn't be written is caught up on the next write.
+#[derive(Default)]
+pub struct AtomNotifier {}
+
+impl AtomNotifier {
+  pub fn new() -> Self {
+    AtomNotifier {}
+  }
+}
+
+/// Where the module's feed is written.
+pub fn atom_path(module: &ModuleConfig) -> String {
+  module.atom_path.clone().unwrap_or(paths().atom_feed().to_string_lossy().to_string())
+}
+
+/// Renders the module's history as it's written to its file.
+pub async fn render_atom_feed(module: &ModuleConfig, id: &str, database: &mut Database) -> Result<String> {
+  let path = atom_path(module);
+  let events = database.get_history_events(ModuleType::Atom.to_string(), &id.to_string()).await?;
+  let title = module.atom_title.clone().unwrap_or("Nyaa notifications".to_string());
+  Ok(atom_feed(&title, &format!("urn:nyaa-notifications:{}", percent_encode(&path)), &events))
+}
+
+async fn write_feed(module: &ModuleConfig, id: &str, database: &mut Database) -> Result<()> {
+  let path = atom_path(module);
+  let feed = render_atom_feed(module, id, database).await?;
+  // written next to the real file first, so that a reader never gets half a feed
+  let temporary = format!("{}.tmp", path);
+  if let Err(e) = async {
+    tokio::fs::write(&temporary, feed).await?;
+    tokio::fs::rename(&temporary, &path).await
+  }.await {
+    warn!("Failed to write the atom feed {:?}: {}", path, e);
+  }
+  Ok(())
+}
+
+#[async_trait]
+impl Notifier for AtomNotifier {
+  async fn send(&self, module: &ModuleConfig, id: &str, database: &mut Database, torrent: &NyaaTorrent, event: &Event<'_>) -> Result<()> {
+    self.send_all(module, id, database, torrent, std::slice::from_ref(event)).await.remove(0)
+  }
+
+  fn keeps_history(&self) -> bool {
+    true
+  }
+
+  /// The file is only rewritten once per torrent.
+  async fn send_all(&self, module: &ModuleConfig, id: &str, database: &mut Database, torrent: &NyaaTorrent, events: &[Event<'_>]) -> Vec<Result<()>> {
+    let keep = module.atom_max_entries.unwrap_or(DEFAULT_MAX_ENTRIES);
+    let mut results = vec![];
+    for event in events {
+      results.push(database.add_history_event(ModuleType::Atom.to_string(), &id.to_string(), &HistoryEvent::new(torrent, event), keep).await);
+    }
+    if results.iter().any(|result| result.is_ok()) {
+      if let Err(e) = write_feed(module, id, database).await {
+        warn!("{}", e);
+      }
+    }
+    results
+  }
+}
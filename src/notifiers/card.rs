@@ -0,0 +1,94 @@
+use chrono::{
+  DateTime, Utc
+};
+
+use crate::discord::unix_to_datetime;
+use crate::notifiers::Event;
+use crate::web::NyaaTorrent;
+
+/// The layout of the discord embeds: a title, the avatar as thumbnail, some fields, a timestamp and two link buttons.
+/// Services with similar messages (slack, pushover) render the same card, so they all show the same things.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Card {
+  pub title: String,
+  /// Empty if there's no avatar.
+  pub thumbnail: String,
+  /// `(name, value, inline)`
+  pub fields: Vec<(String, String, bool)>,
+  pub timestamp: DateTime<Utc>,
+  pub buttons: [Button; 2]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Button {
+  pub label: String,
+  pub url: String,
+  pub emoji: &'static str
+}
+
+impl Button {
+  fn new(label: &str, url: String, emoji: &'static str) -> Self {
+    Button { label: label.to_string(), url, emoji }
+  }
+}
+
+impl Card {
+  pub fn new(torrent: &NyaaTorrent, event: &Event<'_>) -> Self {
+    let title = torrent.title.clone();
+    let view_url = format!("{}view/{}", torrent.domain, torrent.id);
+    match event {
+      Event::Upload => Card {
+        title,
+        thumbnail: torrent.uploader.clone().and_then(|uploader| uploader.avatar).unwrap_or_default(),
+        fields: [("Category".to_string(), torrent.category.clone(), true), ("Size".to_string(), torrent.size.clone(), true)].into_iter()
+          .chain(torrent.release.summary().map(|summary| ("Release".to_string(), summary, true)))
+        .collect(),
+        timestamp: unix_to_datetime(torrent.upload_date_timestamp),
+        buttons: [
+          Button::new("Nyaa.si", view_url, "📰"),
+          Button::new("Torrent-File", format!("{}download/{}.torrent", torrent.domain, torrent.id), "📁")
+        ]
+      },
+      Event::SeriesAlert(alert) => Card {
+        title,
+        thumbnail: torrent.uploader.clone().and_then(|uploader| uploader.avatar).unwrap_or_default(),
+        fields: vec![("Series alert".to_string(), alert.message(), false)],
+        timestamp: unix_to_datetime(torrent.upload_date_timestamp),
+        buttons: [
+          Button::new("Nyaa.si", view_url, "📰"),
+          Button::new("Torrent-File", format!("{}download/{}.torrent", torrent.domain, torrent.id), "📁")
+        ]
+      },
+      Event::NewComment(comment) | Event::EditedComment(comment) | Event::DeletedComment(comment) => {
+        let user_url = format!("{}user/{}", torrent.domain, comment.user.username);
+        let (fields, timestamp, first_button) = match event {
+          Event::EditedComment(_) => (
+            vec![
+              (comment.user.username.clone()+" (old comment)", "```".to_owned()+&comment.old_message.clone().unwrap_or_default()+"```", true),
+              ("new:".to_string(), "```".to_owned()+&comment.message.clone()+"```", true)
+            ],
+            unix_to_datetime(comment.edited_timestamp.unwrap_or(comment.date_timestamp)),
+            Button::new("Comment@Nyaa.si", comment.direct_link.clone(), "💬")
+          ),
+          Event::DeletedComment(_) => (
+            vec![(comment.user.username.clone()+" (deleted comment)", comment.message.clone(), false)],
+            Utc::now(),
+            Button::new("Nyaa.si", view_url, "💬")
+          ),
+          _ => (
+            vec![(comment.user.username.clone(), comment.message.clone(), false)],
+            unix_to_datetime(comment.date_timestamp),
+            Button::new("Comment@Nyaa.si", comment.direct_link.clone(), "💬")
+          )
+        };
+        Card {
+          title,
+          thumbnail: comment.user.avatar.clone().unwrap_or_default(),
+          fields,
+          timestamp,
+          buttons: [first_button, Button::new(&comment.user.username, user_url, "🕵️")]
+        }
+      }
+    }
+  }
+}
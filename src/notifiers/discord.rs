@@ -0,0 +1,275 @@
+use chrono::{
+  DateTime, Utc
+};
+use std::sync::Arc;
+use serenity::{
+  all::{
+    ButtonStyle, CreateButton, CreateMessage
+  },
+  prelude::Mentionable,
+  model::{
+    prelude::{
+      ChannelId, ReactionType, RoleId
+    },
+    Color
+  },
+  builder::CreateEmbed
+};
+use async_trait::async_trait;
+use tracing::error;
+
+use crate::config::ModuleConfig;
+use crate::database::Database;
+use crate::discord::limit_string_length;
+use crate::metrics::DISCORD_CHANNELS_PAUSED;
+use crate::notifiers::{
+  Card, Event, Notifier
+};
+use crate::transport::Transport;
+use crate::web::NyaaTorrent;
+use crate::error::{
+  Error, Result
+};
+
+pub struct DiscordNotifier {
+  transport: Arc<dyn Transport>
+}
+
+impl DiscordNotifier {
+  pub fn new(transport: Arc<dyn Transport>) -> Self {
+    DiscordNotifier { transport }
+  }
+}
+
+fn channel(module: &ModuleConfig) -> Result<ChannelId> {
+  Ok(ChannelId::new(module.discord_channel_id.ok_or_else(|| Error::Config("The discord module is missing discord_channel_id".to_string()))?))
+}
+
+#[async_trait]
+impl Notifier for DiscordNotifier {
+  /// Channels the bot can't reach anymore are paused, instead of failing every check from now on.
+  async fn prepare(&self, module: &ModuleConfig, database: &mut Database) -> Result<bool> {
+    let channel = channel(module)?;
+    if let Err(e) = self.transport.check_discord_channel(channel).await {
+      error!("{}, pausing its notifications.", e);
+      database.pause_discord_channel(&module.discord_bot_id.clone().unwrap_or_default(), channel.get(), false).await?;
+      DISCORD_CHANNELS_PAUSED.inc(&[]);
+      return Ok(false);
+    }
+    Ok(true)
+  }
+
+  async fn send(&self, module: &ModuleConfig, _id: &str, _database: &mut Database, torrent: &NyaaTorrent, event: &Event<'_>) -> Result<()> {
+    let channel = channel(module)?;
+    let Card { title, thumbnail, fields, timestamp, buttons: [first, second] } = Card::new(torrent, event);
+    send_discord_embed(self.transport.as_ref(),
+      channel, module.discord_pinged_role, &limit_string_length(&title, 100), status_color(torrent),
+      thumbnail,
+      fields,
+      timestamp,
+      (first.label, second.label),
+      (first.url, second.url),
+      (ReactionType::Unicode(first.emoji.to_string()), ReactionType::Unicode(second.emoji.to_string()))
+    ).await
+  }
+}
+
+/// The colours of the rows on nyaa: green for trusted uploads and red for remakes.
+fn status_color(torrent: &NyaaTorrent) -> Color {
+  if torrent.remake {
+    Color::from_rgb(217, 83, 79)
+  } else if torrent.trusted {
+    Color::from_rgb(92, 184, 92)
+  } else {
+    Color::BLITZ_BLUE
+  }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn send_discord_embed(transport: &dyn Transport, channel: ChannelId, discord_pinged_role: Option<u64>, title: &str, color: Color, thumbnail: String, fields: Vec<(String, String, bool)>,
+utc_time: DateTime<Utc>, button_labels: (String, String), button_urls: (String, String), button_emojis: (ReactionType, ReactionType)) -> Result<()> {
+  for field in create_embeds_after_size(fields) {
+    let mut embed: CreateEmbed = serenity::builder::CreateEmbed::default();
+    embed = embed
+      .title(title)
+      .color(color)
+      .fields(field)
+    .timestamp(utc_time);
+    // discord rejects the whole embed if the thumbnail isn't a url
+    if thumbnail.starts_with("http") {
+      embed = embed.thumbnail(thumbnail.clone());
+    }
+
+    let buttons: Vec<CreateButton> = vec![
+      CreateButton::new_link(button_urls.0.clone())
+      .label(button_labels.0.clone())
+      .style(ButtonStyle::Primary)
+      .emoji(button_emojis.0.clone()),
+      CreateButton::new_link(button_urls.1.clone())
+      .label(button_labels.1.clone())
+      .style(ButtonStyle::Primary)
+      .emoji(button_emojis.1.clone()),
+    ];
+
+    let mut message = CreateMessage::new()
+      .embed(
+        embed
+      )
+      .button(
+        buttons.first().unwrap().clone()
+      )
+      .button(
+        buttons.get(1).unwrap().clone()
+      );
+    let role_id = discord_pinged_role.unwrap_or(0);
+    if role_id != 0 {
+      message = message.content(RoleId::new(role_id).mention().to_string());
+    }
+    transport.send_discord(channel, message).await?;
+  }
+  Ok(())
+}
+
+fn create_embeds_after_size(fields: Vec<(String, String, bool)>) -> Vec<Vec<(String, String, bool)>> {
+  let mut max_size = 1010; 
+  let mut output: Vec<Vec<(String, String, bool)>> = vec![];
+  let single_size = calculate_single_size(fields.clone());
+  let mut fields_copy = fields.clone();
+  if single_size > max_size {
+    let total_parts = calculate_total_parts(fields.clone(), max_size);
+    let mut message_index = 1;
+    let mut temp: (String, String, bool) = ("1".to_string(), "1".to_string(), false);
+    let mut field1_done = false;
+    let mut field2_done = false;
+    loop {
+      for (index, (name, text, inline)) in fields_copy.iter_mut().enumerate() {
+        if message_index > 10 {
+          break;
+        } 
+        if (fields.len() == 1) && (message_index != 1) {
+          output.append(&mut vec![
+            vec![temp.clone()]
+          ]);
+        }
+
+        let mut name = name.clone();
+        if index == 0 {
+          name += format!(" ({}/{}):", message_index, total_parts).as_str();
+        }
+
+        let message: String;
+        if fields.len() == 1 {
+          if max_size < (text.len() + name.len()) {
+            message = text.clone().split_at(max_size-name.len()).0.to_string();
+            *text = text.clone().split_at(max_size-name.len()).1.to_string();
+          } else {
+            message = text.clone();
+            output.append(&mut vec![
+              vec![(name, message, *inline)]
+            ]);
+            return output;
+          }
+        } else if index == 0 {
+          if (name.len() + text.len() + temp.0.len()) > (max_size / 2) {
+            message = text.clone().split_at((max_size/2)-(name.len()+temp.0.len())).0.to_string();
+            *text = text.clone().split_at((max_size/2)-(name.len()+temp.0.len())).1.to_string();
+          } else if (name.len() + text.len() + temp.0.len()) > (max_size) && (temp.1.is_empty()) {
+            message = text.clone().split_at((max_size)-(name.len()+temp.0.len())).0.to_string();
+            *text = text.clone().split_at((max_size)-(name.len()+temp.0.len())).1.to_string();
+          } else {
+            message = text.clone();
+            *text = "".to_string();
+            field1_done = true;
+          }
+        } else if (name.len() + text.len()) > (max_size / 2) && (!temp.1.is_empty()) {
+          message = text.clone().split_at((max_size/2)-(name.len()-1)).0.to_string();
+          *text = text.clone().split_at((max_size/2)-name.len()-1).1.to_string();
+        } else if (name.len() + text.len() + temp.0.len()) > (max_size) && (temp.1.is_empty()) {
+          message = text.clone().split_at((max_size)-(name.len()+temp.0.len())).0.to_string();
+          *text = text.clone().split_at((max_size)-(name.len()+temp.0.len())).1.to_string();
+        } else {
+          message = text.clone();
+          *text = "".to_string();
+          max_size *= 2;
+          field2_done = true;
+        }
+
+        if index == 0 {
+          temp = (name, message.clone(), *inline);
+          if field1_done && ((message_index > 1) && temp.1 == "1") {
+            return output;
+          }
+          message_index += 1;
+        } else if index == 1 {
+          output.append(&mut vec![
+            vec![temp.clone(), (name.clone(), message.to_string(), *inline)]
+          ]);
+          temp = (name, message.clone(), *inline);
+          if field1_done && field2_done {
+            return output;
+          }
+        }
+      }
+    }
+  } else {
+    for (index, field) in fields_copy.iter_mut().enumerate() {
+      if index == 0 {
+        field.0.push(':');
+      }
+    }
+    vec![fields_copy]
+  }
+}
+
+fn calculate_single_size(fields: Vec<(String, String, bool)>) -> usize {
+  let mut size = 1;
+  for (name, value, _) in fields {
+    size += name.len() + value.len();
+  }
+  size
+}
+
+fn calculate_total_parts(fields: Vec<(String, String, bool)>, max_size: usize) -> usize {
+  let mut amount = 0;
+  let field1_name = fields.first().unwrap().0.len();
+  let mut field1_message = fields.first().unwrap().1.len();
+  if fields.len() > 1 {
+      let field2_name = fields.get(1).unwrap().0.len();
+      let mut field2_message = fields.get(1).unwrap().1.len();
+      while !((field1_message == 0) && (field2_message == 0)) {
+        let mut no_change = false;
+        amount += 1;
+        if (field1_message > (max_size / 2) - (field1_name + field2_name + 6)) && 
+        (field2_message != 0) {
+          field1_message -= (max_size / 2) - (field1_name + field2_name + 6);
+        } else if (field1_message > (max_size) - (field1_name + field2_name + 6)) && 
+        (field2_message == 0) {
+          field1_message -= (max_size) - ((field1_name + 6) + field2_name);
+        } else {
+          field1_message = 0;
+          no_change = true;
+        }
+        
+        if field2_message > (max_size / 2) - field2_name {
+          field2_message -= max_size / 2 - (field2_name);
+        } else if (field2_message > (max_size) - (field2_name + field1_name + 6)) && 
+        (field1_message == 0) {
+          field2_message -= (max_size) - (field1_name + field2_name + 6);
+        } else if no_change {
+          break;
+        } else {
+          field2_message = 0;
+        }
+      }
+  } else {
+      while field1_message != 0 {
+        amount += 1;
+        if field1_message > (max_size) - (field1_name + 6) {
+          field1_message -= (max_size) - (field1_name + 6);
+        } else {
+          break;
+        }
+      }
+  }
+  amount
+}
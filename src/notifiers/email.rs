@@ -0,0 +1,223 @@
+use chrono::TimeZone;
+use std::sync::Arc;
+use lettre::{
+  message::{
+    MultiPart, SinglePart, header
+  },
+  Message
+};
+use async_trait::async_trait;
+
+use crate::config::ModuleConfig;
+use crate::database::Database;
+use crate::notifiers::{
+  Event, Notifier
+};
+use crate::transport::Transport;
+use crate::web::NyaaTorrent;
+use crate::error::{
+  Error, Result
+};
+
+/// Sends one email per torrent, containing the upload and all of its comment changes.
+pub struct EmailNotifier {
+  transport: Arc<dyn Transport>
+}
+
+impl EmailNotifier {
+  pub fn new(transport: Arc<dyn Transport>) -> Self {
+    EmailNotifier { transport }
+  }
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+  async fn send(&self, module: &ModuleConfig, id: &str, database: &mut Database, torrent: &NyaaTorrent, event: &Event<'_>) -> Result<()> {
+    self.send_all(module, id, database, torrent, std::slice::from_ref(event)).await.remove(0)
+  }
+
+  async fn send_all(&self, module: &ModuleConfig, _id: &str, _database: &mut Database, torrent: &NyaaTorrent, events: &[Event<'_>]) -> Vec<Result<()>> {
+    let html = email_create_html(torrent, events);
+    match email_send_message(self.transport.as_ref(), module, html).await {
+      Ok(()) => events.iter().map(|_| Ok(())).collect(),
+      Err(e) => {
+        let reason = e.to_string();
+        events.iter().map(|_| Err(Error::Smtp(reason.clone()))).collect()
+      }
+    }
+  }
+}
+
+fn email_create_html(torrent: &NyaaTorrent, events: &[Event<'_>]) -> String {
+  let mut html = HTML_HEAD.to_string();
+  let title = html_escape::encode_quoted_attribute(&torrent.title).to_string();
+  let view_url = format!("{}view/{}", torrent.domain, torrent.id);
+  let status = status_class(torrent);
+  if events.iter().any(|event| matches!(event, Event::Upload)) {
+    html.push_str(format!(
+      r#"<div class="panel panel-default info-panel new_release{}">
+      <div style="text-align: center;">
+        <a class="new_release" href="{}">{}</a>
+      </div>
+      <p class="info">{}</p>
+      <p class="info">{}</p>
+      <p class="info">{}</p>
+      <a href="{}" class="info">Download .torrent</a>
+      </div>"#,
+      status,
+      view_url,
+      title,
+      torrent.category,
+      torrent.upload_date_str,
+      torrent.size,
+      format_args!("{}download/{}.torrent", torrent.domain, torrent.id)
+    ).as_str());
+  } else {
+    html.push_str(format!(
+      r#"<div class="panel panel-default info-panel{}">
+      <div style="text-align: center;">
+        <a href="{}">{}</a>
+      </div>
+      <p class="info">{}</p>
+      <p class="info">{}</p>
+      <p class="info">{}</p>
+      </div>"#,
+      status,
+      view_url,
+      title,
+      torrent.category,
+      torrent.upload_date_str,
+      torrent.size
+    ).as_str());
+  }
+
+  for event in events {
+    if let Event::SeriesAlert(alert) = event {
+      html.push_str(format!(r#"<div class="panel panel-default info-panel alert"><p class="info">{}</p></div>"#, alert.message()).as_str());
+    }
+  }
+
+  // oldest first, like on the torrent page
+  for event in events.iter().rev() {
+    let (comment, timestamp) = match event {
+      Event::Upload | Event::SeriesAlert(_) => continue,
+      Event::NewComment(comment) => (comment, chrono::Utc.timestamp_opt(comment.date_timestamp as i64, 0).unwrap()),
+      Event::EditedComment(comment) => (comment, chrono::Utc.timestamp_opt(comment.edited_timestamp.unwrap_or(comment.date_timestamp) as i64, 0).unwrap()),
+      Event::DeletedComment(comment) => (comment, chrono::offset::Utc::now())
+    };
+    let timestamp = timestamp.format("%Y-%m-%d %H:%M:%S").to_string();
+    let message = comment.message.clone();
+
+    let text_color = text_color_from_role(comment.user.role.clone());
+    let text_style = if comment.user.banned {
+      " strike"
+    } else {
+      ""
+    };
+
+    html.push_str(format!(
+      r#"<div class="panel panel-default comment-panel" id="com-1">
+      <div class="panel-body">
+        <div class="col-md-2">
+          <p>
+            <a class="text-{}{}" href="{}" data-toggle="tooltip" title="User">{}</a>
+          </p>
+          <img class="avatar" src="{}" alt="User">
+        </div>
+        <div class="col-md-10 comment">
+          <div class="row comment-details">
+            <a href="{}"><small data-timestamp-swap>{}</small></a>
+            <div class="comment-actions">
+            </div>
+          </div>
+          <div class="row comment-body">
+            <div markdown-text class="comment-content" id="comment">{}</div>
+          </div>
+        </div>
+      </div>
+      </div>"#,
+      text_color, text_style,
+      format_args!("{}user/{}", torrent.domain, comment.user.username),
+      comment.user.username.clone(),
+      comment.user.avatar.clone().unwrap_or_default(),
+      comment.direct_link.clone(),
+      timestamp,
+      message
+    ).as_str());
+  }
+
+  html.push_str(r#"</div></body></html>"#);
+  html
+}
+
+async fn email_send_message(transport: &dyn Transport, module: &ModuleConfig, html: String) -> Result<()> {
+  let username = module.smtp_username.clone().ok_or_else(|| Error::Config("The email module is missing smtp_username".to_string()))?;
+  let mut email_template = Message::builder()
+    .from(username.parse().map_err(|e| Error::Smtp(format!("Invalid sender {:?}: {}", username, e)))?)
+    .subject(module.smtp_subject.clone().unwrap_or_default()
+  );
+  for recipient in module.smtp_recipients.clone().unwrap_or_default() {
+    email_template = email_template.bcc(recipient.parse().map_err(|e| Error::Smtp(format!("Invalid recipient {:?}: {}", recipient, e)))?);
+  }
+  let email = email_template.multipart(
+    MultiPart::alternative()
+    .singlepart(SinglePart::builder()
+      .header(header::ContentType::TEXT_HTML)
+      .body(html)
+    )
+  ).map_err(|e| Error::Smtp(format!("Failed to create message: {}", e)))?;
+  transport.send_email(module, email).await
+}
+
+// colours the info panel like the torrent's row on nyaa
+fn status_class(torrent: &NyaaTorrent) -> &'static str {
+  if torrent.remake {
+    " remake"
+  } else if torrent.trusted {
+    " trusted"
+  } else {
+    ""
+  }
+}
+
+fn text_color_from_role(role: String) -> &'static str {
+  match role.as_str() {
+    "Administrator" => {
+      "purple"
+    },
+    "Trusted" => {
+      "success"
+    },
+    _ => {
+      "default"
+    }
+  }
+}
+
+static HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="UTF-8"><meta name="viewport" content="width=device-width, initial-scale=1.0">
+<style>
+.comments {padding-left: 10px;padding-right: 10px;}div.title {font-size: 20px;text-align: center;}
+html {font-family: sans-serif;-ms-text-size-adjust: 100%;-webkit-text-size-adjust: 100%}body {margin: 0}a {background-color: transparent}
+a:active, a:hover {outline: 0}small {font-size: 80%}img {border: 0}
+@media print {*,*:before,*:after {background: transparent !important;color: #000 !important;-webkit-box-shadow: none !important;
+box-shadow: none !important;text-shadow: none !important}a,a:visited {text-decoration: underline}a[href]:after {content: " ("attr(href) ")"}
+img {page-break-inside: avoid}img {max-width: 100% !important}p {orphans: 3;widows: 3}}
+* {-webkit-box-sizing: border-box;-moz-box-sizing: border-box;box-sizing: border-box}
+*:before,*:after {-webkit-box-sizing: border-box;-moz-box-sizing: border-box;box-sizing: border-box}
+html {font-size: 10px;-webkit-tap-highlight-color: rgba(0, 0, 0, 0)}.row {margin-left: -15px;margin-right: -15px}
+body {font-family: "Helvetica Neue", Helvetica, Arial, sans-serif;font-size: 14px;line-height: 1.42857143;color: #afafaf;background-color: #262626}
+a {color: #337ab7;text-decoration: none}a:hover, a:focus {color: #19578b;text-decoration: underline}
+a:focus {outline: 5px auto -webkit-focus-ring-color;outline-offset: -2px}img {vertical-align: middle}p {margin: 0 0 10px}small {font-size: 85%}
+.col-md-2, .col-md-10 {position: relative;min-height: 1px;padding-left: 15px;padding-right: 15px}
+@media (min-width:992px) {.col-md-2,.col-md-10 {float: left}.col-md-10 {width: 83.33333333%}.col-md-2 {width: 16.66666667%}}
+.panel {margin-top: 10px; margin-bottom: 10px;background-color: #323232;border: 1px solid transparent;border-radius: 4px;
+  -webkit-box-shadow: 0 1px 1px rgba(0, 0, 0, 0.05);box-shadow: 0 1px 1px rgba(0, 0, 0, 0.05)}.panel-body {padding: 15px}
+.panel-default {border-color: #111}.row:before, .row:after, .panel-body:before, .panel-body:after {content: " ";display: table}
+.row:after, .panel-body:after {clear: both}@-ms-viewport {width: device-width}.info {margin-bottom: 0px;padding-left: 10px;padding-left: 10px;}
+.info-panel {padding: 10px;}div.new_release {border-color:magenta;box-shadow: 0 0 10px rgba(255, 0, 255, 0.777);}
+a.new_release {text-align: center !important;font-size: 20px;}
+div.trusted {border-color: #3c763d;background-color: #2b3a2b;}div.remake {border-color: #a94442;background-color: #3f2a2a;}
+div.alert {border-color: #8a6d3b;background-color: #3f3a2a;}
+</style>
+</head><body>"#;
@@ -0,0 +1,41 @@
+use std::sync::Arc;
+use async_trait::async_trait;
+
+use crate::config::ModuleConfig;
+use crate::database::Database;
+use crate::discord::limit_string_length;
+use crate::notifiers::{
+  Event, Notifier
+};
+use crate::transport::Transport;
+use crate::web::NyaaTorrent;
+use crate::error::Result;
+
+pub struct GotifyNotifier {
+  transport: Arc<dyn Transport>
+}
+
+impl GotifyNotifier {
+  pub fn new(transport: Arc<dyn Transport>) -> Self {
+    GotifyNotifier { transport }
+  }
+}
+
+#[async_trait]
+impl Notifier for GotifyNotifier {
+  async fn send(&self, module: &ModuleConfig, _id: &str, _database: &mut Database, torrent: &NyaaTorrent, event: &Event<'_>) -> Result<()> {
+    let title = limit_string_length(&torrent.title, 75);
+    let (message, fallback_priority) = match event {
+      Event::Upload => (
+        format!("{} | {} | #{}", torrent.category, torrent.size, torrent.id),
+        module.gotify_upload_priority
+      ),
+      Event::SeriesAlert(alert) => (alert.message(), module.gotify_upload_priority),
+      Event::NewComment(comment) => (format!("{} [NEW]: {}", comment.user.username, comment.message), module.gotify_comment_priority),
+      Event::EditedComment(comment) => (format!("{} [EDITED]: {}", comment.user.username, comment.message), module.gotify_comment_priority),
+      Event::DeletedComment(comment) => (format!("{} [DELETED]: {}", comment.user.username, comment.message), module.gotify_comment_priority)
+    };
+    let priority = event.priority(module).map(|priority| priority.max(0) as u32).or(fallback_priority).unwrap_or_default();
+    self.transport.send_gotify(module, &title, message, priority).await
+  }
+}
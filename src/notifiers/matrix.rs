@@ -0,0 +1,193 @@
+use html_escape::encode_text;
+use serde_json::{
+  json, Value
+};
+use std::sync::{
+  atomic::{AtomicU64, Ordering}, Arc
+};
+use async_trait::async_trait;
+
+use crate::config::{
+  ModuleConfig, ModuleType
+};
+use crate::database::Database;
+use crate::discord::limit_string_length;
+use crate::notifiers::{
+  Event, Notifier
+};
+use crate::rss::percent_encode;
+use crate::series::SeriesAlert;
+use crate::transport::{
+  HttpRequest, Transport
+};
+use crate::web::{
+  NyaaComment, NyaaTorrent
+};
+use crate::error::{
+  Error, Result
+};
+
+/// Posts HTML messages to a room. The event ids of comment notifications are remembered,
+/// so that edited comments become edits (`m.replace`) of them and deleted ones redactions or replies.
+pub struct MatrixNotifier {
+  transport: Arc<dyn Transport>,
+  transactions: AtomicU64
+}
+
+impl MatrixNotifier {
+  pub fn new(transport: Arc<dyn Transport>) -> Self {
+    MatrixNotifier { transport, transactions: AtomicU64::new(0) }
+  }
+
+  /// Transaction ids only have to be unique per access token, the time keeps them apart across restarts.
+  fn transaction_id(&self) -> String {
+    format!("nyaa{}.{}", chrono::Utc::now().timestamp_millis(), self.transactions.fetch_add(1, Ordering::Relaxed))
+  }
+
+  async fn put(&self, module: &ModuleConfig, path: &str, body: &Value) -> Result<Value> {
+    let homeserver = module.matrix_homeserver.clone().ok_or_else(|| Error::Config("The matrix module is missing matrix_homeserver".to_string()))?;
+    let token = module.matrix_access_token.clone().ok_or_else(|| Error::Config("The matrix module is missing matrix_access_token".to_string()))?;
+    let url = format!("{}/_matrix/client/v3/{}", homeserver.trim_end_matches('/'), path);
+    let request = HttpRequest::new("PUT", &url)
+      .header("Authorization", &format!("Bearer {}", token))
+      .json(body);
+    let response = self.transport.http(request).await.map_err(|e| Error::Matrix(e.to_string()))?;
+    if !response.is_success() {
+      return Err(Error::Matrix(format!("Homeserver answered with {}", response.status)));
+    }
+    serde_json::from_str(&response.body).map_err(|e| Error::Matrix(format!("Unreadable answer from the homeserver: {}", e)))
+  }
+
+  /// Sends an `m.room.message` and returns its event id.
+  async fn send_message(&self, module: &ModuleConfig, room: &str, content: Value) -> Result<String> {
+    let path = format!("rooms/{}/send/m.room.message/{}", percent_encode(room), self.transaction_id());
+    let answer = self.put(module, &path, &content).await?;
+    answer["event_id"].as_str().map(str::to_string)
+      .ok_or_else(|| Error::Matrix("The homeserver didn't return an event_id".to_string()))
+  }
+
+  async fn redact(&self, module: &ModuleConfig, room: &str, event_id: &str) -> Result<()> {
+    let path = format!("rooms/{}/redact/{}/{}", percent_encode(room), percent_encode(event_id), self.transaction_id());
+    self.put(module, &path, &json!({ "reason": "The comment was deleted" })).await?;
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl Notifier for MatrixNotifier {
+  async fn send(&self, module: &ModuleConfig, id: &str, database: &mut Database, torrent: &NyaaTorrent, event: &Event<'_>) -> Result<()> {
+    let room = module.matrix_room_id.clone().ok_or_else(|| Error::Config("The matrix module is missing matrix_room_id".to_string()))?;
+    let comment = match event {
+      Event::Upload => {
+        self.send_message(module, &room, upload_content(torrent)).await?;
+        return Ok(());
+      },
+      Event::SeriesAlert(alert) => {
+        self.send_message(module, &room, alert_content(torrent, alert)).await?;
+        return Ok(());
+      },
+      Event::NewComment(comment) | Event::EditedComment(comment) | Event::DeletedComment(comment) => *comment
+    };
+    let key = comment_key(comment);
+    let id = id.to_string();
+    let earlier = database.get_sent_message(ModuleType::Matrix.to_string(), &id, &room, &torrent.domain, torrent.id, &key).await?;
+    match (event, earlier) {
+      (Event::EditedComment(_), Some(event_id)) => {
+        // edits always point at the original message, so the remembered id stays the same
+        self.send_message(module, &room, replacement_content(comment_content(torrent, comment, false), &event_id)).await?;
+      },
+      (Event::DeletedComment(_), Some(event_id)) => {
+        if module.matrix_redact_deleted.unwrap_or_default() {
+          self.redact(module, &room, &event_id).await?;
+        } else {
+          self.send_message(module, &room, reply_content(deleted_content(torrent, comment), &event_id)).await?;
+        }
+        database.forget_sent_message(ModuleType::Matrix.to_string(), &id, &room, &torrent.domain, torrent.id, &key).await?;
+      },
+      (Event::DeletedComment(_), None) => {
+        self.send_message(module, &room, deleted_content(torrent, comment)).await?;
+      },
+      // new comments, and edits of comments which were never announced here
+      _ => {
+        let event_id = self.send_message(module, &room, comment_content(torrent, comment, matches!(event, Event::EditedComment(_)))).await?;
+        database.remember_sent_message(ModuleType::Matrix.to_string(), &id, &room, &torrent.domain, torrent.id, &key, &event_id).await?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Nyaa's comment ids shift when an earlier comment is deleted, author and posting time don't.
+fn comment_key(comment: &NyaaComment) -> String {
+  format!("{}/{}", comment.user.username, comment.date_timestamp as i64)
+}
+
+fn message_content(body: String, formatted_body: String) -> Value {
+  json!({
+    "msgtype": "m.text",
+    "body": body,
+    "format": "org.matrix.custom.html",
+    "formatted_body": formatted_body
+  })
+}
+
+fn torrent_link(torrent: &NyaaTorrent) -> String {
+  format!("<a href=\"{}view/{}\"><b>{}</b></a>", torrent.domain, torrent.id, encode_text(&limit_string_length(&torrent.title, 200)))
+}
+
+fn upload_content(torrent: &NyaaTorrent) -> Value {
+  let view_url = format!("{}view/{}", torrent.domain, torrent.id);
+  let download_url = format!("{}download/{}.torrent", torrent.domain, torrent.id);
+  let uploader = torrent.uploader.as_ref().map(|uploader| uploader.username.clone()).unwrap_or("Anonymous".to_string());
+  message_content(
+    format!("New upload by {}: {}\nCategory: {}\nSize: {}\n{}\n{}", uploader, torrent.title, torrent.category, torrent.size, view_url, download_url),
+    format!("New upload by {}: {}<br>Category: {}<br>Size: {}<br><a href=\"{}\">Torrent-File</a>",
+      encode_text(&uploader), torrent_link(torrent), encode_text(&torrent.category), encode_text(&torrent.size), download_url)
+  )
+}
+
+fn alert_content(torrent: &NyaaTorrent, alert: &SeriesAlert) -> Value {
+  message_content(
+    format!("{}\n{}", alert.message(), torrent.title),
+    format!("<b>{}</b><br>{}", encode_text(&alert.message()), torrent_link(torrent))
+  )
+}
+
+/// Comments which were edited before they were ever announced show the old message struck through.
+fn comment_content(torrent: &NyaaTorrent, comment: &NyaaComment, edited: bool) -> Value {
+  let message = limit_string_length(&comment.message, 3000);
+  let old_message = comment.old_message.as_ref().filter(|_| edited).map(|old_message| limit_string_length(old_message, 500));
+  let (body_old, html_old) = match &old_message {
+    Some(old_message) => (format!("{}\n", old_message), format!("<del>{}</del><br>", encode_text(old_message))),
+    None => (String::new(), String::new())
+  };
+  message_content(
+    format!("{} commented on {}:\n{}{}\n{}", comment.user.username, torrent.title, body_old, message, comment.direct_link),
+    format!("<b>{}</b> <a href=\"{}\">commented</a> on {}:<br>{}{}",
+      encode_text(&comment.user.username), comment.direct_link, torrent_link(torrent), html_old, encode_text(&message))
+  )
+}
+
+fn deleted_content(torrent: &NyaaTorrent, comment: &NyaaComment) -> Value {
+  let message = limit_string_length(&comment.message, 3000);
+  message_content(
+    format!("{} deleted their comment on {}:\n{}", comment.user.username, torrent.title, message),
+    format!("<b>{}</b> deleted their comment on {}:<br><del>{}</del>", encode_text(&comment.user.username), torrent_link(torrent), encode_text(&message))
+  )
+}
+
+/// An edit of `event_id`. Clients which don't know edits show the fallback `body` with the customary `* ` in front.
+fn replacement_content(content: Value, event_id: &str) -> Value {
+  let mut replacement = message_content(
+    format!("* {}", content["body"].as_str().unwrap_or_default()),
+    format!("* {}", content["formatted_body"].as_str().unwrap_or_default())
+  );
+  replacement["m.new_content"] = content;
+  replacement["m.relates_to"] = json!({ "rel_type": "m.replace", "event_id": event_id });
+  replacement
+}
+
+fn reply_content(mut content: Value, event_id: &str) -> Value {
+  content["m.relates_to"] = json!({ "m.in_reply_to": { "event_id": event_id } });
+  content
+}
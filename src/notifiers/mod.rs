@@ -0,0 +1,220 @@
+use async_trait::async_trait;
+use tracing::{
+  debug, error, info_span, warn, Instrument
+};
+
+use crate::atom::HistoryEvent;
+use crate::config::ModuleConfig;
+use crate::database::Database;
+use crate::metrics::DELIVERIES;
+use crate::series::SeriesAlert;
+use crate::web::{
+  NyaaComment, NyaaCommentUpdateType, NyaaTorrent, NyaaUpdate
+};
+use crate::error::Result;
+
+mod atom;
+mod card;
+mod discord;
+mod email;
+mod gotify;
+mod matrix;
+mod ntfy;
+mod pushover;
+mod slack;
+mod telegram;
+mod webhook;
+
+pub use atom::{
+  atom_path, render_atom_feed, AtomNotifier
+};
+pub use card::{
+  Button, Card
+};
+pub use discord::DiscordNotifier;
+pub use email::EmailNotifier;
+pub use gotify::GotifyNotifier;
+pub use matrix::MatrixNotifier;
+pub use ntfy::NtfyNotifier;
+pub use pushover::PushoverNotifier;
+pub use slack::SlackNotifier;
+pub use telegram::TelegramNotifier;
+pub use webhook::{
+  sign, WebhookNotifier, SIGNATURE_HEADER
+};
+
+/// Something worth telling the module about, always concerning one torrent.
+#[derive(Debug, Clone, Copy)]
+pub enum Event<'a> {
+  Upload,
+  NewComment(&'a NyaaComment),
+  /// The comment carries both `old_message` and the new `message`.
+  EditedComment(&'a NyaaComment),
+  DeletedComment(&'a NyaaComment),
+  /// Sent right after the upload it came with.
+  SeriesAlert(&'a SeriesAlert)
+}
+
+impl<'a> Event<'a> {
+  fn from_comment(comment: &'a NyaaComment) -> Option<Self> {
+    match comment.update_type {
+      NyaaCommentUpdateType::NEW => Some(Event::NewComment(comment)),
+      NyaaCommentUpdateType::EDITED => Some(Event::EditedComment(comment)),
+      NyaaCommentUpdateType::DELETED => Some(Event::DeletedComment(comment)),
+      NyaaCommentUpdateType::UNDECIDED | NyaaCommentUpdateType::UNCHECKED => None
+    }
+  }
+
+  /// The comment it's about, none for uploads.
+  pub fn comment(&self) -> Option<&'a NyaaComment> {
+    match self {
+      Event::Upload | Event::SeriesAlert(_) => None,
+      Event::NewComment(comment) | Event::EditedComment(comment) | Event::DeletedComment(comment) => Some(comment)
+    }
+  }
+
+  /// How the event is called in webhooks and the atom history.
+  pub fn name(&self) -> &'static str {
+    match self {
+      Event::Upload => "upload",
+      Event::NewComment(_) => "new_comment",
+      Event::EditedComment(_) => "edited_comment",
+      Event::DeletedComment(_) => "deleted_comment",
+      Event::SeriesAlert(_) => "series_alert"
+    }
+  }
+
+  /// The module's `priorities` entry for this kind of event, if it has one.
+  pub fn priority(&self, module: &ModuleConfig) -> Option<i32> {
+    let priorities = module.priorities.as_ref()?;
+    match self {
+      Event::Upload => priorities.upload,
+      Event::NewComment(_) => priorities.new_comment,
+      Event::EditedComment(_) => priorities.edited_comment,
+      Event::DeletedComment(_) => priorities.deleted_comment,
+      Event::SeriesAlert(_) => priorities.series_alert
+    }
+  }
+}
+
+/// A notification backend. It only has to send events, `deliver` decides what gets stored afterwards.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+  /// Runs before a module's updates are sent. Returning false skips them all, and nothing gets stored.
+  async fn prepare(&self, _module: &ModuleConfig, _database: &mut Database) -> Result<bool> {
+    Ok(true)
+  }
+
+  /// The database is only needed by backends which have to remember what they sent, like matrix for its edits.
+  /// They keep it under the module's `id`, so that resetting the module forgets it as well.
+  async fn send(&self, module: &ModuleConfig, id: &str, database: &mut Database, torrent: &NyaaTorrent, event: &Event<'_>) -> Result<()>;
+
+  /// Backends whose history is what they deliver, like atom, add the events to it themselves instead of `deliver`.
+  fn keeps_history(&self) -> bool {
+    false
+  }
+
+  /// Sends all events of one torrent, returning one result per event.
+  /// Backends which bundle them into a single message override this.
+  async fn send_all(&self, module: &ModuleConfig, id: &str, database: &mut Database, torrent: &NyaaTorrent, events: &[Event<'_>]) -> Vec<Result<()>> {
+    let mut results = vec![];
+    for event in events {
+      results.push(self.send(module, id, database, torrent, event).await);
+    }
+    results
+  }
+}
+
+/// How many delivered events are kept per module, for looking at through the api.
+pub const HISTORY_LENGTH: u32 = 100;
+
+/// Sends the updates and returns what should go back into the database:
+/// - an upload which couldn't be announced isn't stored at all, so it's new again on the next check, alerts included
+/// - announced comments become `UNCHECKED`, announced deletions are dropped
+/// - comments which failed are stored as they were before, with a comment count that makes the next check look again
+///
+/// Delivered events are also added to the module's history, unless the backend keeps it itself.
+pub async fn deliver(notifier: &dyn Notifier, module: &ModuleConfig, id: &String, database: &mut Database, updates: Vec<NyaaUpdate>) -> Vec<NyaaUpdate> {
+  let uploads = module.uploads.unwrap_or_default();
+  let comments = module.comments.unwrap_or_default();
+  let mut successful_updates: Vec<NyaaUpdate> = vec![];
+  for update in updates {
+    let upload = update.new_upload && uploads;
+    let mut events: Vec<Event> = vec![];
+    if upload {
+      events.push(Event::Upload);
+      events.extend(update.alerts.iter().map(Event::SeriesAlert));
+    }
+    if comments {
+      events.extend(update.torrent.comments.iter().filter_map(Event::from_comment));
+    }
+    let mut delivered = vec![];
+    if !events.is_empty() {
+      let span = info_span!("deliver", backend = %module.module_type, torrent_id = update.torrent.id);
+      let results = notifier.send_all(module, id, database, &update.torrent, &events).instrument(span.clone()).await;
+      for (result, event) in results.into_iter().zip(&events) {
+        DELIVERIES.inc(&[&module.module_type.to_string(), if result.is_ok() { "success" } else { "failure" }]);
+        let comment = event.comment().map(|comment| comment.direct_link.as_str());
+        match &result {
+          Ok(()) => {
+            debug!(parent: &span, event = event.name(), comment, "Sent the message.");
+            if !notifier.keeps_history() {
+              let history_event = HistoryEvent::new(&update.torrent, event);
+              if let Err(e) = database.add_history_event(module.module_type.to_string(), id, &history_event, HISTORY_LENGTH).await {
+                warn!(parent: &span, "{}", e);
+              }
+            }
+          },
+          Err(e) => error!(parent: &span, event = event.name(), comment, "Failed to send message: {}", e)
+        }
+        delivered.push(result.is_ok());
+      }
+    }
+    let mut delivered = delivered.into_iter();
+    if upload && !delivered.next().unwrap_or_default() {
+      continue;
+    }
+    // alerts which failed aren't sent again, the upload they came with is stored all the same
+    if upload {
+      delivered.by_ref().take(update.alerts.len()).for_each(drop);
+    }
+
+    let mut torrent = update.torrent;
+    let mut failed = false;
+    let mut stored_comments = vec![];
+    for mut comment in std::mem::take(&mut torrent.comments) {
+      let update_type = comment.update_type.clone();
+      if !comments || matches!(update_type, NyaaCommentUpdateType::UNDECIDED | NyaaCommentUpdateType::UNCHECKED) {
+        if update_type != NyaaCommentUpdateType::DELETED {
+          stored_comments.push(comment);
+        }
+        continue;
+      }
+      let sent = delivered.next().unwrap_or_default();
+      failed |= !sent;
+      match (update_type, sent) {
+        (NyaaCommentUpdateType::DELETED, true) => continue,
+        (NyaaCommentUpdateType::NEW, false) => continue,
+        (NyaaCommentUpdateType::EDITED, false) => {
+          comment.message = comment.old_message.take().unwrap_or(comment.message);
+          comment.edited_timestamp = comment.old_edited_timestamp.take();
+        },
+        _ => {}
+      }
+      comment.update_type = NyaaCommentUpdateType::UNCHECKED;
+      stored_comments.push(comment);
+    }
+    torrent.comments = stored_comments;
+    if failed {
+      // A stored count that doesn't match the listing makes the next check fetch the comments again.
+      torrent.comments_amount = torrent.comments.len() as u64;
+    }
+    successful_updates.push(NyaaUpdate {
+      new_upload: update.new_upload,
+      torrent,
+      feed: update.feed,
+      alerts: vec![]
+    });
+  }
+  successful_updates
+}
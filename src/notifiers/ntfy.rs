@@ -0,0 +1,74 @@
+use serde_json::json;
+use std::sync::Arc;
+use async_trait::async_trait;
+
+use crate::config::ModuleConfig;
+use crate::database::Database;
+use crate::discord::limit_string_length;
+use crate::notifiers::{
+  Event, Notifier
+};
+use crate::transport::{
+  HttpRequest, Transport
+};
+use crate::web::NyaaTorrent;
+use crate::error::{
+  Error, Result
+};
+
+const NTFY_SERVER: &str = "https://ntfy.sh";
+const DEFAULT_PRIORITY: i32 = 3;
+
+/// Publishes to a topic on ntfy.sh or a self-hosted server. Tapping a notification opens the torrent's page,
+/// uploads come with the `.torrent` file attached.
+pub struct NtfyNotifier {
+  transport: Arc<dyn Transport>
+}
+
+impl NtfyNotifier {
+  pub fn new(transport: Arc<dyn Transport>) -> Self {
+    NtfyNotifier { transport }
+  }
+}
+
+#[async_trait]
+impl Notifier for NtfyNotifier {
+  async fn send(&self, module: &ModuleConfig, _id: &str, _database: &mut Database, torrent: &NyaaTorrent, event: &Event<'_>) -> Result<()> {
+    let topic = module.ntfy_topic.clone().ok_or_else(|| Error::Config("The ntfy module is missing ntfy_topic".to_string()))?;
+    let server = module.ntfy_server.as_deref().unwrap_or(NTFY_SERVER).trim_end_matches('/');
+    let download_url = format!("{}download/{}.torrent", torrent.domain, torrent.id);
+    let (tag, message) = match event {
+      Event::Upload => ("inbox_tray", format!("{} | {} | #{}", torrent.category, torrent.size, torrent.id)),
+      Event::SeriesAlert(alert) => ("warning", alert.message()),
+      Event::NewComment(comment) => ("speech_balloon", format!("{}: {}", comment.user.username, comment.message)),
+      Event::EditedComment(comment) => ("pencil2", format!("{} (edited): {}", comment.user.username, comment.message)),
+      Event::DeletedComment(comment) => ("wastebasket", format!("{} (deleted): {}", comment.user.username, comment.message))
+    };
+    let mut tags = vec![tag.to_string()];
+    tags.extend(module.ntfy_tags.iter().flatten().cloned());
+
+    let mut body = json!({
+      "topic": topic,
+      "title": limit_string_length(&torrent.title, 250),
+      "message": limit_string_length(&message, 4000),
+      "tags": tags,
+      "priority": event.priority(module).unwrap_or(DEFAULT_PRIORITY),
+      "click": format!("{}view/{}", torrent.domain, torrent.id),
+      "actions": [{ "action": "view", "label": "Torrent-File", "url": download_url }]
+    });
+    if let Event::Upload = event {
+      body["attach"] = json!(download_url);
+      body["filename"] = json!(format!("{}.torrent", torrent.id));
+    }
+
+    let mut request = HttpRequest::post(server).json(&body);
+    if let Some(token) = &module.ntfy_token {
+      request = request.header("Authorization", &format!("Bearer {}", token));
+    }
+    let response = self.transport.http(request).await.map_err(|e| Error::Ntfy(e.to_string()))?;
+    if !response.is_success() {
+      return Err(Error::Ntfy(format!("Server answered with {}", response.status)));
+    }
+    Ok(())
+  }
+}
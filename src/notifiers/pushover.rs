@@ -0,0 +1,78 @@
+use html_escape::encode_text;
+use serde_json::json;
+use std::sync::Arc;
+use async_trait::async_trait;
+
+use crate::config::ModuleConfig;
+use crate::database::Database;
+use crate::discord::limit_string_length;
+use crate::notifiers::{
+  Card, Event, Notifier
+};
+use crate::transport::{
+  HttpRequest, Transport
+};
+use crate::web::NyaaTorrent;
+use crate::error::{
+  Error, Result
+};
+
+const PUSHOVER_API_URL: &str = "https://api.pushover.net";
+
+/// Sends the same card as discord and slack: the fields as the message, the first button as the supplementary url
+/// and the second one as a link below the fields.
+pub struct PushoverNotifier {
+  transport: Arc<dyn Transport>
+}
+
+impl PushoverNotifier {
+  pub fn new(transport: Arc<dyn Transport>) -> Self {
+    PushoverNotifier { transport }
+  }
+}
+
+#[async_trait]
+impl Notifier for PushoverNotifier {
+  async fn send(&self, module: &ModuleConfig, _id: &str, _database: &mut Database, torrent: &NyaaTorrent, event: &Event<'_>) -> Result<()> {
+    let token = module.pushover_token.clone().ok_or_else(|| Error::Config("The pushover module is missing pushover_token".to_string()))?;
+    let user = module.pushover_user.clone().ok_or_else(|| Error::Config("The pushover module is missing pushover_user".to_string()))?;
+    let api_url = module.pushover_api_url.as_deref().unwrap_or(PUSHOVER_API_URL).trim_end_matches('/');
+    let card = Card::new(torrent, event);
+    let [first, second] = &card.buttons;
+
+    // the edited comments' code blocks are discord markdown, pushover would show the backticks
+    let mut message: Vec<String> = card.fields.iter().map(|(name, value, _)| {
+      format!("<b>{}</b>\n{}", encode_text(name), encode_text(&limit_string_length(value.trim_matches('`'), 400)))
+    }).collect();
+    message.push(format!("<a href=\"{}\">{} {}</a>", second.url, second.emoji, encode_text(&second.label)));
+    let priority = event.priority(module).unwrap_or_default();
+
+    let mut body = json!({
+      "token": token,
+      "user": user,
+      "title": limit_string_length(&card.title, 250),
+      "message": limit_string_length(&message.join("\n"), 1024),
+      "html": 1,
+      "url": first.url,
+      "url_title": format!("{} {}", first.emoji, first.label),
+      "timestamp": card.timestamp.timestamp(),
+      "priority": priority
+    });
+    if let Some(device) = &module.pushover_device {
+      body["device"] = json!(device);
+    }
+    if priority == 2 {
+      // emergency messages repeat until they're acknowledged, every minute for at most an hour
+      body["retry"] = json!(60);
+      body["expire"] = json!(3600);
+    }
+
+    let url = format!("{}/1/messages.json", api_url);
+    let response = self.transport.http(HttpRequest::post(&url).json(&body)).await
+      .map_err(|e| Error::Pushover(e.to_string()))?;
+    if !response.is_success() {
+      return Err(Error::Pushover(format!("Server answered with {}: {}", response.status, response.body)));
+    }
+    Ok(())
+  }
+}
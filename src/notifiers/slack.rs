@@ -0,0 +1,97 @@
+use serde_json::{
+  json, Value
+};
+use std::sync::Arc;
+use async_trait::async_trait;
+
+use crate::config::ModuleConfig;
+use crate::database::Database;
+use crate::discord::limit_string_length;
+use crate::notifiers::{
+  Card, Event, Notifier
+};
+use crate::transport::{
+  HttpRequest, Transport
+};
+use crate::web::NyaaTorrent;
+use crate::error::{
+  Error, Result
+};
+
+/// Posts Block Kit messages to a Slack incoming webhook, laid out like the discord embeds.
+/// Mattermost, Rocket.Chat and others accept the same payload, the `text` is their fallback.
+pub struct SlackNotifier {
+  transport: Arc<dyn Transport>
+}
+
+impl SlackNotifier {
+  pub fn new(transport: Arc<dyn Transport>) -> Self {
+    SlackNotifier { transport }
+  }
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+  async fn send(&self, module: &ModuleConfig, _id: &str, _database: &mut Database, torrent: &NyaaTorrent, event: &Event<'_>) -> Result<()> {
+    let url = module.slack_webhook_url.clone().ok_or_else(|| Error::Config("The slack module is missing slack_webhook_url".to_string()))?;
+    let response = self.transport.http(HttpRequest::post(&url).json(&slack_message(&Card::new(torrent, event)))).await
+      .map_err(|e| Error::Slack(e.to_string()))?;
+    if !response.is_success() {
+      return Err(Error::Slack(format!("Server answered with {}", response.status)));
+    }
+    Ok(())
+  }
+}
+
+/// A header with the title, the fields as sections (inline ones side by side) with the avatar next to the first one,
+/// the timestamp and the two buttons.
+fn slack_message(card: &Card) -> Value {
+  let mut blocks = vec![json!({
+    "type": "header",
+    "text": { "type": "plain_text", "text": limit_string_length(&card.title, 150) }
+  })];
+  let mut inline_fields: Vec<Value> = vec![];
+  let mut sections: Vec<Value> = vec![];
+  for (name, value, inline) in &card.fields {
+    let text = format!("*{}*\n{}", escape(name), escape(&limit_string_length(value, 1800)));
+    if *inline && inline_fields.len() < 10 {
+      inline_fields.push(json!({ "type": "mrkdwn", "text": text }));
+      continue;
+    }
+    if !inline_fields.is_empty() {
+      sections.push(json!({ "type": "section", "fields": std::mem::take(&mut inline_fields) }));
+    }
+    sections.push(json!({ "type": "section", "text": { "type": "mrkdwn", "text": text } }));
+  }
+  if !inline_fields.is_empty() {
+    sections.push(json!({ "type": "section", "fields": inline_fields }));
+  }
+  if let Some(first) = sections.first_mut().filter(|_| card.thumbnail.starts_with("http")) {
+    first["accessory"] = json!({ "type": "image", "image_url": card.thumbnail, "alt_text": "avatar" });
+  }
+  blocks.extend(sections);
+  blocks.push(json!({
+    "type": "context",
+    "elements": [{
+      "type": "mrkdwn",
+      "text": format!("<!date^{}^{{date_short_pretty}} {{time}}|{}>", card.timestamp.timestamp(), card.timestamp.format("%Y-%m-%d %H:%M UTC"))
+    }]
+  }));
+  blocks.push(json!({
+    "type": "actions",
+    "elements": card.buttons.iter().map(|button| json!({
+      "type": "button",
+      "text": { "type": "plain_text", "text": limit_string_length(&format!("{} {}", button.emoji, button.label), 75), "emoji": true },
+      "url": button.url
+    })).collect::<Vec<Value>>()
+  }));
+  json!({
+    "text": card.title,
+    "blocks": blocks
+  })
+}
+
+/// The three characters Slack wants escaped in mrkdwn, everything else is left to the formatting.
+fn escape(text: &str) -> String {
+  text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
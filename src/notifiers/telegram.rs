@@ -0,0 +1,143 @@
+use html_escape::encode_text;
+use serde_json::{
+  json, Value
+};
+use std::{
+  sync::Arc, time::Duration
+};
+use async_trait::async_trait;
+
+use crate::config::{
+  ModuleConfig, ModuleType
+};
+use crate::database::Database;
+use crate::discord::limit_string_length;
+use crate::notifiers::{
+  Event, Notifier
+};
+use crate::transport::{
+  HttpRequest, Transport
+};
+use crate::web::NyaaTorrent;
+use crate::error::{
+  Error, Result
+};
+
+const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+
+/// Sends every event to all of the module's chats, with the same two link buttons as the discord embeds.
+/// An event only counts as delivered once every chat got it. Until then the chats which did are remembered,
+/// so that the retry on the next check only goes to the others.
+pub struct TelegramNotifier {
+  transport: Arc<dyn Transport>
+}
+
+impl TelegramNotifier {
+  pub fn new(transport: Arc<dyn Transport>) -> Self {
+    TelegramNotifier { transport }
+  }
+}
+
+#[async_trait]
+impl Notifier for TelegramNotifier {
+  async fn send(&self, module: &ModuleConfig, id: &str, database: &mut Database, torrent: &NyaaTorrent, event: &Event<'_>) -> Result<()> {
+    let token = module.telegram_token.clone().ok_or_else(|| Error::Config("The telegram module is missing telegram_token".to_string()))?;
+    let api_url = module.telegram_api_url.as_deref().unwrap_or(TELEGRAM_API_URL).trim_end_matches('/');
+    let (method, mut message) = telegram_message(torrent, event);
+    let key = delivery_key(event);
+    let id = id.to_string();
+    let chats: Vec<String> = module.telegram_chat_ids.iter().flatten().map(|chat| chat.to_string()).collect();
+    let mut failures = vec![];
+    for chat in &chats {
+      if database.get_sent_message(ModuleType::Telegram.to_string(), &id, chat, &torrent.domain, torrent.id, &key).await?.is_some() {
+        continue;
+      }
+      message["chat_id"] = json!(chat);
+      let url = format!("{}/bot{}/{}", api_url, token, method);
+      let result = self.transport.http(HttpRequest::post(&url).json(&message)).await;
+      self.transport.pause(Duration::from_secs(1)).await;
+      match result {
+        Ok(response) if response.is_success() => {
+          let message_id = serde_json::from_str::<Value>(&response.body).ok()
+            .and_then(|body| body["result"]["message_id"].as_i64()).unwrap_or_default();
+          database.remember_sent_message(ModuleType::Telegram.to_string(), &id, chat, &torrent.domain, torrent.id, &key, &message_id.to_string()).await?;
+        },
+        Ok(response) => failures.push(format!("{} answered {} with {}", method, chat, response.status)),
+        // the url contains the bot token, so only the reason is passed on
+        Err(Error::Http { reason, .. }) => failures.push(format!("{} to {} failed: {}", method, chat, reason)),
+        Err(e) => failures.push(e.to_string())
+      }
+    }
+    if !failures.is_empty() {
+      return Err(Error::Telegram(failures.join("; ")));
+    }
+    for chat in &chats {
+      database.forget_sent_message(ModuleType::Telegram.to_string(), &id, chat, &torrent.domain, torrent.id, &key).await?;
+    }
+    Ok(())
+  }
+}
+
+/// Tells the event apart from the torrent's others, comments by their author and date.
+fn delivery_key(event: &Event<'_>) -> String {
+  match event {
+    Event::Upload => event.name().to_string(),
+    Event::SeriesAlert(alert) => format!("{}/{}", event.name(), alert.message()),
+    Event::NewComment(comment) | Event::EditedComment(comment) | Event::DeletedComment(comment) =>
+      format!("{}/{}/{}", event.name(), comment.user.username, comment.date_timestamp as i64)
+  }
+}
+
+/// The Bot API method and its parameters, without the `chat_id`.
+/// Uploads are sent as a photo of the uploader's avatar if there is one.
+fn telegram_message(torrent: &NyaaTorrent, event: &Event<'_>) -> (&'static str, Value) {
+  let title = format!("<b>{}</b>", encode_text(&limit_string_length(&torrent.title, 200)));
+  let view_url = format!("{}view/{}", torrent.domain, torrent.id);
+  let (text, buttons) = match event {
+    Event::Upload => (
+      format!("{}\nCategory: {}\nSize: {}", title, encode_text(&torrent.category), encode_text(&torrent.size)),
+      [("Nyaa.si".to_string(), view_url), ("Torrent-File".to_string(), format!("{}download/{}.torrent", torrent.domain, torrent.id))]
+    ),
+    Event::SeriesAlert(alert) => (
+      format!("{}\n⚠️ {}", title, encode_text(&alert.message())),
+      [("Nyaa.si".to_string(), view_url), ("Torrent-File".to_string(), format!("{}download/{}.torrent", torrent.domain, torrent.id))]
+    ),
+    Event::NewComment(comment) | Event::EditedComment(comment) | Event::DeletedComment(comment) => {
+      let username = encode_text(&comment.user.username).to_string();
+      let message = encode_text(&limit_string_length(&comment.message, 3000)).to_string();
+      let text = match event {
+        Event::EditedComment(_) => format!("{}\n<b>{}</b> (edited comment):\n<s>{}</s>\n{}",
+          title, username, encode_text(&limit_string_length(&comment.old_message.clone().unwrap_or_default(), 500)), message),
+        Event::DeletedComment(_) => format!("{}\n<b>{}</b> (deleted comment):\n<s>{}</s>", title, username, message),
+        _ => format!("{}\n<b>{}</b>:\n{}", title, username, message)
+      };
+      let link = match event {
+        Event::DeletedComment(_) => view_url,
+        _ => comment.direct_link.clone()
+      };
+      (text, [("Comment@Nyaa.si".to_string(), link), (comment.user.username.clone(), format!("{}user/{}", torrent.domain, comment.user.username))])
+    }
+  };
+  let reply_markup = json!({
+    "inline_keyboard": [[
+      { "text": buttons[0].0, "url": buttons[0].1 },
+      { "text": buttons[1].0, "url": buttons[1].1 }
+    ]]
+  });
+
+  let avatar = torrent.uploader.as_ref().and_then(|uploader| uploader.avatar.clone()).filter(|avatar| avatar.starts_with("http"));
+  match (event, avatar) {
+    (Event::Upload, Some(avatar)) => ("sendPhoto", json!({
+      "photo": avatar,
+      "caption": text,
+      "parse_mode": "HTML",
+      "reply_markup": reply_markup
+    })),
+    _ => ("sendMessage", json!({
+      "text": text,
+      "parse_mode": "HTML",
+      "link_preview_options": { "is_disabled": true },
+      "reply_markup": reply_markup
+    }))
+  }
+}
@@ -0,0 +1,181 @@
+use hmac::{
+  Hmac, Mac
+};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{
+  json, Value
+};
+use sha2::Sha256;
+use std::sync::Arc;
+use async_trait::async_trait;
+
+use crate::config::ModuleConfig;
+use crate::database::Database;
+use crate::notifiers::{
+  Event, Notifier
+};
+use crate::transport::{
+  HttpRequest, Transport
+};
+use crate::web::NyaaTorrent;
+use crate::error::{
+  Error, Result
+};
+
+pub const SIGNATURE_HEADER: &str = "X-Nyaa-Signature-256";
+
+lazy_static! {
+  static ref PLACEHOLDER: Regex = Regex::new(r"\{([a-z_]+(?:\.[a-z_]+)*)\}").unwrap();
+}
+
+/// Posts a JSON document per event. Only a 2xx answer counts as delivered.
+pub struct WebhookNotifier {
+  transport: Arc<dyn Transport>
+}
+
+impl WebhookNotifier {
+  pub fn new(transport: Arc<dyn Transport>) -> Self {
+    WebhookNotifier { transport }
+  }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+  async fn send(&self, module: &ModuleConfig, _id: &str, _database: &mut Database, torrent: &NyaaTorrent, event: &Event<'_>) -> Result<()> {
+    let url = module.webhook_url.clone().ok_or_else(|| Error::Config("The webhook module is missing webhook_url".to_string()))?;
+    let fields = webhook_fields(torrent, event);
+    let body = match &module.webhook_template {
+      Some(template) => {
+        let template: Value = serde_json::from_str(template).map_err(|e| Error::Config(format!("The webhook_template isn't valid JSON: {}", e)))?;
+        fill_template(template, &fields)
+      },
+      None => fields
+    }.to_string();
+
+    let mut request = HttpRequest::post(&url).header("Content-Type", "application/json");
+    for (name, value) in module.webhook_headers.iter().flatten() {
+      request = request.header(name, value);
+    }
+    if let Some(secret) = &module.webhook_secret {
+      request = request.header(SIGNATURE_HEADER, &sign(secret, &body));
+    }
+    // webhook urls often carry a token, so only the host is passed on
+    let response = self.transport.http(request.body(body)).await.map_err(|e| match e {
+      Error::Http { reason, .. } => Error::Webhook(format!("Request to {} failed: {}", host(&url), reason)),
+      e => e
+    })?;
+    if !response.is_success() {
+      return Err(Error::Webhook(format!("Server answered with {}", response.status)));
+    }
+    Ok(())
+  }
+}
+
+/// The host of the url, without the credentials, path or query which could give away a secret.
+fn host(url: &str) -> &str {
+  let authority = url.split_once("://").map_or(url, |(_, rest)| rest).split(['/', '?', '#']).next().unwrap_or_default();
+  authority.rsplit_once('@').map_or(authority, |(_, host)| host)
+}
+
+/// Everything known about the event, which is also the body when there's no template.
+fn webhook_fields(torrent: &NyaaTorrent, event: &Event<'_>) -> Value {
+  let comment = match event {
+    Event::Upload | Event::SeriesAlert(_) => None,
+    Event::NewComment(comment) | Event::EditedComment(comment) | Event::DeletedComment(comment) => Some(comment)
+  };
+  let alert = match event {
+    Event::SeriesAlert(alert) => Some(alert.message()),
+    _ => None
+  };
+  json!({
+    "event": event.name(),
+    "torrent": {
+      "id": torrent.id,
+      "title": torrent.title,
+      "category": torrent.category,
+      "size": torrent.size,
+      "url": format!("{}view/{}", torrent.domain, torrent.id),
+      "download": format!("{}download/{}.torrent", torrent.domain, torrent.id),
+      "magnet": torrent.magnet_link,
+      "uploader": torrent.uploader.as_ref().map(|uploader| uploader.username.clone()),
+      "date": torrent.upload_date_str,
+      "timestamp": torrent.upload_date_timestamp as i64,
+      "seeders": torrent.seeders,
+      "leechers": torrent.leechers,
+      "completed": torrent.completed,
+      "comments": torrent.comments_amount,
+      "trusted": torrent.trusted,
+      "remake": torrent.remake,
+      "release": {
+        "group": torrent.release.group,
+        "series": torrent.release.series,
+        "season": torrent.release.season,
+        "episode": torrent.release.episode,
+        "last_episode": torrent.release.last_episode,
+        "version": torrent.release.version,
+        "resolution": torrent.release.resolution,
+        "codec": torrent.release.codec,
+        "source": torrent.release.source,
+        "crc": torrent.release.crc,
+        "summary": torrent.release.summary()
+      }
+    },
+    "comment": comment.map(|comment| json!({
+      "user": comment.user.username,
+      "role": comment.user.role,
+      "uploader": comment.uploader,
+      "message": comment.message,
+      "old_message": comment.old_message,
+      "url": comment.direct_link,
+      "timestamp": comment.date_timestamp as i64,
+      "edited_timestamp": comment.edited_timestamp.map(|timestamp| timestamp as i64)
+    })),
+    "alert": alert
+  })
+}
+
+/// Replaces `{path.to.field}` in the template's strings with the field's value.
+/// A string which is nothing but a placeholder takes the field's JSON type, nulls become empty strings elsewhere
+/// and unknown placeholders are left alone.
+fn fill_template(template: Value, fields: &Value) -> Value {
+  match template {
+    Value::String(text) => {
+      if let Some(captures) = PLACEHOLDER.captures(&text) {
+        if captures[0].len() == text.len() {
+          if let Some(value) = lookup(fields, &captures[1]) {
+            return value.clone();
+          }
+        }
+      }
+      Value::String(PLACEHOLDER.replace_all(&text, |captures: &regex::Captures| {
+        match lookup(fields, &captures[1]) {
+          Some(Value::String(value)) => value.clone(),
+          Some(Value::Null) => String::new(),
+          Some(value) => value.to_string(),
+          None => captures[0].to_string()
+        }
+      }).to_string())
+    },
+    Value::Array(values) => Value::Array(values.into_iter().map(|value| fill_template(value, fields)).collect()),
+    Value::Object(entries) => Value::Object(entries.into_iter().map(|(key, value)| (key, fill_template(value, fields))).collect()),
+    other => other
+  }
+}
+
+/// Paths running into a null (like `comment.user` for uploads) are null as well.
+fn lookup<'a>(fields: &'a Value, path: &str) -> Option<&'a Value> {
+  path.split('.').try_fold(fields, |value, key| if value.is_null() {
+    Some(value)
+  } else {
+    value.get(key)
+  })
+}
+
+/// `sha256=<hex>` of the body, keyed with the secret.
+pub fn sign(secret: &str, body: &str) -> String {
+  let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+  mac.update(body.as_bytes());
+  let digest = mac.finalize().into_bytes();
+  format!("sha256={}", digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
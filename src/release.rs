@@ -0,0 +1,228 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{
+  Deserialize, Serialize
+};
+
+lazy_static! {
+  static ref EXTENSION: Regex = Regex::new(r"(?i)\.(mkv|mp4|avi|ts|m2ts|webm)$").unwrap();
+  static ref LEADING_GROUP: Regex = Regex::new(r"^\s*[\[(]([^\])]+)[\])]\s*").unwrap();
+  static ref SCENE_GROUP: Regex = Regex::new(r"-([A-Za-z0-9]+)$").unwrap();
+  static ref TAG: Regex = Regex::new(r"[\[(]([^\])]*)[\])]").unwrap();
+  static ref CRC: Regex = Regex::new(r"[\[(]([0-9A-Fa-f]{8})[\])]").unwrap();
+  static ref RESOLUTION: Regex = Regex::new(r"(?i)\b(?:(\d{3,4})p|\d{3,4}x(\d{3,4})|(4k|uhd))\b").unwrap();
+  static ref CODEC: Regex = Regex::new(r"(?i)\b(x[ .]?264|h[ .]?264|avc|x[ .]?265|h[ .]?265|hevc|av1|xvid)\b").unwrap();
+  static ref SOURCE: Regex = Regex::new(r"(?i)\b(web[ -]?dl|web[ -]?rip|web|blu[ -]?ray|bd[ -]?rip|bdmv|bd|dvd[ -]?rip|dvd|hdtv|tv[ -]?rip)\b").unwrap();
+  static ref SEASON_EPISODE: Regex = Regex::new(r"(?i)\bS(\d{1,2}) ?E(\d{1,4})(?:v(\d))?(?: ?- ?E?(\d{1,4}))?\b").unwrap();
+  static ref DASH_EPISODE: Regex = Regex::new(r" - (\d{1,4})(?:v(\d))?(?: ?[-~] ?(\d{1,4})(?:v\d)?)?(?: |$)").unwrap();
+  static ref EPISODE: Regex = Regex::new(r"(?i)\b(?:E|EP|Episode ?)(\d{1,4})(?:v(\d))?\b").unwrap();
+  static ref RANGE: Regex = Regex::new(r"(?:^| )(\d{1,3}) ?[-~] ?(\d{1,3})(?: |$)").unwrap();
+  static ref SEASON: Regex = Regex::new(r"(?i) +(?:S(\d{1,2})|Season (\d{1,2})|(\d{1,2})(?:st|nd|rd|th) Season)$").unwrap();
+}
+
+/// What a release name like `[SubsPlease] Show Name - 07 (1080p) [ABCD1234].mkv` says about the torrent.
+/// Everything is optional, titles follow a few conventions at best.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Release {
+  pub group: Option<String>,
+  pub series: Option<String>,
+  pub season: Option<u32>,
+  pub episode: Option<u32>,
+  /// The last episode of a batch, `episode` being its first.
+  pub last_episode: Option<u32>,
+  /// The `2` of `07v2`, for fixed re-releases.
+  pub version: Option<u32>,
+  /// Like `1080p`.
+  pub resolution: Option<String>,
+  /// `H.264`, `HEVC`, `AV1` or `XviD`.
+  pub codec: Option<String>,
+  /// `WEB`, `BD`, `DVD` or `TV`.
+  pub source: Option<String>,
+  pub crc: Option<String>
+}
+
+impl Release {
+  pub fn parse(title: &str) -> Self {
+    let mut rest = EXTENSION.replace(title.trim(), "").to_string();
+    let mut release = Release::default();
+    if let Some(captures) = LEADING_GROUP.captures(&rest) {
+      release.group = Some(captures[1].trim().to_string());
+      rest = rest[captures[0].len()..].to_string();
+    }
+    // scene and some fansub names use dots or underscores instead of spaces
+    if !rest.contains(' ') {
+      rest = rest.replace(['.', '_'], " ");
+      if release.group.is_none() {
+        if let Some(captures) = SCENE_GROUP.captures(&rest) {
+          release.group = Some(captures[1].to_string());
+          rest.truncate(rest.len() - captures[0].len());
+        }
+      }
+    }
+
+    release.crc = CRC.captures_iter(&rest).last().map(|captures| captures[1].to_uppercase());
+    release.resolution = RESOLUTION.captures(&rest).and_then(|captures| {
+      let height = captures.get(1).or(captures.get(2)).map(|height| height.as_str()).unwrap_or("2160");
+      normalize_resolution(height)
+    });
+    release.codec = CODEC.find(&rest).and_then(|codec| normalize_codec(codec.as_str()));
+    release.source = SOURCE.find(&rest).and_then(|source| normalize_source(source.as_str()));
+
+    let head = rest[..rest.find(['[', '(']).unwrap_or(rest.len())].trim();
+    let mut series = head;
+    if let Some(captures) = SEASON_EPISODE.captures(head) {
+      release.season = captures[1].parse().ok();
+      release.episode = captures[2].parse().ok();
+      release.version = captures.get(3).and_then(|version| version.as_str().parse().ok());
+      release.last_episode = captures.get(4).and_then(|last| last.as_str().parse().ok());
+      series = &head[..captures.get(0).unwrap().start()];
+    } else if let Some(captures) = DASH_EPISODE.captures_iter(head).last() {
+      release.episode = captures[1].parse().ok();
+      release.version = captures.get(2).and_then(|version| version.as_str().parse().ok());
+      release.last_episode = captures.get(3).and_then(|last| last.as_str().parse().ok());
+      series = &head[..captures.get(0).unwrap().start()];
+    } else if let Some(captures) = EPISODE.captures(head) {
+      release.episode = captures[1].parse().ok();
+      release.version = captures.get(2).and_then(|version| version.as_str().parse().ok());
+      series = &head[..captures.get(0).unwrap().start()];
+    } else if let Some(captures) = TAG.captures_iter(&rest).find_map(|tag| RANGE.captures(&tag[1]).map(|range| (range[1].to_string(), range[2].to_string()))) {
+      release.episode = captures.0.parse().ok();
+      release.last_episode = captures.1.parse().ok();
+    }
+
+    // without brackets the technical part follows the name directly
+    let technical = [&*RESOLUTION, &*CODEC, &*SOURCE].iter().filter_map(|regex| regex.find(series)).map(|found| found.start()).min();
+    let mut series = series[..technical.unwrap_or(series.len())].trim().trim_end_matches('-').trim().to_string();
+    if let Some(captures) = SEASON.captures(&series) {
+      release.season = release.season.or(captures.iter().skip(1).flatten().next().and_then(|season| season.as_str().parse().ok()));
+      series.truncate(captures.get(0).unwrap().start());
+    }
+    release.series = (!series.is_empty()).then_some(series);
+    release
+  }
+
+  pub fn is_batch(&self) -> bool {
+    self.last_episode.is_some()
+  }
+
+  /// Like "Episode 7 of Show Name by SubsPlease", if the series could be made out.
+  pub fn summary(&self) -> Option<String> {
+    let series = self.series.as_ref()?;
+    let mut summary = match (self.episode, self.last_episode) {
+      (Some(first), Some(last)) => format!("Episodes {}-{} of {}", first, last, series),
+      (Some(episode), None) => format!("Episode {} of {}", episode, series),
+      _ => series.clone()
+    };
+    if let Some(season) = self.season {
+      summary.push_str(&format!(" (season {})", season));
+    }
+    if let Some(group) = &self.group {
+      summary.push_str(&format!(" by {}", group));
+    }
+    Some(summary)
+  }
+}
+
+/// `1080`, `1080p` and `1920x1080` all become `1080p`.
+pub(crate) fn normalize_resolution(resolution: &str) -> Option<String> {
+  let resolution = resolution.trim().to_lowercase();
+  let height = match resolution.as_str() {
+    "4k" | "uhd" => "2160",
+    other => other.rsplit('x').next().unwrap_or(other).trim_end_matches('p')
+  };
+  (!height.is_empty() && height.chars().all(|c| c.is_ascii_digit())).then(|| format!("{}p", height))
+}
+
+pub(crate) fn normalize_codec(codec: &str) -> Option<String> {
+  let codec = codec.to_lowercase();
+  let name = if codec.contains("264") || codec == "avc" {
+    "H.264"
+  } else if codec.contains("265") || codec == "hevc" {
+    "HEVC"
+  } else if codec == "av1" {
+    "AV1"
+  } else if codec == "xvid" {
+    "XviD"
+  } else {
+    return None;
+  };
+  Some(name.to_string())
+}
+
+pub(crate) fn normalize_source(source: &str) -> Option<String> {
+  let source = source.to_lowercase().replace([' ', '-'], "");
+  let name = if source.starts_with("web") {
+    "WEB"
+  } else if source.starts_with("bd") || source == "bluray" {
+    "BD"
+  } else if source.starts_with("dvd") {
+    "DVD"
+  } else if source == "hdtv" || source == "tvrip" || source == "tv" {
+    "TV"
+  } else {
+    return None;
+  };
+  Some(name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn fansub_release() {
+    let release = Release::parse("[SubsPlease] Sousou no Frieren - 02 (1080p) [8B7A61A2].mkv");
+    assert_eq!(release, Release {
+      group: Some("SubsPlease".to_string()),
+      series: Some("Sousou no Frieren".to_string()),
+      season: None,
+      episode: Some(2),
+      last_episode: None,
+      version: None,
+      resolution: Some("1080p".to_string()),
+      codec: None,
+      source: None,
+      crc: Some("8B7A61A2".to_string())
+    });
+    assert_eq!(release.summary().as_deref(), Some("Episode 2 of Sousou no Frieren by SubsPlease"));
+  }
+
+  #[test]
+  fn seasons_versions_and_tags() {
+    let release = Release::parse("[ASW] Kimetsu no Yaiba S2 - 07v2 [1080p HEVC x265 10Bit][AAC]");
+    assert_eq!((release.series.as_deref(), release.season, release.episode, release.version), (Some("Kimetsu no Yaiba"), Some(2), Some(7), Some(2)));
+    assert_eq!((release.resolution.as_deref(), release.codec.as_deref()), (Some("1080p"), Some("HEVC")));
+
+    let release = Release::parse("[Erai-raws] Shingeki no Kyojin 3rd Season - 12 [720p][Multiple Subtitle]");
+    assert_eq!((release.group.as_deref(), release.series.as_deref(), release.season), (Some("Erai-raws"), Some("Shingeki no Kyojin"), Some(3)));
+  }
+
+  #[test]
+  fn batches() {
+    let release = Release::parse("[Judas] Mob Psycho 100 (01-12) [BD 1080p][Batch]");
+    assert_eq!((release.series.as_deref(), release.episode, release.last_episode), (Some("Mob Psycho 100"), Some(1), Some(12)));
+    assert_eq!(release.source.as_deref(), Some("BD"));
+    assert!(release.is_batch());
+    assert_eq!(release.summary().as_deref(), Some("Episodes 1-12 of Mob Psycho 100 by Judas"));
+
+    let release = Release::parse("[Group] Show - 01 ~ 24 (1080p)");
+    assert_eq!((release.episode, release.last_episode), (Some(1), Some(24)));
+  }
+
+  #[test]
+  fn scene_release() {
+    let release = Release::parse("Show.Name.S01E07.1080p.WEB.H264-GROUP.mkv");
+    assert_eq!(release.group.as_deref(), Some("GROUP"));
+    assert_eq!(release.series.as_deref(), Some("Show Name"));
+    assert_eq!((release.season, release.episode), (Some(1), Some(7)));
+    assert_eq!((release.resolution.as_deref(), release.source.as_deref(), release.codec.as_deref()), (Some("1080p"), Some("WEB"), Some("H.264")));
+  }
+
+  #[test]
+  fn titles_without_conventions() {
+    let release = Release::parse("Some artbook collection");
+    assert_eq!(release.series.as_deref(), Some("Some artbook collection"));
+    assert_eq!((release.group, release.episode, release.crc), (None, None, None));
+    assert_eq!(Release::parse("").summary(), None);
+  }
+}
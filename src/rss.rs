@@ -0,0 +1,156 @@
+use quick_xml::{
+  events::Event, Reader
+};
+
+use crate::release::Release;
+use crate::web::NyaaTorrent;
+use crate::error::{
+  Error, Result
+};
+
+// The same trackers nyaa puts into the magnet links of its listings.
+const NYAA_TRACKERS: [&str; 5] = [
+  "http://nyaa.tracker.wf:7777/announce",
+  "udp://open.stealth.si:80/announce",
+  "udp://tracker.opentrackr.org:1337/announce",
+  "udp://exodus.desync.com:6969/announce",
+  "udp://tracker.torrent.eu.org:451/announce"
+];
+
+#[derive(Default)]
+struct RssItem {
+  title: String,
+  guid: String,
+  pub_date: String,
+  seeders: String,
+  leechers: String,
+  downloads: String,
+  info_hash: String,
+  category: String,
+  size: String,
+  comments: String,
+  trusted: String,
+  remake: String
+}
+
+/// Turns nyaa's `?page=rss` feed into the same torrents the listing html would give, minus the uploader.
+pub fn serialize_rss(xml: &str, domain: &str) -> Result<Vec<NyaaTorrent>> {
+  let mut reader = Reader::from_str(xml);
+  reader.trim_text(true);
+  let mut torrents: Vec<NyaaTorrent> = vec![];
+  let mut item: Option<RssItem> = None;
+  let mut element: Vec<u8> = vec![];
+  loop {
+    match reader.read_event().map_err(|e| Error::parse("rss feed", e))? {
+      Event::Start(start) => {
+        element = start.name().as_ref().to_vec();
+        if element == b"item" {
+          item = Some(RssItem::default());
+        }
+      },
+      Event::Text(text) => {
+        if let Some(item) = item.as_mut() {
+          let text = text.unescape().map_err(|e| Error::parse("rss feed", e))?;
+          push_field(item, &element, &text);
+        }
+      },
+      Event::CData(text) => {
+        if let Some(item) = item.as_mut() {
+          push_field(item, &element, &String::from_utf8_lossy(&text));
+        }
+      },
+      Event::End(end) => {
+        if end.name().as_ref() == b"item" {
+          if let Some(item) = item.take() {
+            torrents.append(&mut vec![torrent_from_item(item, domain)?]);
+          }
+        }
+        element.clear();
+      },
+      Event::Eof => break,
+      _ => {}
+    }
+  }
+
+  if torrents.is_empty() && !xml.contains("<channel>") {
+    return Err(Error::parse("rss feed", "This is not an rss feed!"));
+  }
+  Ok(torrents)
+}
+
+fn push_field(item: &mut RssItem, element: &[u8], text: &str) {
+  let field = match element {
+    b"title" => &mut item.title,
+    b"guid" => &mut item.guid,
+    b"pubDate" => &mut item.pub_date,
+    b"nyaa:seeders" => &mut item.seeders,
+    b"nyaa:leechers" => &mut item.leechers,
+    b"nyaa:downloads" => &mut item.downloads,
+    b"nyaa:infoHash" => &mut item.info_hash,
+    b"nyaa:category" => &mut item.category,
+    b"nyaa:size" => &mut item.size,
+    b"nyaa:comments" => &mut item.comments,
+    b"nyaa:trusted" => &mut item.trusted,
+    b"nyaa:remake" => &mut item.remake,
+    _ => return
+  };
+  field.push_str(text);
+}
+
+fn torrent_from_item(item: RssItem, domain: &str) -> Result<NyaaTorrent> {
+  let id = item.guid.trim_end_matches('/').rsplit('/').next()
+    .and_then(|id| id.parse::<u64>().ok())
+    .ok_or_else(|| Error::parse("rss feed", format!("No torrent id in {:?}", item.guid)))?;
+  let date = chrono::DateTime::parse_from_rfc2822(&item.pub_date)
+    .map_err(|e| Error::parse("upload date", e))?
+  .with_timezone(&chrono::Utc);
+  Ok(NyaaTorrent {
+    id,
+    domain: domain.to_owned(),
+    magnet_link: magnet_link(&item.info_hash, &item.title),
+    release: Release::parse(&item.title),
+    title: item.title,
+    category: item.category,
+    size: item.size,
+    uploader: None,
+    upload_date_str: date.format("%Y-%m-%d %H:%M").to_string(),
+    upload_date_timestamp: date.timestamp() as f64,
+    seeders: item.seeders.parse::<u64>().map_err(|e| Error::parse("seeders", e))?,
+    leechers: item.leechers.parse::<u64>().map_err(|e| Error::parse("leechers", e))?,
+    completed: item.downloads.parse::<u64>().map_err(|e| Error::parse("completed", e))?,
+    trusted: item.trusted == "Yes",
+    remake: item.remake == "Yes",
+    comments_amount: item.comments.parse::<u64>().map_err(|e| Error::parse("comment amount", e))?,
+    comments: vec![]
+  })
+}
+
+fn magnet_link(info_hash: &str, title: &str) -> String {
+  let mut magnet = format!("magnet:?xt=urn:btih:{}&dn={}", info_hash, percent_encode(title));
+  for tracker in NYAA_TRACKERS {
+    magnet.push_str(&format!("&tr={}", percent_encode(tracker)));
+  }
+  magnet
+}
+
+pub(crate) fn percent_encode(text: &str) -> String {
+  text.bytes().map(|byte| match byte {
+    b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+    _ => format!("%{:02X}", byte)
+  }).collect()
+}
+
+/// `https://nyaa.si/user/name?q=x` becomes `https://nyaa.si/?page=rss&u=name&q=x`.
+pub fn rss_url(feed_url: &str, domain: &str) -> String {
+  let path = feed_url.trim_start_matches(domain);
+  let (path, query) = path.split_once('?').unwrap_or((path, ""));
+  let mut url = format!("{}?page=rss", domain);
+  if let Some(user) = path.strip_prefix("user/") {
+    url.push_str(&format!("&u={}", user.trim_end_matches('/')));
+  }
+  // there is only one page of rss
+  for parameter in query.split('&').filter(|parameter| !parameter.is_empty() && !parameter.starts_with("p=")) {
+    url.push_str(&format!("&{}", parameter));
+  }
+  url
+}
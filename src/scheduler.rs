@@ -0,0 +1,398 @@
+use chrono::{
+  DateTime, Utc
+};
+use serde::Serialize;
+use sqlx::{
+  Pool, Sqlite
+};
+use std::{
+  collections::{BTreeMap, HashMap}, future::Future, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex, RwLock}, time::{Duration, Instant}
+};
+use tokio::{
+  sync::{mpsc, watch, Notify}, task::JoinHandle
+};
+use tracing::{
+  debug, error, info, info_span, warn, Instrument
+};
+
+use crate::config::{
+  Config, ModuleConfig, ModuleType
+};
+use crate::database::Database;
+use crate::filter::{
+  seen, Filter
+};
+use crate::metrics::{
+  POLL_DURATION, UPDATES_DETECTED
+};
+use crate::notifications::Notifications;
+use crate::series;
+use crate::web::{
+  NyaaCommentUpdateType, Web
+};
+use crate::error::{
+  Error, Result
+};
+
+// Channels are added, changed and paused through slash commands, so they're looked up more often than feeds are checked.
+const DISCORD_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Every running module by its key (`Gotify_1`, `Discord_<bot>_<channel>`, ...), shared with the api.
+pub type Modules = Arc<RwLock<BTreeMap<String, Arc<ModuleControl>>>>;
+
+/// How a module's task is doing, and the handles to pause it or have it check right away.
+pub struct ModuleControl {
+  pub module_type: ModuleType,
+  pub id: String,
+  pub interval: Duration,
+  paused: AtomicBool,
+  check_now: Notify,
+  status: Mutex<ModuleStatus>
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModuleStatus {
+  pub checking: bool,
+  pub last_check: Option<DateTime<Utc>>,
+  pub last_error: Option<String>
+}
+
+impl ModuleControl {
+  fn new(module_type: ModuleType, id: String, interval: Duration) -> Self {
+    ModuleControl { module_type, id, interval, paused: AtomicBool::new(false), check_now: Notify::new(), status: Mutex::default() }
+  }
+
+  pub fn status(&self) -> ModuleStatus {
+    self.status.lock().unwrap().clone()
+  }
+
+  /// Paused modules keep their schedule but skip the checks, until they're resumed or the program restarts.
+  pub fn pause(&self, paused: bool) {
+    self.paused.store(paused, Ordering::Relaxed);
+  }
+
+  pub fn is_paused(&self) -> bool {
+    self.paused.load(Ordering::Relaxed)
+  }
+
+  /// Starts a check without waiting for the interval, or right after the running one.
+  pub fn check_now(&self) {
+    self.check_now.notify_one();
+  }
+}
+
+/// Runs every module as its own task, so that a slow module doesn't hold back the others.
+pub struct Scheduler {
+  web: Arc<Web>,
+  modules: Modules,
+  notifications: Notifications,
+  database_pool: Pool<Sqlite>,
+  update_interval: u64,
+  tasks: HashMap<String, ModuleTask>,
+  stopped: HashMap<String, JoinHandle<()>>,
+  fatal_sender: mpsc::UnboundedSender<Error>,
+  fatal_receiver: mpsc::UnboundedReceiver<Error>
+}
+
+struct ModuleTask {
+  module: ModuleConfig,
+  stop: watch::Sender<bool>,
+  handle: JoinHandle<()>
+}
+
+impl Scheduler {
+  pub fn new(config: &Config, notifications: Notifications, database: &Database) -> Self {
+    // Pages are shared for half of the shortest interval, long enough for modules sharing a feed but never across two checks of the same module.
+    let shortest_interval = config.module.iter()
+      .filter_map(|module| module.update_interval)
+      .fold(config.update_interval, u64::min);
+    let (fatal_sender, fatal_receiver) = mpsc::unbounded_channel();
+    Scheduler {
+      web: Arc::new(Web::new(Duration::from_secs(shortest_interval * 30))),
+      modules: Modules::default(),
+      notifications,
+      database_pool: database.database.clone(),
+      update_interval: config.update_interval,
+      tasks: HashMap::new(),
+      stopped: HashMap::new(),
+      fatal_sender,
+      fatal_receiver
+    }
+  }
+
+  /// Polls through the given `Web` instead, which is how the tests point it at a fake nyaa.
+  pub fn with_web(mut self, web: Web) -> Self {
+    self.web = Arc::new(web);
+    self
+  }
+
+  /// The running modules, kept up to date while the scheduler runs.
+  pub fn modules(&self) -> Modules {
+    self.modules.clone()
+  }
+
+  /// Checks each module a single time, one after the other, and returns how every check went.
+  pub async fn check_once(self, modules: Vec<ModuleConfig>) -> Result<Vec<(String, Result<()>)>> {
+    let mut database = Database::use_pool(self.database_pool.clone()).await?;
+    let mut results = vec![];
+    for (id, module) in polled_modules(&modules, &mut database).await? {
+      let key = module_key(&module.module_type, &id);
+      let result = check_module(&self.web, &self.notifications, &mut database, &module, &id)
+        .instrument(info_span!("module", module = %key)).await;
+      results.push((key, result));
+    }
+    Ok(results)
+  }
+
+  /// Polls until ctrl+c or SIGTERM is received, or until one of the modules runs into an unrecoverable database error.
+  pub async fn run(self, modules: Vec<ModuleConfig>) -> Result<()> {
+    self.run_until(modules, shutdown_signal()).await
+  }
+
+  /// Like `run`, but stops once `shutdown` completes. Checks which are running by then are finished first.
+  pub async fn run_until(mut self, modules: Vec<ModuleConfig>, shutdown: impl Future<Output = ()>) -> Result<()> {
+    let mut discord_bot_id: Option<String> = None;
+    for (index, module) in modules.into_iter().enumerate() {
+      if !module.active {
+        continue;
+      }
+      if module.module_type == ModuleType::Discord && module.discord_token.is_some() {
+        // the bot itself is not a subscription, its channels are
+        discord_bot_id = module.discord_bot_id.clone();
+        continue;
+      }
+      self.spawn(module, index.to_string());
+    }
+
+    tokio::pin!(shutdown);
+    let mut discord_refresh = tokio::time::interval(DISCORD_REFRESH_INTERVAL);
+    let result = loop {
+      tokio::select! {
+        _ = &mut shutdown => {
+          info!("Shutting down, waiting for running checks to finish...");
+          break Ok(());
+        },
+        Some(e) = self.fatal_receiver.recv() => {
+          break Err(e);
+        },
+        _ = discord_refresh.tick(), if discord_bot_id.is_some() => {
+          if let Err(e) = self.refresh_discord_channels(discord_bot_id.as_ref().unwrap()).await {
+            break Err(e);
+          }
+        }
+      }
+    };
+
+    self.shutdown().await;
+    result
+  }
+
+  fn spawn(&mut self, module: ModuleConfig, id: String) {
+    let key = module_key(&module.module_type, &id);
+    let (stop, stop_receiver) = watch::channel(false);
+    // A replaced task may still be delivering, the new one has to wait for it to avoid sending updates twice.
+    let previous = self.tasks.remove(&key).map(|task| {
+      let _ = task.stop.send(true);
+      task.handle
+    }).or_else(|| self.stopped.remove(&key));
+    let interval = Duration::from_secs(module.update_interval.unwrap_or(self.update_interval) * 60);
+    debug!("Starting {} with an interval of {:?}.", key, interval);
+    let control = Arc::new(ModuleControl::new(module.module_type.clone(), id.clone(), interval));
+    // a changed discord channel is still the same module to whoever paused it
+    if let Some(previous) = self.modules.read().unwrap().get(&key) {
+      control.pause(previous.is_paused());
+      *control.status.lock().unwrap() = previous.status();
+    }
+    self.modules.write().unwrap().insert(key.clone(), control.clone());
+    let handle = tokio::spawn(poll_module(
+      self.web.clone(),
+      self.notifications.clone(),
+      self.database_pool.clone(),
+      module.clone(),
+      id,
+      control,
+      previous,
+      stop_receiver,
+      self.fatal_sender.clone()
+    ).instrument(info_span!("module", module = %key)));
+    self.tasks.insert(key, ModuleTask { module, stop, handle });
+  }
+
+  async fn refresh_discord_channels(&mut self, discord_bot_id: &String) -> Result<()> {
+    debug!("Refreshing discord modules.");
+    let mut database = Database::use_pool(self.database_pool.clone()).await?;
+    let channels: HashMap<String, ModuleConfig> = database.get_discord_channels(discord_bot_id).await?
+      .into_iter()
+      .filter(|channel| channel.active)
+      .map(|channel| (discord_bot_id.to_string()+"_"+&channel.discord_channel_id.unwrap().to_string(), channel))
+      .collect();
+
+    let removed: Vec<String> = self.tasks.iter()
+      .filter(|(key, task)| task.module.module_type == ModuleType::Discord &&
+      !channels.keys().any(|id| module_key(&ModuleType::Discord, id) == **key))
+      .map(|(key, _)| key.clone())
+      .collect();
+    for key in removed {
+      debug!("Stopping {}.", key);
+      self.modules.write().unwrap().remove(&key);
+      if let Some(task) = self.tasks.remove(&key) {
+        let _ = task.stop.send(true);
+        self.stopped.insert(key, task.handle);
+      }
+    }
+
+    self.stopped.retain(|_, handle| !handle.is_finished());
+    for (id, channel) in channels {
+      let key = module_key(&ModuleType::Discord, &id);
+      if self.tasks.get(&key).is_none_or(|task| task.module != channel) {
+        self.spawn(channel, id);
+      }
+    }
+    Ok(())
+  }
+
+  async fn shutdown(self) {
+    for task in self.tasks.values() {
+      let _ = task.stop.send(true);
+    }
+    let handles = self.tasks.into_iter().map(|(key, task)| (key, task.handle)).chain(self.stopped);
+    for (key, handle) in handles {
+      if let Err(e) = handle.await {
+        error!("{} stopped unexpectedly: {}", key, e);
+      }
+    }
+  }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn poll_module(web: Arc<Web>, notifications: Notifications, database_pool: Pool<Sqlite>, module: ModuleConfig, id: String, control: Arc<ModuleControl>,
+previous: Option<JoinHandle<()>>, mut stop: watch::Receiver<bool>, fatal: mpsc::UnboundedSender<Error>) {
+  if let Some(previous) = previous {
+    let _ = previous.await;
+  }
+  let mut database = match Database::use_pool(database_pool).await {
+    Ok(database) => database,
+    Err(e) => {
+      let _ = fatal.send(e);
+      return;
+    }
+  };
+
+  while !*stop.borrow() {
+    if control.is_paused() {
+      debug!("Skipping the check, the module is paused.");
+    } else {
+      info!("Checking.");
+      control.status.lock().unwrap().checking = true;
+      let started = Instant::now();
+      let result = check_module(&web, &notifications, &mut database, &module, &id).await;
+      POLL_DURATION.observe(&[&module.module_type.to_string()], started.elapsed().as_secs_f64());
+      *control.status.lock().unwrap() = ModuleStatus {
+        checking: false,
+        last_check: Some(Utc::now()),
+        last_error: result.as_ref().err().map(|e| e.to_string())
+      };
+      if let Err(e) = result {
+        match e {
+          Error::Database { .. } => {
+            // A broken database won't fix itself, give up unless the connection can be restored.
+            error!("{}", e);
+            if let Err(e) = database.check_database_connection().await {
+              let _ = fatal.send(e);
+              return;
+            }
+          },
+          _ if e.is_transient() => {
+            warn!("The check will be retried next time: {}", e);
+          },
+          _ => {
+            error!("Skipping this check: {}", e);
+          }
+        }
+      }
+    }
+
+    debug!("Waiting {:?}.", control.interval);
+    tokio::select! {
+      _ = tokio::time::sleep(control.interval) => {},
+      _ = control.check_now.notified() => {},
+      _ = stop.changed() => {}
+    }
+  }
+}
+
+/// How a module is called in the api, the logs and the database: `Gotify_0`, `Discord_<bot>_<channel>`, ...
+pub fn module_key(module_type: &ModuleType, id: &str) -> String {
+  format!("{}_{}", module_type, id)
+}
+
+/// Every module of the configuration with its id, where an active discord bot stands for its channels.
+pub async fn configured_modules(modules: &[ModuleConfig], database: &mut Database) -> Result<Vec<(String, ModuleConfig)>> {
+  let mut configured = vec![];
+  for (index, module) in modules.iter().enumerate() {
+    if module.active && module.module_type == ModuleType::Discord && module.discord_token.is_some() {
+      let discord_bot_id = module.discord_bot_id.clone().unwrap_or_default();
+      for channel in database.get_discord_channels(&discord_bot_id).await? {
+        configured.push((format!("{}_{}", discord_bot_id, channel.discord_channel_id.unwrap()), channel));
+      }
+    } else {
+      configured.push((index.to_string(), module.clone()));
+    }
+  }
+  Ok(configured)
+}
+
+/// The modules `run` polls, with their ids.
+pub async fn polled_modules(modules: &[ModuleConfig], database: &mut Database) -> Result<Vec<(String, ModuleConfig)>> {
+  Ok(configured_modules(modules, database).await?.into_iter().filter(|(_, module)| module.active).collect())
+}
+
+/// One round for a module: fetch what changed, notify, and store what was delivered.
+pub async fn check_module(web: &Web, notifications: &Notifications, database: &mut Database, module: &ModuleConfig, id: &String) -> Result<()> {
+  debug!("Getting updates from nyaa.");
+  let updates = web.get_updates(module, id, database).await?;
+  for update in &updates {
+    if update.new_upload {
+      UPDATES_DETECTED.inc(&["UPLOAD"]);
+    }
+    for comment in update.torrent.comments.iter().filter(|comment| matches!(comment.update_type, NyaaCommentUpdateType::NEW | NyaaCommentUpdateType::EDITED | NyaaCommentUpdateType::DELETED)) {
+      UPDATES_DETECTED.inc(&[&comment.update_type.to_string()]);
+    }
+  }
+  // filtered out torrents are stored all the same, so that they don't come up as new later,
+  // while those waiting on their swarm are left out, so that they do
+  let (mut updates, filtered) = Filter::new(module)?.split(updates);
+  for update in filtered {
+    debug!(torrent_id = update.torrent.id, "Filtered out {:?}.", update.torrent.title);
+    database.update_db_table(module.module_type.to_string(), id, seen(update)).await?;
+  }
+  updates.reverse();
+  series::track(database, module, id, &mut updates).await?;
+  debug!("Sending updates:\n{:?}", updates);
+  for update in notifications.process_updates(module, id, database, updates).await? {
+    database.update_db_table(module.module_type.to_string(), id, update).await?;
+  }
+  Ok(())
+}
+
+async fn shutdown_signal() {
+  #[cfg(unix)]
+  {
+    let mut terminate = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+      Ok(terminate) => terminate,
+      Err(e) => {
+        warn!("Failed to listen for SIGTERM: {}", e);
+        let _ = tokio::signal::ctrl_c().await;
+        return;
+      }
+    };
+    tokio::select! {
+      _ = tokio::signal::ctrl_c() => {},
+      _ = terminate.recv() => {}
+    }
+  }
+  #[cfg(not(unix))]
+  {
+    let _ = tokio::signal::ctrl_c().await;
+  }
+}
@@ -0,0 +1,112 @@
+use crate::config::ModuleConfig;
+use crate::database::Database;
+use crate::release::Release;
+use crate::web::NyaaUpdate;
+use crate::error::Result;
+
+/// Something about a followed series which a new upload gave away. It's sent along with the upload.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SeriesAlert {
+  /// The episodes between the last one seen and the upload's never came.
+  MissingEpisodes { series: String, first: u32, last: u32 },
+  /// The upload's episode was seen before in the same resolution, `version` is set for fixed re-releases like a `v2`.
+  Duplicate { series: String, episode: u32, version: Option<u32> }
+}
+
+impl SeriesAlert {
+  pub fn message(&self) -> String {
+    match self {
+      SeriesAlert::MissingEpisodes { series, first, last } if first == last => format!("Episode {} of {} is missing.", first, series),
+      SeriesAlert::MissingEpisodes { series, first, last } => format!("Episodes {}-{} of {} are missing.", first, last, series),
+      SeriesAlert::Duplicate { series, episode, version: Some(version) } if *version > 1 =>
+        format!("Episode {} of {} was re-released as v{}.", episode, series, version),
+      SeriesAlert::Duplicate { series, episode, .. } => format!("Episode {} of {} was released again.", episode, series)
+    }
+  }
+}
+
+/// Fills in the alerts of new uploads, if the module has `series_alerts`.
+/// A series is followed per release group and season. The episodes stored for the module count as seen,
+/// and so do the ones of the updates before in the same check, which aren't stored yet.
+pub async fn track(database: &mut Database, module: &ModuleConfig, id: &String, updates: &mut [NyaaUpdate]) -> Result<()> {
+  if !module.series_alerts.unwrap_or_default() || !module.uploads.unwrap_or_default() {
+    return Ok(());
+  }
+  let mut checked: Vec<Release> = vec![];
+  for update in updates.iter_mut().filter(|update| update.new_upload) {
+    let release = &update.torrent.release;
+    if release.series.is_none() || release.episode.is_none() {
+      continue;
+    }
+    let mut seen = database.get_series_releases(module.module_type.to_string(), id, release).await?;
+    seen.extend(checked.iter().filter(|earlier| same_series(earlier, release)).cloned());
+    update.alerts = alerts(release, &seen);
+    checked.push(release.clone());
+  }
+  Ok(())
+}
+
+fn same_series(first: &Release, second: &Release) -> bool {
+  first.series == second.series && first.group == second.group && first.season == second.season
+}
+
+/// What a release says about its series, given the releases of it seen before. Nothing for the first one.
+pub fn alerts(release: &Release, seen: &[Release]) -> Vec<SeriesAlert> {
+  let (Some(series), Some(episode)) = (series_name(release), release.episode) else {
+    return vec![];
+  };
+  let mut alerts = vec![];
+  // batches collect episodes, they don't repeat them
+  let repeated = seen.iter().any(|earlier| !earlier.is_batch() && earlier.episode == Some(episode) && earlier.resolution == release.resolution);
+  if !release.is_batch() && repeated {
+    alerts.push(SeriesAlert::Duplicate { series: series.clone(), episode, version: release.version });
+  }
+  if let Some(latest) = seen.iter().filter_map(|earlier| earlier.last_episode.or(earlier.episode)).max() {
+    if episode > latest + 1 {
+      alerts.push(SeriesAlert::MissingEpisodes { series, first: latest + 1, last: episode - 1 });
+    }
+  }
+  alerts
+}
+
+/// Like "Show Name (season 2) by SubsPlease".
+fn series_name(release: &Release) -> Option<String> {
+  let mut name = release.series.clone()?;
+  if let Some(season) = release.season {
+    name.push_str(&format!(" (season {})", season));
+  }
+  if let Some(group) = &release.group {
+    name.push_str(&format!(" by {}", group));
+  }
+  Some(name)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn releases(titles: &[&str]) -> Vec<Release> {
+    titles.iter().map(|title| Release::parse(title)).collect()
+  }
+
+  #[test]
+  fn skipped_episodes() {
+    let seen = releases(&["[SubsPlease] Show - 03 (1080p)", "[SubsPlease] Show - 04 (1080p)"]);
+    assert_eq!(alerts(&Release::parse("[SubsPlease] Show - 05 (1080p)"), &seen), vec![]);
+    assert_eq!(alerts(&Release::parse("[SubsPlease] Show - 06 (1080p)"), &seen)[0].message(), "Episode 5 of Show by SubsPlease is missing.");
+    assert_eq!(alerts(&Release::parse("[SubsPlease] Show - 08 (1080p)"), &seen)[0].message(), "Episodes 5-7 of Show by SubsPlease are missing.");
+    // a batch counts up to its last episode
+    assert_eq!(alerts(&Release::parse("[SubsPlease] Show - 11 (1080p)"), &releases(&["[SubsPlease] Show (01-10) [1080p]"])), vec![]);
+    assert_eq!(alerts(&Release::parse("[SubsPlease] Show - 09 (1080p)"), &[]), vec![]);
+  }
+
+  #[test]
+  fn repeated_episodes() {
+    let seen = releases(&["[SubsPlease] Show - 04 (1080p)", "[SubsPlease] Show - 04 (720p)"]);
+    assert_eq!(alerts(&Release::parse("[SubsPlease] Show - 04v2 (1080p)"), &seen), vec![
+      SeriesAlert::Duplicate { series: "Show by SubsPlease".to_string(), episode: 4, version: Some(2) }
+    ]);
+    assert_eq!(alerts(&Release::parse("[SubsPlease] Show - 04 (480p)"), &seen), vec![]);
+    assert_eq!(alerts(&Release::parse("[SubsPlease] Show - 04 (720p)"), &seen)[0].message(), "Episode 4 of Show by SubsPlease was released again.");
+  }
+}
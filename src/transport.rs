@@ -0,0 +1,184 @@
+use serde_json::json;
+use std::{
+  sync::Arc, time::Duration
+};
+use isahc::{
+  prelude::Configurable, AsyncReadResponseExt, RequestExt, http::StatusCode
+};
+use lettre::{
+  AsyncSmtpTransport, Tokio1Executor, AsyncTransport,
+  transport::smtp::authentication::Credentials, Message
+};
+use serenity::{
+  all::CreateMessage, model::prelude::ChannelId, http::Http, async_trait
+};
+use tracing::debug;
+
+use crate::config::ModuleConfig;
+use crate::error::{
+  Error, Result
+};
+
+/// Everything that leaves the program on behalf of a module goes through here.
+/// The notifiers only build messages and keep the books, so tests can swap in a transport which records instead of sending.
+#[async_trait]
+pub trait Transport: Send + Sync {
+  async fn send_email(&self, module: &ModuleConfig, email: Message) -> Result<()>;
+  async fn send_gotify(&self, module: &ModuleConfig, title: &str, message: String, priority: u32) -> Result<()>;
+  /// Fails if the bot can't see the channel (anymore).
+  async fn check_discord_channel(&self, channel: ChannelId) -> Result<()>;
+  async fn send_discord(&self, channel: ChannelId, message: CreateMessage) -> Result<()>;
+  /// Plain HTTP for the notifiers which only need a request and its answer. Any status counts as a response,
+  /// the caller decides what it means.
+  async fn http(&self, request: HttpRequest) -> Result<HttpResponse>;
+  /// Waits between messages to stay clear of a service's rate limit, unless the transport doesn't care about them.
+  async fn pause(&self, _duration: Duration) {}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+  pub method: String,
+  pub url: String,
+  pub headers: Vec<(String, String)>,
+  pub body: String
+}
+
+impl HttpRequest {
+  pub fn new(method: &str, url: &str) -> Self {
+    HttpRequest { method: method.to_string(), url: url.to_string(), headers: vec![], body: String::new() }
+  }
+
+  pub fn post(url: &str) -> Self {
+    HttpRequest::new("POST", url)
+  }
+
+  pub fn header(mut self, name: &str, value: &str) -> Self {
+    self.headers.push((name.to_string(), value.to_string()));
+    self
+  }
+
+  pub fn body(mut self, body: String) -> Self {
+    self.body = body;
+    self
+  }
+
+  pub fn json(self, body: &serde_json::Value) -> Self {
+    self.header("Content-Type", "application/json").body(body.to_string())
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+  pub status: u16,
+  pub body: String
+}
+
+impl HttpResponse {
+  pub fn is_success(&self) -> bool {
+    (200..300).contains(&self.status)
+  }
+}
+
+/// Talks to the real SMTP relays, Gotify servers, Discord and whatever else is reached over HTTP.
+pub struct LiveTransport {
+  discord: Option<Arc<Http>>,
+  pauses: bool
+}
+
+impl LiveTransport {
+  pub fn new(discord: Option<Arc<Http>>) -> Self {
+    LiveTransport { discord, pauses: true }
+  }
+
+  /// Skips the pauses between messages, which are only there to stay clear of the services' rate limits.
+  pub fn without_pauses(mut self) -> Self {
+    self.pauses = false;
+    self
+  }
+
+  fn discord(&self) -> Result<&Arc<Http>> {
+    self.discord.as_ref().ok_or_else(|| Error::Discord("The discord client hasn't been started".to_string()))
+  }
+}
+
+#[async_trait]
+impl Transport for LiveTransport {
+  async fn send_email(&self, module: &ModuleConfig, email: Message) -> Result<()> {
+    let username = module.smtp_username.clone().ok_or_else(|| Error::Config("The email module is missing smtp_username".to_string()))?;
+    let smtp_creds = Credentials::new(username, module.smtp_password.clone().unwrap_or_default());
+    let domain = module.smtp_domain.clone().ok_or_else(|| Error::Config("The email module is missing smtp_domain".to_string()))?;
+    let mail = AsyncSmtpTransport::<Tokio1Executor>::relay(&domain)
+      .map_err(|e| Error::Smtp(format!("Invalid relay {:?}: {}", domain, e)))?
+      .credentials(smtp_creds)
+    .build();
+    mail.send(email).await.map_err(|e| Error::Smtp(e.to_string()))?;
+    Ok(())
+  }
+
+  async fn send_gotify(&self, module: &ModuleConfig, title: &str, message: String, priority: u32) -> Result<()> {
+    let json_body = json!({
+      "title": title,
+      "message": message,
+      "priority": priority
+    }).to_string();
+
+    let url = format!("{}/message?token={}", module.gotify_domain.clone().unwrap_or_default(), module.gotify_token.clone().unwrap_or_default());
+    let post_request = isahc::Request::post(url)
+      .header("Content-Type", "application/json")
+      .timeout(Duration::from_secs(5))
+      .body(json_body).map_err(|e| Error::Gotify(e.to_string()))?
+    .send_async().await;
+
+    self.pause(Duration::from_secs(2)).await;
+
+    match post_request {
+      Ok(request) if request.status() == StatusCode::OK => Ok(()),
+      Ok(request) => Err(Error::Gotify(format!("Server answered with {}", request.status()))),
+      Err(e) => Err(Error::Gotify(e.to_string()))
+    }
+  }
+
+  async fn check_discord_channel(&self, channel: ChannelId) -> Result<()> {
+    let http = self.discord()?;
+    for attempt in 1..=5 {
+      match channel.to_channel(http).await {
+        Ok(_) => break,
+        Err(e) if attempt == 5 => {
+          return Err(Error::Discord(format!("Channel {:?} is unreachable: {}", channel.get(), e)));
+        },
+        Err(_) => self.pause(Duration::from_secs(3)).await
+      }
+    }
+    Ok(())
+  }
+
+  async fn send_discord(&self, channel: ChannelId, message: CreateMessage) -> Result<()> {
+    let result = channel.send_message(self.discord()?, message).await;
+    self.pause(Duration::from_secs(1)).await;
+    if let Err(e) = result {
+      debug!("Discord refused the message: {:?}", e);
+      return Err(Error::Discord(e.to_string()));
+    }
+    Ok(())
+  }
+
+  async fn http(&self, request: HttpRequest) -> Result<HttpResponse> {
+    let mut builder = isahc::Request::builder()
+      .method(request.method.as_str())
+      .uri(request.url.as_str())
+      .timeout(Duration::from_secs(5));
+    for (name, value) in &request.headers {
+      builder = builder.header(name.as_str(), value.as_str());
+    }
+    let mut response = builder.body(request.body).map_err(|e| Error::http(&request.url, e))?
+      .send_async().await.map_err(|e| Error::http(&request.url, e))?;
+    let body = response.text().await.map_err(|e| Error::http(&request.url, e))?;
+    Ok(HttpResponse { status: response.status().as_u16(), body })
+  }
+
+  async fn pause(&self, duration: Duration) {
+    if self.pauses {
+      tokio::time::sleep(duration).await;
+    }
+  }
+}
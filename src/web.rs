@@ -1,27 +1,69 @@
 use std::{
-  thread, time::Duration
+  collections::HashMap, sync::Arc, time::Duration
 };
 use isahc::{
-  prelude::Configurable, RequestExt, http::StatusCode, ReadResponseExt
+  prelude::Configurable, AsyncReadResponseExt, RequestExt,
+  http::{header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RETRY_AFTER}, StatusCode}
 };
+use lazy_static::lazy_static;
 use serde::{
   Deserialize, Serialize
 };
+use tokio::{
+  sync::{Mutex, OwnedMutexGuard}, time::Instant
+};
+use tracing::{
+  info, info_span, warn, Instrument
+};
 
+use crate::paths;
 use crate::database::Database;
-use crate::config::{ModuleConfig, ModuleType};
+use crate::config::{FeedMode, ModuleConfig};
 use crate::discord::unix_to_datetime;
 use crate::html::{serialize_feed, serialize_torrent, serialize_user_page};
+use crate::release::Release;
+use crate::series::SeriesAlert;
+use crate::rss::{rss_url, serialize_rss};
+use crate::metrics::{
+  NYAA_REQUESTS, NYAA_RETRIES, PARSE_FAILURES
+};
+use crate::error::{
+  Error, Result
+};
+use crate::limiter::{
+  RateLimiter, backoff, parse_retry_after
+};
+use crate::cache::{
+  CachedPage, PageCache, PageKind
+};
+
+pub const DEFAULT_NYAA_REQUESTS_PER_MINUTE: u32 = 30;
+const NYAA_MAX_ATTEMPTS: u32 = 4;
 
+lazy_static! {
+  /// Every request to nyaa goes through this bucket, no matter which module asked for it.
+  pub static ref NYAA_LIMITER: RateLimiter = RateLimiter::new(DEFAULT_NYAA_REQUESTS_PER_MINUTE, 3);
+}
+
+/// Shared by all module tasks, so that feeds and torrent pages requested by several modules are only downloaded once.
 pub struct Web {
-  pub cache_users: Vec<NyaaUser>,
-  pub cache_pages: Vec<NyaaPage>
+  // avatars by user page url
+  cache_users: Mutex<HashMap<String, (Instant, String)>>,
+  // feeds by url and whether all pages have been retrieved
+  cache_pages: Mutex<HashMap<(String, bool), NyaaPage>>,
+  // full torrents by view url
+  cache_torrents: Mutex<HashMap<String, (Instant, NyaaTorrent)>>,
+  in_flight: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+  cache_ttl: Duration,
+  // downloaded pages survive restarts, so that they can be revalidated instead of downloaded again
+  page_cache: PageCache,
+  base_url: Option<String>
 }
 
 #[derive(Debug, Clone)]
 pub struct NyaaPage {
-  url: String,
   complete: bool,
+  fetched_at: Instant,
   torrents: Vec<NyaaTorrent>
 }
 
@@ -31,6 +73,8 @@ pub struct NyaaTorrent {
   pub id: u64,
   pub domain: String,
   pub title: String,
+  /// What the title says about the release, see `Release::parse`.
+  pub release: Release,
   pub category: String,
   pub size: String,
   pub magnet_link: String,
@@ -39,6 +83,10 @@ pub struct NyaaTorrent {
   pub seeders: u64,
   pub leechers: u64,
   pub completed: u64,
+  /// Uploaded by a trusted user, a green row on nyaa.
+  pub trusted: bool,
+  /// Marked as a remake, a red row on nyaa.
+  pub remake: bool,
   pub comments_amount: u64,
   pub comments: Vec<NyaaComment>
 }
@@ -74,166 +122,255 @@ pub enum NyaaCommentUpdateType {
   UNCHECKED
 }
 
+impl std::fmt::Display for NyaaCommentUpdateType {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      NyaaCommentUpdateType::NEW => {
+        write!(f, "NEW")
+      },
+      NyaaCommentUpdateType::EDITED => {
+        write!(f, "EDITED")
+      },
+      NyaaCommentUpdateType::DELETED => {
+        write!(f, "DELETED")
+      },
+      NyaaCommentUpdateType::UNDECIDED => {
+        write!(f, "UNDECIDED")
+      },
+      NyaaCommentUpdateType::UNCHECKED => {
+        write!(f, "UNCHECKED")
+      }
+    }
+  }
+}
+
+impl std::str::FromStr for NyaaCommentUpdateType {
+  type Err = String;
+
+  fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+    match s {
+      "NEW" => Ok(NyaaCommentUpdateType::NEW),
+      "EDITED" => Ok(NyaaCommentUpdateType::EDITED),
+      "DELETED" => Ok(NyaaCommentUpdateType::DELETED),
+      "UNDECIDED" => Ok(NyaaCommentUpdateType::UNDECIDED),
+      "UNCHECKED" => Ok(NyaaCommentUpdateType::UNCHECKED),
+      _ => Err(format!("Unknown comment update type {:?}", s))
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
 pub struct NyaaUpdate {
   pub new_upload: bool,
-  pub torrent: NyaaTorrent
+  pub torrent: NyaaTorrent,
+  /// The url of the feed the torrent was found on.
+  pub feed: String,
+  /// Filled in by `series::track` for new uploads, sent along with them.
+  pub alerts: Vec<SeriesAlert>
 }
 
-impl Default for Web {
-  fn default() -> Self {
-    Self::new()
+impl Web {
+  /// Cached pages are reused for `cache_ttl`, which should be shorter than the shortest update interval.
+  pub fn new(cache_ttl: Duration) -> Self {
+    Web {
+      cache_users: Mutex::new(HashMap::new()),
+      cache_pages: Mutex::new(HashMap::new()),
+      cache_torrents: Mutex::new(HashMap::new()),
+      in_flight: Mutex::new(HashMap::new()),
+      cache_ttl,
+      page_cache: PageCache::new(paths().cache()),
+      base_url: None
+    }
+  }
+
+  pub fn with_page_cache(mut self, page_cache: PageCache) -> Self {
+    self.page_cache = page_cache;
+    self
   }
-}
 
-impl Web {
-  fn new() -> Self {
-    Web { cache_users: vec![], cache_pages: vec![] }
+  /// Sends every request to `base_url` instead of the feed's domain, the stored torrents keep the original domain.
+  pub fn with_base_url(mut self, base_url: &str) -> Self {
+    self.base_url = Some(format!("{}/", base_url.trim_end_matches('/')));
+    self
   }
 
-  pub async fn get_updates(&mut self, module: &ModuleConfig, module_id: &String, database: &mut Database) -> Vec<NyaaUpdate> {
+  /// Requests for the same url wait for each other, the later ones will then find the result in the cache.
+  async fn fetch_lock(&self, url: &str) -> OwnedMutexGuard<()> {
+    let lock = {
+      let mut in_flight = self.in_flight.lock().await;
+      in_flight.retain(|_, lock| Arc::strong_count(lock) > 1);
+      in_flight.entry(url.to_string()).or_default().clone()
+    };
+    lock.lock_owned().await
+  }
+
+  pub async fn get_updates(&self, module: &ModuleConfig, module_id: &String, database: &mut Database) -> Result<Vec<NyaaUpdate>> {
     let mut updates: Vec<NyaaUpdate> = vec![];
     let mut table_exists: bool = true; // specifically needed for channels with multiple feeds, as everything goes into the same table
-    for url in &module.feeds.clone().unwrap() {
-      let mut feed = self.search_feed(url, module.retrieve_all_pages.unwrap());
-      // Check if table exist
-      database.check_database_connection().await.unwrap();
-      if database.data_table_exists(module.module_type.to_string(), module_id).await && table_exists {
-        let database_torrents = database.get_torrents_from_db(module.module_type.to_string(), module_id).await;
-        for torrent in feed.torrents.iter_mut() {
-          if let Some(db_torrent) = database_torrents.iter().find(|t| t.id == torrent.id) {
-            // Torrent is not new
-            if module.comments.unwrap() {
-              if db_torrent.comments_amount != torrent.comments_amount {
-                // If the current comment amount is 0 but the db one is not, then don't get the comments again.
-                if torrent.comments_amount == 0 {
-                  let mut update: NyaaTorrent = db_torrent.clone();
-                  for comment in update.comments.iter_mut() {
-                    comment.update_type = NyaaCommentUpdateType::DELETED;
-                  }
-                  update.comments_amount = 0;
-                  updates.append(&mut vec![NyaaUpdate {
-                    new_upload: false,
-                    torrent: update
-                  }]);
-                } else {
-                  if torrent.comments.is_empty() {
-                    if let Ok(full_torrent) = self.get_torrent(torrent.clone()) {
-                      *torrent = full_torrent.clone();
+    for feed_config in &module.feeds.clone().unwrap() {
+      let url = &feed_config.url().to_string();
+      async {
+        let mode = feed_config.mode().or(module.feed_mode).unwrap_or_default();
+        let mut feed = self.search_feed(url, module.retrieve_all_pages.unwrap(), mode).await?;
+        // Check if table exist
+        database.check_database_connection().await?;
+        if database.subscription_indexed(module.module_type.to_string(), module_id).await? && table_exists {
+          let database_torrents = database.get_torrents_from_db(module.module_type.to_string(), module_id).await?;
+          for torrent in feed.torrents.iter_mut() {
+            if let Some(db_torrent) = database_torrents.iter().find(|t| t.id == torrent.id) {
+              // Torrent is not new
+              if module.comments.unwrap() {
+                if db_torrent.comments_amount != torrent.comments_amount {
+                  // If the current comment amount is 0 but the db one is not, then don't get the comments again.
+                  if torrent.comments_amount == 0 {
+                    let mut update: NyaaTorrent = db_torrent.clone();
+                    for comment in update.comments.iter_mut() {
+                      comment.update_type = NyaaCommentUpdateType::DELETED;
                     }
-                  }
-                  let mut update: NyaaTorrent = torrent.clone();
-                  // find new / edited / deleted comments
-                  update.comments = self.find_comment_changes(torrent.clone(), db_torrent.clone());
-                  if update.comments.iter().any(|c| (c.update_type != NyaaCommentUpdateType::UNCHECKED) &&
-                  (c.update_type != NyaaCommentUpdateType::UNDECIDED)) {
+                    update.comments_amount = 0;
                     updates.append(&mut vec![NyaaUpdate {
                       new_upload: false,
-                      torrent: update
+                      torrent: update,
+                      feed: url.to_string(),
+                      alerts: vec![]
                     }]);
-                  }
-                }
-              } else if db_torrent.comments_amount != 0 {
-                // Check if there is a comment with the "new" type which is more than one hour old.
-                if db_torrent.comments.iter().any(|c| (c.update_type == NyaaCommentUpdateType::UNCHECKED) &&
-                (unix_to_datetime(c.date_timestamp)+chrono::Duration::hours(1) <= chrono::Utc::now())) {
-                  if torrent.comments.is_empty() {
-                    if let Ok(full_torrent) = self.get_torrent(torrent.clone()) {
-                      *torrent = full_torrent.clone();
+                  } else {
+                    if torrent.comments.is_empty() {
+                      if let Ok(full_torrent) = self.get_torrent(torrent.clone()).await.inspect_err(|e| warn!("{}", e)) {
+                        *torrent = full_torrent.clone();
+                      }
+                    }
+                    let mut update: NyaaTorrent = torrent.clone();
+                    // find new / edited / deleted comments
+                    update.comments = self.find_comment_changes(torrent.clone(), db_torrent.clone()).await;
+                    if update.comments.iter().any(|c| (c.update_type != NyaaCommentUpdateType::UNCHECKED) &&
+                    (c.update_type != NyaaCommentUpdateType::UNDECIDED)) {
+                      updates.append(&mut vec![NyaaUpdate {
+                        new_upload: false,
+                        torrent: update,
+                        feed: url.to_string(),
+                        alerts: vec![]
+                      }]);
                     }
                   }
-                  let mut update: NyaaTorrent = torrent.clone();
-                  // find new / edited / deleted comments
-                  update.comments = self.find_comment_changes(torrent.clone(), db_torrent.clone());
-                  if update.comments.iter().any(|c| c.update_type != NyaaCommentUpdateType::UNDECIDED) {
-                    updates.append(&mut vec![NyaaUpdate {
-                      new_upload: false,
-                      torrent: update
-                    }]);
+                } else if db_torrent.comments_amount != 0 {
+                  // Check if there is a comment with the "new" type which is more than one hour old.
+                  if db_torrent.comments.iter().any(|c| (c.update_type == NyaaCommentUpdateType::UNCHECKED) &&
+                  (unix_to_datetime(c.date_timestamp)+chrono::Duration::hours(1) <= chrono::Utc::now())) {
+                    if torrent.comments.is_empty() {
+                      if let Ok(full_torrent) = self.get_torrent(torrent.clone()).await.inspect_err(|e| warn!("{}", e)) {
+                        *torrent = full_torrent.clone();
+                      }
+                    }
+                    let mut update: NyaaTorrent = torrent.clone();
+                    // find new / edited / deleted comments
+                    update.comments = self.find_comment_changes(torrent.clone(), db_torrent.clone()).await;
+                    if update.comments.iter().any(|c| c.update_type != NyaaCommentUpdateType::UNDECIDED) {
+                      updates.append(&mut vec![NyaaUpdate {
+                        new_upload: false,
+                        torrent: update,
+                        feed: url.to_string(),
+                        alerts: vec![]
+                      }]);
+                    }
                   }
                 }
               }
-            }
-          } else {
-            // Torrent is new
-            // a few complicated if statements, because it's possible the torrent is cached
+            } else {
+              // Torrent is new
+              // a few complicated if statements, because it's possible the torrent is cached
             
-            if (torrent.comments.is_empty() && torrent.comments_amount != 0) && module.comments.unwrap() ||
-            (module.module_type == ModuleType::Discord && torrent.uploader.is_none()) {
-              if let Ok(full_torrent) = self.get_torrent(torrent.clone()) {
-                *torrent = full_torrent;
-                for comment in torrent.comments.iter_mut() {
-                  comment.update_type = NyaaCommentUpdateType::NEW;
+              if (torrent.comments.is_empty() && torrent.comments_amount != 0) && module.comments.unwrap() ||
+              (module.needs_uploader() && torrent.uploader.is_none()) {
+                if let Ok(full_torrent) = self.get_torrent(torrent.clone()).await.inspect_err(|e| warn!("{}", e)) {
+                  *torrent = full_torrent;
+                  for comment in torrent.comments.iter_mut() {
+                    comment.update_type = NyaaCommentUpdateType::NEW;
+                  }
                 }
               }
-            }
 
-            // see if uploader needed (and see if it has been retrieved above already)
-            if module.module_type == ModuleType::Discord {
-              if torrent.uploader.clone().unwrap().anonymous {
-                torrent.uploader = Some(NyaaUser {
-                  anonymous: true,
-                  role: "User".to_string(),
-                  username: "Anonymous".to_string(),
-                  avatar: Some(torrent.domain.clone()+"static/img/avatar/default.png"),
-                  banned: false
-                });
-              } else if let Ok(avatar) = self.get_user_avatar(torrent.clone()) {
-                let uploader = torrent.uploader.clone().unwrap();
-                torrent.uploader = Some(NyaaUser {
-                  anonymous: false,
-                  username: uploader.username,
-                  role: uploader.role,
-                  avatar: Some(avatar),
-                  banned: uploader.banned
-                });
-              } else {
+              // without the torrent page there is nothing to put into the embed's author field or to filter on,
+              // so the torrent is left for the next check
+              if module.needs_uploader() && torrent.uploader.is_none() {
                 continue;
               }
-            }
+              // see if uploader needed (and see if it has been retrieved above already)
+              if let (true, Some(uploader)) = (module.module_type.shows_uploader(), torrent.uploader.clone()) {
+                if uploader.anonymous {
+                  torrent.uploader = Some(NyaaUser {
+                    anonymous: true,
+                    role: "User".to_string(),
+                    username: "Anonymous".to_string(),
+                    avatar: Some(torrent.domain.clone()+"static/img/avatar/default.png"),
+                    banned: false
+                  });
+                } else if let Ok(avatar) = self.get_user_avatar(torrent.clone()).await.inspect_err(|e| warn!("{}", e)) {
+                  torrent.uploader = Some(NyaaUser {
+                    anonymous: false,
+                    username: uploader.username,
+                    role: uploader.role,
+                    avatar: Some(avatar),
+                    banned: uploader.banned
+                  });
+                } else {
+                  continue;
+                }
+              }
 
-            updates.append(&mut vec![NyaaUpdate {
-              new_upload: true,
-              torrent: torrent.clone()
-            }]);
-          }
-        }
-      } else {
-        // index all torrents but not as new torrents
-        for torrent in feed.torrents.iter_mut() {
-          if (torrent.comments_amount != 0) && module.comments.unwrap() {
-            if let Ok(full_torrent) = self.get_torrent(torrent.to_owned()) {
-              torrent.comments = full_torrent.comments;
+              updates.append(&mut vec![NyaaUpdate {
+                new_upload: true,
+                torrent: torrent.clone(),
+                feed: url.to_string(),
+                alerts: vec![]
+              }]);
             }
           }
+        } else {
+          // index all torrents but not as new torrents
+          for torrent in feed.torrents.iter_mut() {
+            if (torrent.comments_amount != 0) && module.comments.unwrap() {
+              if let Ok(full_torrent) = self.get_torrent(torrent.to_owned()).await.inspect_err(|e| warn!("{}", e)) {
+                torrent.comments = full_torrent.comments;
+              }
+            }
 
-          database.update_db_table(module.module_type.to_string(), module_id,
-          NyaaUpdate {
-            new_upload: true,
-            torrent: torrent.clone()
-          }).await;
+            database.update_db_table(module.module_type.to_string(), module_id,
+            NyaaUpdate {
+              new_upload: true,
+              torrent: torrent.clone(),
+              feed: url.to_string(),
+              alerts: vec![]
+            }).await?;
 
-          table_exists = false;
+            table_exists = false;
+          }
         }
-      }
-      // here put all of it into the cache
-      self.cache_pages.append(&mut vec![NyaaPage {
-        url: url.to_string(),
-        complete: module.retrieve_all_pages.unwrap(),
-        torrents: feed.torrents
-      }]);
+        // here put all of it into the cache, replacing the bare listing stored by search_feed
+        self.cache_pages.lock().await.insert((url.to_string(), feed.complete), NyaaPage {
+          complete: feed.complete,
+          fetched_at: feed.fetched_at,
+          torrents: feed.torrents
+        });
+        Ok::<(), Error>(())
+      }.instrument(info_span!("feed", url = %url)).await?;
     }
-    updates
+    Ok(updates)
   }
 
-  fn find_comment_changes(&mut self, full_torrent: NyaaTorrent, db_torrent: NyaaTorrent) -> Vec<NyaaComment> {
+  async fn find_comment_changes(&self, full_torrent: NyaaTorrent, db_torrent: NyaaTorrent) -> Vec<NyaaComment> {
     let mut update = vec![];
 
+    // put all of the users into the cache
+    self.cache_users.lock().await.extend(full_torrent.comments.iter()
+      .filter(|comment| !comment.user.anonymous)
+      .filter_map(|comment| Some((
+        format!("{}user/{}", full_torrent.domain, comment.user.username),
+        (Instant::now(), comment.user.avatar.clone()?)
+      ))));
+
     // new comment (based on the username and initial timestamp) [looking for negative]
     for mut new_comment in full_torrent.comments.clone() {
-      // put all of the users into the cache
-      self.cache_users.append(&mut vec![new_comment.user.clone()]);
-
       let mut new_flag: bool = true;
       for old_comment in db_torrent.comments.clone() {
         if (new_comment.user.username == old_comment.user.username) &&
@@ -316,88 +453,71 @@ impl Web {
     }
 
     if update.is_empty() {
-      eprintln!("Incorrect comment amount detected in database.\n\
+      warn!("Incorrect comment amount detected in database.\n\
       Reset the database if releases are constantly being downloaded without any updates!");
     }
 
     update
   }
 
-  fn get_user_avatar(&mut self, torrent: NyaaTorrent) -> Result<String, ()> {
+  async fn get_user_avatar(&self, torrent: NyaaTorrent) -> Result<String> {
     let uploader = torrent.uploader.unwrap();
-    for cached_user in self.cache_users.clone() {
-      if cached_user.username == uploader.username {
-        if let Some(avatar) = cached_user.avatar {
-          return Ok(avatar);
-        }
+    let nyaa_url = format!("{}user/{}", torrent.domain, uploader.username);
+    let _fetch_lock = self.fetch_lock(&nyaa_url).await;
+    if let Some((fetched_at, avatar)) = self.cache_users.lock().await.get(&nyaa_url) {
+      if fetched_at.elapsed() < PageKind::User.max_age() {
+        return Ok(avatar.clone());
       }
     }
 
-    let nyaa_url = format!("{}user/{}", torrent.domain, uploader.username);
-    if let Ok(html) = get_nyaa(&nyaa_url) {
-      let avatar = serialize_user_page(&html, &torrent.domain);
-      self.cache_users.append(&mut vec![NyaaUser {
-        anonymous: false,
-        role: uploader.role,
-        username: uploader.username,
-        avatar: Some(avatar.clone()),
-        banned: uploader.banned
-      }]);
-      Ok(avatar)
-    } else {
-      Err(())
-    }
+    let html = self.get_nyaa(&nyaa_url, PageKind::User).await?;
+    let avatar = serialize_user_page(&html, &torrent.domain).inspect_err(|_| PARSE_FAILURES.inc(&["user"]))?;
+    self.cache_users.lock().await.insert(nyaa_url, (Instant::now(), avatar.clone()));
+    Ok(avatar)
   }
 
-  fn get_torrent(&mut self, torrent: NyaaTorrent) -> Result<NyaaTorrent, ()> {
+  async fn get_torrent(&self, torrent: NyaaTorrent) -> Result<NyaaTorrent> {
+    let nyaa_url = format!("{}view/{}", torrent.domain, torrent.id);
+    let _fetch_lock = self.fetch_lock(&nyaa_url).await;
     // check if comments have already been loaded into self if not then get it
-    for page in self.cache_pages.clone() {
-      for cached_torrent in page.torrents {
-        if (cached_torrent.id == torrent.id) && (!cached_torrent.comments.is_empty()) {
-          return Ok(cached_torrent);
-        }
+    if let Some((fetched_at, cached_torrent)) = self.cache_torrents.lock().await.get(&nyaa_url) {
+      if fetched_at.elapsed() < self.cache_ttl {
+        return Ok(cached_torrent.clone());
       }
     }
     
     // serialize torrent page
-    let nyaa_url = format!("{}view/{}", torrent.domain, torrent.id);
-    if let Ok(html) = get_nyaa(&nyaa_url) {
-      let (uploader, comments) = serialize_torrent(&html, nyaa_url, &torrent.domain);
-      let mut full_torrent = torrent;
-      full_torrent.comments = comments;
-      full_torrent.uploader = uploader;
-      Ok(full_torrent)
-    } else {
-      Err(())
-    }
+    let html = self.get_nyaa(&nyaa_url, PageKind::Torrent).await?;
+    let (uploader, comments) = serialize_torrent(&html, nyaa_url.clone(), &torrent.domain).inspect_err(|_| PARSE_FAILURES.inc(&["torrent"]))?;
+    let mut full_torrent = torrent;
+    full_torrent.comments = comments;
+    full_torrent.uploader = Some(uploader);
+    let mut cache_torrents = self.cache_torrents.lock().await;
+    cache_torrents.retain(|_, (fetched_at, _)| fetched_at.elapsed() < self.cache_ttl);
+    cache_torrents.insert(nyaa_url, (Instant::now(), full_torrent.clone()));
+    Ok(full_torrent)
   }
 
-  pub fn search_feed(&mut self, url: &String, complete: bool) -> NyaaPage {
+  pub async fn search_feed(&self, feed_url: &String, complete: bool, mode: FeedMode) -> Result<NyaaPage> {
+    // rss only ever contains the first page
+    let complete = complete && mode != FeedMode::Rss;
+    let url = feed_url;
+    let _fetch_lock = self.fetch_lock(url).await;
     let mut cache_complete: bool = false;
     let mut torrents: Vec<NyaaTorrent> = vec![];
-    for page in self.cache_pages.clone() {
-      if page.url == *url {
-        if page.complete && ! complete {
-          for page_torrent in page.torrents {
-            if !torrents.iter().any(|t| page_torrent.id == t.id) {
-              torrents.append(&mut vec![page_torrent]);
-            }
-            if torrents.len() == 85 {
-              break;
-            }
-          }
-          break;
-        } else {
-          cache_complete = true;
-          for page_torrent in page.torrents.clone() {
-            if !torrents.iter().any(|t| page_torrent.id == t.id) {
-              torrents.append(&mut vec![page_torrent]);
-            }
-          }
-          if !complete {
-            break;
-          }
-        }
+    let mut fetched_at = Instant::now();
+    {
+      let mut cache_pages = self.cache_pages.lock().await;
+      cache_pages.retain(|_, page| page.fetched_at.elapsed() < self.cache_ttl);
+      if let Some(page) = cache_pages.get(&(url.to_string(), complete)) {
+        cache_complete = true;
+        fetched_at = page.fetched_at;
+        torrents = page.torrents.clone();
+      } else if let Some(page) = cache_pages.get(&(url.to_string(), !complete)) {
+        // a complete feed starts with the first page, an incomplete one is only the first page
+        fetched_at = page.fetched_at;
+        torrents = page.torrents.iter().take(85).cloned().collect();
+        cache_complete = !complete;
       }
     }
 
@@ -411,74 +531,168 @@ impl Web {
 
     url = url.replace("http:", "https:");
 
-    if torrents.is_empty() {
-      torrents.append(&mut self.get_feed(&url, complete, false));
-    } else if complete && ! cache_complete {
-      torrents.append(&mut self.get_feed(&url, complete, true));
+    if cache_complete {
+      return Ok(NyaaPage {
+        complete,
+        fetched_at,
+        torrents
+      });
+    } else if torrents.is_empty() {
+      torrents.append(&mut self.get_listing(&url, complete, mode).await?);
+    } else {
+      torrents.append(&mut self.get_feed(&url, complete, true).await?);
     }
 
-    NyaaPage {
-      url: url.to_string(),
+    let page = NyaaPage {
       complete,
+      fetched_at: Instant::now(),
       torrents
+    };
+    // other modules with the same feed will find it here while it's fresh
+    self.cache_pages.lock().await.insert((feed_url.to_string(), complete), page.clone());
+    Ok(page)
+  }
+
+  async fn get_listing(&self, url: &String, complete: bool, mode: FeedMode) -> Result<Vec<NyaaTorrent>> {
+    if mode == FeedMode::Html || (mode == FeedMode::Auto && complete) {
+      return self.get_feed(url, complete, false).await;
+    }
+    match self.get_rss(url).await {
+      Ok(torrents) => Ok(torrents),
+      Err(e) if mode == FeedMode::Auto => {
+        warn!("{}, falling back to html.", e);
+        self.get_feed(url, complete, false).await
+      },
+      Err(e) => Err(e)
     }
   }
 
-  fn get_feed(&mut self, url: &String, complete: bool, skip_first_page: bool) -> Vec<NyaaTorrent> {
-    let domain = get_domain(url);
+  async fn get_rss(&self, url: &str) -> Result<Vec<NyaaTorrent>> {
+    let domain = get_domain(url)?;
+    let xml = self.get_nyaa(&rss_url(url, &domain), PageKind::Feed).await?;
+    serialize_rss(&xml, &domain).inspect_err(|_| PARSE_FAILURES.inc(&["rss"]))
+  }
+
+  async fn get_feed(&self, url: &String, complete: bool, skip_first_page: bool) -> Result<Vec<NyaaTorrent>> {
+    let domain = get_domain(url)?;
     let mut torrents: Vec<NyaaTorrent> = vec![];
-    let mut page_number = if skip_first_page { 2 } else { 1 };
+    let first_page = if skip_first_page { 2 } else { 1 };
+    let mut page_number = first_page;
     loop {
       let nyaa_url = format!("{}p={}", url, page_number);
-      if let Ok(html) = get_nyaa(&nyaa_url) {
-        if let Ok((mut feed, continuing)) = serialize_feed(html, &domain) {
+      match self.get_nyaa(&nyaa_url, PageKind::Feed).await {
+        Ok(html) => {
+          let (mut feed, continuing) = serialize_feed(html, &domain).inspect_err(|_| PARSE_FAILURES.inc(&["feed"]))?;
           torrents.append(&mut feed);
           if ! continuing || ! complete {
             break;
           }
+        },
+        // Whatever has been found on the previous pages is still good to use.
+        Err(e) if page_number != first_page => {
+          warn!("{}", e);
+          return Ok(torrents);
+        },
+        Err(e) => {
+          return Err(e);
         }
-      } else {
-        return torrents;
       }
 
       page_number += 1;
     }
 
-    torrents
+    Ok(torrents)
   }
-}
 
-fn get_nyaa(nyaa_url: &String) -> Result<String, ()> {
-  for attempt in 1..3 {
-    println!("[INF] Requesting {:?}", nyaa_url);
-    let get_request = isahc::Request::get(nyaa_url)
-      .timeout(Duration::from_secs(15))
-      .body(()).expect("Failed to create request.")
-    .send();
-  
-    thread::sleep(Duration::from_secs(2));
-    
-    if let Ok(mut request) = get_request {
-      if request.status() == StatusCode::OK {
-        match request.text() {
-          Ok(nya) => {
-            return Ok(nya);
-          },
-          Err(e) => {
-            eprintln!("Failed nyaa request:\n{:?}", e);
-            return Err(());
+  async fn get_nyaa(&self, nyaa_url: &String, kind: PageKind) -> Result<String> {
+    let cached = self.page_cache.get(nyaa_url).await;
+    if let Some(page) = cached.as_ref().filter(|page| page.is_fresh(kind)) {
+      return Ok(page.body.clone());
+    }
+
+    let request_url = match &self.base_url {
+      Some(base_url) => format!("{}{}", base_url, nyaa_url.trim_start_matches(get_domain(nyaa_url)?.as_str())),
+      None => nyaa_url.to_string()
+    };
+
+    let mut last_error = Error::http(nyaa_url, "No request has been sent");
+    for attempt in 1..=NYAA_MAX_ATTEMPTS {
+      NYAA_LIMITER.acquire().await;
+      info!("Requesting {:?}", request_url);
+      let mut builder = isahc::Request::get(&request_url)
+        .timeout(Duration::from_secs(15));
+      if let Some(page) = &cached {
+        if let Some(etag) = &page.etag {
+          builder = builder.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &page.last_modified {
+          builder = builder.header(IF_MODIFIED_SINCE, last_modified);
+        }
+      }
+      let get_request = builder
+        .body(()).map_err(|e| Error::http(nyaa_url, e))?
+      .send_async().await;
+
+      let mut delay = backoff(attempt, Duration::from_secs(2), Duration::from_secs(120));
+      match &get_request {
+        Ok(request) => NYAA_REQUESTS.inc(&[request.status().as_str()]),
+        Err(_) => NYAA_REQUESTS.inc(&["error"])
+      }
+      match get_request {
+        Ok(request) if request.status() == StatusCode::NOT_MODIFIED && cached.is_some() => {
+          let mut page = cached.unwrap();
+          page.fetched_at = chrono::Utc::now().timestamp();
+          self.page_cache.insert(&page).await;
+          return Ok(page.body);
+        },
+        Ok(mut request) if request.status() == StatusCode::OK => {
+          let header = |name| request.headers().get(name)
+            .and_then(|value: &isahc::http::HeaderValue| value.to_str().ok())
+            .map(|value| value.to_string());
+          let etag = header(ETAG);
+          let last_modified = header(LAST_MODIFIED);
+          let body = request.text().await.map_err(|e| Error::http(nyaa_url, e))?;
+          // without a validator the page can only be reused while it's fresh
+          if etag.is_some() || last_modified.is_some() || kind.max_age() > Duration::ZERO {
+            self.page_cache.insert(&CachedPage {
+              url: nyaa_url.to_string(),
+              etag,
+              last_modified,
+              fetched_at: chrono::Utc::now().timestamp(),
+              body: body.clone()
+            }).await;
+          }
+          return Ok(body);
+        },
+        Ok(request) if request.status() == StatusCode::TOO_MANY_REQUESTS || request.status().is_server_error() => {
+          if let Some(retry_after) = request.headers().get(RETRY_AFTER)
+          .and_then(|value| value.to_str().ok())
+          .and_then(parse_retry_after) {
+            delay = retry_after.min(Duration::from_secs(600));
           }
+          last_error = Error::http(nyaa_url, format!("Unexpected status {}", request.status()));
+        },
+        Ok(request) => {
+          // Anything else (f.e. 404 after the last page) won't change by asking again.
+          return Err(Error::http(nyaa_url, format!("Unexpected status {}", request.status())));
+        },
+        Err(e) => {
+          last_error = Error::http(nyaa_url, e);
         }
       }
+      if attempt < NYAA_MAX_ATTEMPTS {
+        warn!(attempt, "Failed to send get request to {:?}, retrying in {:?}", request_url, delay);
+        NYAA_RETRIES.inc(&[]);
+        tokio::time::sleep(delay).await;
+      }
     }
-    eprintln!("Failed to send get request (attempt: {})", attempt);
-  }
 
-  eprintln!("Skipping request ...");
-  Err(())
+    warn!("Skipping request to {:?} ...", request_url);
+    Err(last_error)
+  }
 }
 
-fn get_domain(url: &str) -> String {
-  let re = regex::Regex::new(r"https?://([a-zA-Z]+.[a-z]+|[0-9]{1,3}\.[0-9]{1,3}\.[0-9]{1,3}\.[0-9]{1,3})/").unwrap();
-  return re.find(url).unwrap().as_str().to_string()
+pub(crate) fn get_domain(url: &str) -> Result<String> {
+  let re = regex::Regex::new(r"^https?://[^/?#]+/").unwrap();
+  re.find(url).map(|domain| domain.as_str().to_string()).ok_or_else(|| Error::parse("feed url", format!("No nyaa domain in {:?}", url)))
 }
@@ -0,0 +1,109 @@
+mod common;
+
+use isahc::{
+  AsyncReadResponseExt, Request, RequestExt
+};
+use serde_json::Value;
+use std::sync::Arc;
+
+use common::*;
+use nyaa_notifications::api::{
+  self, ApiState
+};
+use nyaa_notifications::config::Config;
+use nyaa_notifications::notifications::Notifications;
+use nyaa_notifications::scheduler::Scheduler;
+
+const FEED: &str = "/user/subsplease?p=1";
+
+async fn call(method: &str, url: &str, token: Option<&str>) -> (u16, Value) {
+  let mut request = Request::builder().method(method).uri(url);
+  if let Some(token) = token {
+    request = request.header("Authorization", format!("Bearer {}", token));
+  }
+  let mut response = request.body(()).unwrap().send_async().await.unwrap();
+  let body = response.text().await.unwrap();
+  (response.status().as_u16(), serde_json::from_str(&body).unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn api_shows_and_controls_the_modules() {
+  let scratch = Scratch::new("api");
+  let nyaa = FakeServer::start().await;
+  let database = scratch.database().await;
+  let transport = Arc::new(RecordingTransport::default());
+  let config: Config = toml::from_str(r#"
+    update_interval = 60
+    [[module]]
+    module_type = "Gotify"
+    active = true
+    feeds = ["https://nyaa.si/user/subsplease"]
+    comments = true
+    uploads = true
+    retrieve_all_pages = false
+    gotify_domain = "https://gotify.example.com"
+    gotify_token = "secret"
+  "#).unwrap();
+  nyaa.serve(FEED, without_next_page(&without_torrent(LISTING, 1720002)));
+  nyaa.serve("/view/1720000", VIEW_COMMENTS);
+  nyaa.serve("/view/1720002", VIEW_UPLOAD);
+  nyaa.serve("/user/subsplease", USER);
+
+  let scheduler = Scheduler::new(&config, Notifications::with_transport(transport.clone()), &database)
+    .with_web(scratch.web(&nyaa).await);
+  let modules = scheduler.modules();
+  let listener = api::bind("127.0.0.1:0").await.unwrap();
+  let base_url = format!("http://{}", listener.local_addr().unwrap());
+  tokio::spawn(api::serve(listener, ApiState { modules: modules.clone(), database_pool: database.database.clone(), token: Some("letmein".to_string()) }));
+  let started = chrono::Utc::now();
+  tokio::spawn(scheduler.run(config.module.clone()));
+
+  assert_eq!(call("GET", &format!("{}/health", base_url), None).await.0, 200);
+  assert_eq!(call("GET", &format!("{}/ready", base_url), None).await.0, 200);
+
+  // The first check only indexes the feed.
+  checked(&modules, "Gotify_0", started).await;
+  let (status, listed) = call("GET", &format!("{}/modules", base_url), None).await;
+  assert_eq!(status, 200);
+  assert_eq!(listed[0]["key"], "Gotify_0");
+  assert_eq!(listed[0]["interval_minutes"], 60);
+  assert_eq!(listed[0]["paused"], false);
+  assert!(listed[0]["status"]["last_check"].is_string());
+  assert_eq!(listed[0]["status"]["last_error"], Value::Null);
+  assert!(transport.take_sent().is_empty());
+
+  // A triggered check doesn't wait for the hour to pass, and what it delivered shows up as events.
+  nyaa.serve(FEED, without_next_page(&with_comment_count(LISTING, 1720000, 3, 4)));
+  nyaa.serve("/view/1720000", with_comment(VIEW_COMMENTS, 4, "fern", chrono::Utc::now().timestamp() - 60, None, "Frieren-sama, please wake up."));
+  assert_eq!(call("POST", &format!("{}/modules/Gotify_0/check", base_url), None).await.0, 401);
+  let triggered = chrono::Utc::now();
+  assert_eq!(call("POST", &format!("{}/modules/Gotify_0/check", base_url), Some("letmein")).await.0, 202);
+  checked(&modules, "Gotify_0", triggered).await;
+  assert_eq!(transport.take_sent().len(), 2);
+  let (status, events) = call("GET", &format!("{}/modules/Gotify_0/events?limit=1", base_url), None).await;
+  assert_eq!(status, 200);
+  assert_eq!(events.as_array().unwrap().len(), 1);
+  assert_eq!(events[0]["kind"], "upload");
+  assert_eq!(events[0]["torrent_id"], 1720002);
+  assert_eq!(call("GET", &format!("{}/modules/Gotify_0/events", base_url), None).await.1.as_array().unwrap().len(), 2);
+
+  let metrics = isahc::get_async(format!("{}/metrics", base_url)).await.unwrap().text().await.unwrap();
+  for line in [
+    "nyaa_requests_total{status=\"200\"}",
+    "nyaa_updates_detected_total{type=\"NEW\"} ",
+    "nyaa_updates_detected_total{type=\"UPLOAD\"} ",
+    "nyaa_deliveries_total{backend=\"Gotify\",outcome=\"success\"} 2",
+    "nyaa_poll_duration_seconds_count{module_type=\"Gotify\"} 2",
+    "nyaa_discord_channels_paused_total 0"
+  ] {
+    assert!(metrics.contains(line), "{} is missing from\n{}", line, metrics);
+  }
+
+  // Paused modules can't be triggered until they're resumed.
+  let (status, paused) = call("POST", &format!("{}/modules/Gotify_0/pause", base_url), Some("letmein")).await;
+  assert_eq!(status, 200);
+  assert_eq!(paused["paused"], true);
+  assert_eq!(call("POST", &format!("{}/modules/Gotify_0/check", base_url), Some("letmein")).await.0, 409);
+  assert_eq!(call("POST", &format!("{}/modules/Gotify_0/resume", base_url), Some("letmein")).await.1["paused"], false);
+  assert_eq!(call("POST", &format!("{}/modules/Gotify_9/resume", base_url), Some("letmein")).await.0, 404);
+}
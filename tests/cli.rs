@@ -0,0 +1,186 @@
+mod common;
+
+use std::{
+  path::Path, sync::Arc
+};
+
+use common::*;
+use nyaa_notifications::atom::HistoryEvent;
+use nyaa_notifications::config::Config;
+use nyaa_notifications::database::Database;
+use nyaa_notifications::notifications::Notifications;
+use nyaa_notifications::scheduler::Scheduler;
+
+/// Runs the program on the scratch directory, returning the exit code and stdout.
+async fn nyaa_notifications(data_dir: &Path, arguments: &[&str]) -> (i32, String) {
+  let output = tokio::process::Command::new(env!("CARGO_BIN_EXE_nyaa-notifications"))
+    .arg("--data-dir").arg(data_dir)
+    .args(arguments)
+    .output().await.unwrap();
+  (output.status.code().unwrap(), String::from_utf8(output.stdout).unwrap())
+}
+
+fn history_event(title: &str) -> HistoryEvent {
+  HistoryEvent {
+    id: 0,
+    kind: "upload".to_string(),
+    domain: DOMAIN.to_string(),
+    torrent_id: 1720002,
+    torrent_title: title.to_string(),
+    category: "Anime - English-translated".to_string(),
+    size: "1.4 GiB".to_string(),
+    username: Some("subsplease".to_string()),
+    message: None,
+    old_message: None,
+    link: format!("{}view/1720002", DOMAIN),
+    timestamp: 1720000000.5
+  }
+}
+
+#[tokio::test]
+async fn commands_work_on_the_data_directory() {
+  let scratch = Scratch::new("cli");
+  let hook = FakeServer::start().await;
+  let data_dir = scratch.path.join("data");
+
+  // Without a configuration there's a template to fill in.
+  assert_eq!(nyaa_notifications(&data_dir, &["validate-config"]).await.0, 78);
+  assert!(data_dir.join("config.toml").is_file());
+  // Its atom feed goes into the data directory as well.
+  let template: Config = toml::from_str(&std::fs::read_to_string(data_dir.join("config.toml")).unwrap()).unwrap();
+  assert!(template.module.iter().all(|module| module.atom_path.is_none()));
+  // One that can't be written is a file error instead.
+  assert_eq!(nyaa_notifications(&data_dir, &["--config", data_dir.to_str().unwrap(), "validate-config"]).await.0, 74);
+
+  std::fs::write(data_dir.join("config.toml"), format!(r#"
+    update_interval = 10
+    [[module]]
+    module_type = "Webhook"
+    active = true
+    feeds = ["https://nyaa.si/user/subsplease"]
+    comments = true
+    uploads = true
+    retrieve_all_pages = false
+    webhook_url = "{}hooks/nyaa"
+    [[module]]
+    module_type = "Gotify"
+    active = false
+    feeds = ["https://nyaa.si/user/Erai-raws"]
+    comments = false
+    uploads = true
+    retrieve_all_pages = false
+    update_interval = 30
+    gotify_domain = "https://gotify.example.com"
+    gotify_token = "secret"
+  "#, hook.base_url)).unwrap();
+  let (code, output) = nyaa_notifications(&data_dir, &["validate-config"]).await;
+  assert_eq!(code, 0);
+  assert!(output.contains("1 of its 2 modules are active"), "{}", output);
+
+  let (code, output) = nyaa_notifications(&data_dir, &["list-modules"]).await;
+  assert_eq!(code, 0);
+  let lines: Vec<Vec<&str>> = output.lines().map(|line| line.split_whitespace().collect()).collect();
+  assert_eq!(lines, vec![
+    vec!["Webhook_0", "active", "every", "10", "min", "https://nyaa.si/user/subsplease"],
+    vec!["Gotify_1", "inactive", "every", "30", "min", "https://nyaa.si/user/Erai-raws"]
+  ]);
+
+  // A test notification goes through the real backend.
+  let (code, output) = nyaa_notifications(&data_dir, &["test-notify", "Webhook_0"]).await;
+  assert_eq!(code, 0, "{}", output);
+  let posts = hook.take_posts();
+  assert_eq!(posts.len(), 2);
+  assert!(posts.iter().all(|post| post.path == "/hooks/nyaa"));
+  assert!(posts[0].body.contains(r#""event":"upload""#), "{}", posts[0].body);
+  assert!(posts[1].body.contains(r#""event":"new_comment""#), "{}", posts[1].body);
+  assert_eq!(nyaa_notifications(&data_dir, &["test-notify", "Webhook_7"]).await.0, 78);
+
+  // Exported state comes back after a reset.
+  let mut database = Database::open(data_dir.join("nyaa-notifications.sqlite").to_str().unwrap()).await.unwrap();
+  let id = "0".to_string();
+  assert!(!database.subscription_indexed("Webhook".to_string(), &id).await.unwrap());
+  database.add_history_event("Webhook".to_string(), &id, &history_event("[SubsPlease] Sousou no Frieren - 01 (1080p) [A1B2C3D4].mkv"), 10).await.unwrap();
+  let exported = database.get_history_events("Webhook".to_string(), &id).await.unwrap();
+  database.database.close().await;
+
+  let export = scratch.path.join("export.json");
+  assert_eq!(nyaa_notifications(&data_dir, &["export", export.to_str().unwrap()]).await.0, 0);
+  let (code, output) = nyaa_notifications(&data_dir, &["reset-module", "Webhook_0"]).await;
+  assert_eq!(code, 0, "{}", output);
+  let mut database = Database::open(data_dir.join("nyaa-notifications.sqlite").to_str().unwrap()).await.unwrap();
+  assert!(database.get_history_events("Webhook".to_string(), &id).await.unwrap().is_empty());
+  assert!(!database.subscription_indexed("Webhook".to_string(), &id).await.unwrap());
+  database.database.close().await;
+
+  assert_eq!(nyaa_notifications(&data_dir, &["import", export.to_str().unwrap()]).await.0, 0);
+  let mut database = Database::open(data_dir.join("nyaa-notifications.sqlite").to_str().unwrap()).await.unwrap();
+  assert_eq!(database.get_history_events("Webhook".to_string(), &id).await.unwrap(), exported);
+  assert!(database.subscription_indexed("Webhook".to_string(), &id).await.unwrap());
+  database.database.close().await;
+
+  // An export of another schema version is refused as bad data, a missing one can't be read.
+  std::fs::write(&export, r#"{"schema_version": 1, "tables": {}}"#).unwrap();
+  assert_eq!(nyaa_notifications(&data_dir, &["import", export.to_str().unwrap()]).await.0, 65);
+  assert_eq!(nyaa_notifications(&data_dir, &["import", scratch.path.join("missing.json").to_str().unwrap()]).await.0, 74);
+}
+
+#[tokio::test]
+async fn database_commands_need_no_configuration() {
+  let scratch = Scratch::new("cli-data-only");
+  let data_dir = scratch.path.join("data");
+  let export = scratch.path.join("export.json");
+
+  let (code, output) = nyaa_notifications(&data_dir, &["export", export.to_str().unwrap()]).await;
+  assert_eq!(code, 0, "{}", output);
+  assert_eq!(nyaa_notifications(&data_dir, &["import", export.to_str().unwrap()]).await.0, 0);
+  assert!(!data_dir.join("config.toml").exists());
+
+  let mut database = Database::open(data_dir.join("nyaa-notifications.sqlite").to_str().unwrap()).await.unwrap();
+  assert!(!database.subscription_indexed("Gotify".to_string(), &"3".to_string()).await.unwrap());
+  database.database.close().await;
+  let (code, output) = nyaa_notifications(&data_dir, &["reset-module", "Gotify_3"]).await;
+  assert_eq!(code, 0, "{}", output);
+  assert_eq!(nyaa_notifications(&data_dir, &["reset-module", "Gotify_4"]).await.0, 78);
+  assert!(!data_dir.join("config.toml").exists());
+}
+
+#[tokio::test]
+async fn check_once_checks_every_active_module() {
+  let scratch = Scratch::new("check-once");
+  let nyaa = FakeServer::start().await;
+  let mut database = scratch.database().await;
+  let transport = Arc::new(RecordingTransport::default());
+  let config: Config = toml::from_str(r#"
+    update_interval = 60
+    [[module]]
+    module_type = "Gotify"
+    active = true
+    feeds = ["https://nyaa.si/user/subsplease"]
+    comments = false
+    uploads = true
+    retrieve_all_pages = false
+    gotify_domain = "https://gotify.example.com"
+    gotify_token = "secret"
+    [[module]]
+    module_type = "Gotify"
+    active = false
+    feeds = ["https://nyaa.si/user/subsplease"]
+    comments = false
+    uploads = true
+    retrieve_all_pages = false
+    gotify_domain = "https://gotify.example.com"
+    gotify_token = "secret"
+  "#).unwrap();
+  nyaa.serve("/user/subsplease?p=1", without_next_page(&without_torrent(LISTING, 1720002)));
+  let scheduler = |web| Scheduler::new(&config, Notifications::with_transport(transport.clone()), &database).with_web(web);
+
+  let results = scheduler(scratch.web(&nyaa).await).check_once(config.module.clone()).await.unwrap();
+  assert_eq!(results.iter().map(|(key, result)| (key.as_str(), result.is_ok())).collect::<Vec<_>>(), vec![("Gotify_0", true)]);
+  assert!(transport.take_sent().is_empty());
+
+  nyaa.serve("/user/subsplease?p=1", without_next_page(LISTING));
+  let results = scheduler(scratch.web(&nyaa).await).check_once(config.module.clone()).await.unwrap();
+  assert!(results[0].1.is_ok());
+  assert_eq!(transport.take_sent().len(), 1);
+  assert!(database.subscription_indexed("Gotify".to_string(), &"0".to_string()).await.unwrap());
+}
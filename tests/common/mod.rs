@@ -0,0 +1,440 @@
+#![allow(dead_code)]
+
+use std::{
+  collections::HashMap, path::PathBuf, sync::{Arc, Mutex}, time::Duration
+};
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt}, net::{TcpListener, TcpStream}
+};
+
+use serenity::{
+  all::CreateMessage, model::prelude::ChannelId, async_trait
+};
+
+use nyaa_notifications::cache::PageCache;
+use nyaa_notifications::config::ModuleConfig;
+use nyaa_notifications::database::Database;
+use nyaa_notifications::error::{Error, Result};
+use nyaa_notifications::scheduler::Modules;
+use nyaa_notifications::transport::{
+  HttpRequest, HttpResponse, Transport
+};
+use nyaa_notifications::web::{NyaaCommentUpdateType, NyaaUpdate, Web, NYAA_LIMITER};
+
+pub const DOMAIN: &str = "https://nyaa.si/";
+pub const LISTING: &str = include_str!("../fixtures/listing.html");
+pub const LISTING_PAGE2: &str = include_str!("../fixtures/listing_page2.html");
+pub const VIEW_COMMENTS: &str = include_str!("../fixtures/view_comments.html");
+pub const VIEW_UPLOAD: &str = include_str!("../fixtures/view_upload.html");
+pub const VIEW_ANONYMOUS: &str = include_str!("../fixtures/view_anonymous.html");
+pub const USER: &str = include_str!("../fixtures/user.html");
+pub const RSS: &str = include_str!("../fixtures/rss.xml");
+
+/// Serves canned pages by path (including the query) and remembers every request, anything unknown is a 404.
+/// POSTs and PUTs are answered with 200 and their bodies kept, which is all a Gotify server needs to be.
+pub struct FakeServer {
+  pub base_url: String,
+  state: Arc<Mutex<ServerState>>
+}
+
+#[derive(Default)]
+struct ServerState {
+  pages: HashMap<String, String>,
+  etags: HashMap<String, String>,
+  delays: HashMap<String, Duration>,
+  failures: HashMap<String, usize>,
+  answers: Vec<(String, String)>,
+  requests: Vec<String>,
+  posts: Vec<Post>
+}
+
+#[derive(Debug, Clone)]
+pub struct Post {
+  pub method: String,
+  pub path: String,
+  pub headers: Vec<(String, String)>,
+  pub body: String
+}
+
+impl Post {
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self.headers.iter().find(|(header, _)| header.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+  }
+}
+
+impl FakeServer {
+  pub async fn start() -> Self {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}/", listener.local_addr().unwrap());
+    let state: Arc<Mutex<ServerState>> = Arc::default();
+    let server_state = state.clone();
+    tokio::spawn(async move {
+      while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(respond(stream, server_state.clone()));
+      }
+    });
+    FakeServer { base_url, state }
+  }
+
+  pub fn serve(&self, path: &str, body: impl Into<String>) {
+    self.state.lock().unwrap().pages.insert(path.to_string(), body.into());
+  }
+
+  /// Sends the ETag with the path's page, and answers requests which already have it with a 304.
+  pub fn etag(&self, path: &str, etag: &str) {
+    self.state.lock().unwrap().etags.insert(path.to_string(), etag.to_string());
+  }
+
+  /// Holds back every answer to the path, for checks which are still running when something else happens.
+  pub fn delay(&self, path: &str, delay: Duration) {
+    self.state.lock().unwrap().delays.insert(path.to_string(), delay);
+  }
+
+  pub fn remove(&self, path: &str) {
+    self.state.lock().unwrap().pages.remove(path);
+  }
+
+  /// Answers the next `times` requests to the path with a 500.
+  pub fn fail(&self, path: &str, times: usize) {
+    self.state.lock().unwrap().failures.insert(path.to_string(), times);
+  }
+
+  /// Answers POSTs and PUTs to paths starting with the prefix with the body instead of `{}`.
+  /// `{n}` in it becomes the number of the request, for services which hand out ids.
+  pub fn answer(&self, path_prefix: &str, body: &str) {
+    self.state.lock().unwrap().answers.push((path_prefix.to_string(), body.to_string()));
+  }
+
+  /// Returns and forgets the paths requested since the last call.
+  pub fn take_requests(&self) -> Vec<String> {
+    std::mem::take(&mut self.state.lock().unwrap().requests)
+  }
+
+  /// Returns and forgets every POST and PUT since the last call, including the failed ones.
+  pub fn take_posts(&self) -> Vec<Post> {
+    std::mem::take(&mut self.state.lock().unwrap().posts)
+  }
+}
+
+async fn respond(mut stream: TcpStream, state: Arc<Mutex<ServerState>>) {
+  let mut request: Vec<u8> = vec![];
+  let mut buffer = [0u8; 1024];
+  let head_end = loop {
+    if let Some(position) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+      break position + 4;
+    }
+    match stream.read(&mut buffer).await {
+      Ok(0) | Err(_) => return,
+      Ok(read) => request.extend_from_slice(&buffer[..read])
+    }
+  };
+  let head = String::from_utf8_lossy(&request[..head_end]).to_string();
+  let content_length = head.lines()
+    .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|length| length.trim().parse::<usize>().unwrap_or(0)))
+    .unwrap_or(0);
+  while request.len() < head_end + content_length {
+    match stream.read(&mut buffer).await {
+      Ok(0) | Err(_) => return,
+      Ok(read) => request.extend_from_slice(&buffer[..read])
+    }
+  }
+  let mut parts = head.split_whitespace();
+  let method = parts.next().unwrap_or("GET").to_string();
+  let path = parts.next().unwrap_or("/").to_string();
+  let if_none_match = head.lines()
+    .find_map(|line| line.to_ascii_lowercase().starts_with("if-none-match:").then(|| line[14..].trim().to_string()));
+
+  let response = {
+    let mut state = state.lock().unwrap();
+    state.requests.push(path.clone());
+    let write = method == "POST" || method == "PUT";
+    if write {
+      let headers = head.lines().skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+      let body = String::from_utf8_lossy(&request[head_end..head_end + content_length]).to_string();
+      state.posts.push(Post { method: method.clone(), path: path.clone(), headers, body });
+    }
+    let failing = match state.failures.get_mut(&path) {
+      Some(times) if *times > 0 => {
+        *times -= 1;
+        true
+      },
+      _ => false
+    };
+    if failing {
+      Some((500, String::new()))
+    } else if write {
+      let answer = state.answers.iter().find(|(prefix, _)| path.starts_with(prefix.as_str())).map(|(_, body)| body.clone());
+      Some((200, answer.unwrap_or("{}".to_string()).replace("{n}", &state.requests.len().to_string())))
+    } else if state.pages.contains_key(&path) && if_none_match.is_some() && state.etags.get(&path) == if_none_match.as_ref() {
+      Some((304, String::new()))
+    } else {
+      state.pages.get(&path).cloned().map(|body| (200, body))
+    }
+  };
+  let (etag, delay) = {
+    let state = state.lock().unwrap();
+    (state.etags.get(&path).map(|etag| format!("ETag: {}\r\n", etag)).unwrap_or_default(), state.delays.get(&path).copied())
+  };
+  if let Some(delay) = delay {
+    tokio::time::sleep(delay).await;
+  }
+  let response = match response {
+    Some((200, body)) => format!("HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}", etag, body.len(), body),
+    Some((304, _)) => format!("HTTP/1.1 304 Not Modified\r\n{}Content-Length: 0\r\nConnection: close\r\n\r\n", etag),
+    Some((status, _)) => format!("HTTP/1.1 {} ERROR\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status),
+    None => "HTTP/1.1 404 NOT FOUND\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+  };
+  let _ = stream.write_all(response.as_bytes()).await;
+  let _ = stream.shutdown().await;
+}
+
+/// Records everything instead of sending it, and fails the sends matching `fail_when`.
+/// Emails are kept as their formatted source and discord messages as the JSON that would be posted.
+#[derive(Default)]
+pub struct RecordingTransport {
+  pub sent: Mutex<Vec<Sent>>,
+  fail_when: Mutex<Option<FailWhen>>,
+  unreachable_channels: Mutex<Vec<u64>>
+}
+
+type FailWhen = Box<dyn Fn(&Sent) -> bool + Send>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sent {
+  Email(String),
+  Gotify { title: String, message: String, priority: u32 },
+  Discord { channel: u64, message: serde_json::Value },
+  Http(HttpRequest)
+}
+
+impl Sent {
+  /// Whatever is shown to the reader, for looking up a notification by its content.
+  pub fn text(&self) -> String {
+    match self {
+      // undoes the quoted-printable line wrapping, so that links can be searched for
+      Sent::Email(source) => source.replace("=\r\n", "").replace("=3D", "="),
+      Sent::Gotify { title, message, .. } => format!("{}\n{}", title, message),
+      Sent::Discord { message, .. } => message.to_string(),
+      Sent::Http(request) => request.body.clone()
+    }
+  }
+}
+
+impl RecordingTransport {
+  pub fn fail_when(&self, predicate: impl Fn(&Sent) -> bool + Send + 'static) {
+    *self.fail_when.lock().unwrap() = Some(Box::new(predicate));
+  }
+
+  pub fn succeed(&self) {
+    *self.fail_when.lock().unwrap() = None;
+  }
+
+  pub fn unreachable_channel(&self, channel: u64) {
+    self.unreachable_channels.lock().unwrap().push(channel);
+  }
+
+  /// Returns and forgets everything sent since the last call, including the failed ones.
+  pub fn take_sent(&self) -> Vec<Sent> {
+    std::mem::take(&mut *self.sent.lock().unwrap())
+  }
+
+  fn record(&self, sent: Sent) -> Result<()> {
+    let failing = self.fail_when.lock().unwrap().as_ref().is_some_and(|predicate| predicate(&sent));
+    let error = match sent {
+      Sent::Email(_) => Error::Smtp,
+      Sent::Gotify { .. } => Error::Gotify,
+      Sent::Discord { .. } => Error::Discord,
+      Sent::Http(_) => Error::Webhook
+    };
+    self.sent.lock().unwrap().push(sent);
+    if failing {
+      return Err(error("Refused by the test".to_string()));
+    }
+    Ok(())
+  }
+}
+
+#[async_trait]
+impl Transport for RecordingTransport {
+  async fn send_email(&self, _module: &ModuleConfig, email: lettre::Message) -> Result<()> {
+    self.record(Sent::Email(String::from_utf8_lossy(&email.formatted()).to_string()))
+  }
+
+  async fn send_gotify(&self, _module: &ModuleConfig, title: &str, message: String, priority: u32) -> Result<()> {
+    self.record(Sent::Gotify { title: title.to_string(), message, priority })
+  }
+
+  async fn check_discord_channel(&self, channel: ChannelId) -> Result<()> {
+    if self.unreachable_channels.lock().unwrap().contains(&channel.get()) {
+      return Err(Error::Discord(format!("Channel {:?} is unreachable", channel.get())));
+    }
+    Ok(())
+  }
+
+  async fn send_discord(&self, channel: ChannelId, message: CreateMessage) -> Result<()> {
+    self.record(Sent::Discord { channel: channel.get(), message: serde_json::to_value(&message).unwrap() })
+  }
+
+  /// Failing requests are answered with a 500, the others with an empty JSON object.
+  async fn http(&self, request: HttpRequest) -> Result<HttpResponse> {
+    match self.record(Sent::Http(request)) {
+      Ok(()) => Ok(HttpResponse { status: 200, body: "{}".to_string() }),
+      Err(_) => Ok(HttpResponse { status: 500, body: String::new() })
+    }
+  }
+}
+
+/// A scratch directory for the database and the page cache, removed on drop.
+pub struct Scratch {
+  pub path: PathBuf
+}
+
+impl Scratch {
+  pub fn new(name: &str) -> Self {
+    let path = std::env::temp_dir().join(format!("nyaa-notifications-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    Scratch { path }
+  }
+
+  pub async fn database(&self) -> Database {
+    Database::open(self.path.join("test.sqlite").to_str().unwrap()).await.unwrap()
+  }
+
+  /// Every poll gets its own `Web`, like separate runs of the program sharing the on-disk cache.
+  pub async fn web(&self, nyaa: &FakeServer) -> Web {
+    NYAA_LIMITER.set_rate(u32::MAX).await;
+    Web::new(Duration::ZERO)
+      .with_page_cache(PageCache::new(self.path.join("cache")))
+      .with_base_url(&nyaa.base_url)
+  }
+}
+
+impl Drop for Scratch {
+  fn drop(&mut self) {
+    let _ = std::fs::remove_dir_all(&self.path);
+  }
+}
+
+/// Waits until the module has finished a check after `since`.
+pub async fn checked(modules: &Modules, key: &str, since: chrono::DateTime<chrono::Utc>) {
+  for _ in 0..200 {
+    let status = modules.read().unwrap().get(key).map(|module| module.status());
+    if status.is_some_and(|status| !status.checking && status.last_check.is_some_and(|last_check| last_check >= since)) {
+      return;
+    }
+    tokio::time::sleep(Duration::from_millis(10)).await;
+  }
+  panic!("{} wasn't checked", key);
+}
+
+pub fn module(toml: &str) -> ModuleConfig {
+  toml::from_str::<ModuleConfig>(toml).unwrap()
+}
+
+/// Stores the updates the way a notifier does after a successful delivery:
+/// announced comments become `UNCHECKED` and deleted ones are dropped.
+pub async fn deliver(database: &mut Database, module: &ModuleConfig, id: &String, updates: &[NyaaUpdate]) {
+  for update in updates.iter().rev() {
+    let mut update = update.clone();
+    update.torrent.comments.retain(|comment| comment.update_type != NyaaCommentUpdateType::DELETED);
+    for comment in update.torrent.comments.iter_mut() {
+      if matches!(comment.update_type, NyaaCommentUpdateType::NEW | NyaaCommentUpdateType::EDITED) {
+        comment.update_type = NyaaCommentUpdateType::UNCHECKED;
+      }
+    }
+    database.update_db_table(module.module_type.to_string(), id, update).await.unwrap();
+  }
+}
+
+pub type Comments = Vec<(String, NyaaCommentUpdateType)>;
+
+/// `(torrent id, new upload, [(username, update type)])` for every update, which is what the assertions compare.
+pub fn summary(updates: &[NyaaUpdate]) -> Vec<(u64, bool, Comments)> {
+  updates.iter().map(|update| (
+    update.torrent.id,
+    update.new_upload,
+    update.torrent.comments.iter().map(|comment| (comment.user.username.clone(), comment.update_type.clone())).collect()
+  )).collect()
+}
+
+/// `(torrent id, comments amount, [(username, update type)])` of everything stored for the subscription.
+pub async fn stored(database: &mut Database, module: &ModuleConfig, id: &String) -> Vec<(u64, u64, Comments)> {
+  let mut torrents = database.get_torrents_from_db(module.module_type.to_string(), id).await.unwrap();
+  torrents.sort_by_key(|torrent| torrent.id);
+  torrents.into_iter().map(|torrent| (
+    torrent.id,
+    torrent.comments_amount,
+    torrent.comments.into_iter().map(|comment| (comment.user.username, comment.update_type)).collect()
+  )).collect()
+}
+
+pub fn names(comments: &[(&str, NyaaCommentUpdateType)]) -> Comments {
+  comments.iter().map(|(username, update_type)| (username.to_string(), update_type.clone())).collect()
+}
+
+pub fn without_torrent(listing: &str, id: u64) -> String {
+  let mut rows: Vec<&str> = listing.split("\t\t<tr ").collect();
+  rows.retain(|row| !row.contains(&format!("href=\"/view/{}\"", id)));
+  rows.join("\t\t<tr ")
+}
+
+/// Changes when a torrent on the listing was uploaded and how many seeders it has.
+pub fn with_swarm(listing: &str, id: u64, timestamp: i64, seeders: u64) -> String {
+  let cells = regex::Regex::new(r#"data-timestamp="\d+">([^<]*)</td>\s*<td class="text-center">\d+</td>"#).unwrap();
+  listing.split("\t\t<tr ").map(|row| match row.contains(&format!("href=\"/view/{}\"", id)) {
+    true => cells.replace(row, format!("data-timestamp=\"{}\">$1</td>\n\t\t\t<td class=\"text-center\">{}</td>", timestamp, seeders)).into_owned(),
+    false => row.to_string()
+  }).collect::<Vec<String>>().join("\t\t<tr ")
+}
+
+pub fn with_comment_count(listing: &str, id: u64, from: u64, to: u64) -> String {
+  listing.replace(
+    &format!("<a href=\"/view/{}#comments\" class=\"comments\" title=\"{} comments\">\n\t\t\t\t<i class=\"fa fa-comments-o\"></i>{}</a>", id, from, from),
+    &format!("<a href=\"/view/{}#comments\" class=\"comments\" title=\"{} comments\">\n\t\t\t\t<i class=\"fa fa-comments-o\"></i>{}</a>", id, to, to)
+  )
+}
+
+pub fn without_next_page(listing: &str) -> String {
+  listing.lines().filter(|line| !line.contains("rel=\"next\"")).collect::<Vec<&str>>().join("\n")
+}
+
+pub fn with_comment(view: &str, number: u64, username: &str, timestamp: i64, edited_timestamp: Option<i64>, message: &str) -> String {
+  let edited = edited_timestamp.map(|edited| format!(
+    "\t\t\t\t\t\t<small data-timestamp-swap data-timestamp=\"{}\" title=\"edited\">(edited)</small>\n", edited
+  )).unwrap_or_default();
+  let panel = format!(r##"		<div class="panel panel-default comment-panel" id="com-{number}">
+			<div class="panel-body">
+				<div class="col-md-2">
+					<p>
+						<a class="text-default" href="/user/{username}" data-toggle="tooltip" title="User">{username}</a>
+					</p>
+					<img class="avatar" src="/static/img/avatar/default.png" alt="User">
+				</div>
+				<div class="col-md-10 comment">
+					<div class="row comment-details">
+						<a href="#com-{number}"><small data-timestamp-swap data-timestamp="{timestamp}">now</small></a>
+{edited}						<div class="comment-actions">
+						</div>
+					</div>
+					<div class="row comment-body">
+						<div markdown-text class="comment-content" id="torrent-comment{number}">{message}</div>
+					</div>
+				</div>
+			</div>
+		</div>
+"##);
+  let end = view.rfind("\t</div>\n</div>\n\t\t</div>\n\t\t<footer").unwrap();
+  format!("{}{}{}", &view[..end], panel, &view[end..])
+}
+
+pub fn without_comment(view: &str, number: u64) -> String {
+  let start = view.find(&format!("\t\t<div class=\"panel panel-default comment-panel\" id=\"com-{}\">", number)).unwrap();
+  let end = view[start+1..].find("\t\t<div class=\"panel panel-default comment-panel\"")
+    .or_else(|| view[start+1..].find("\t</div>\n</div>\n\t\t</div>\n\t\t<footer"))
+    .unwrap() + start + 1;
+  format!("{}{}", &view[..start], &view[end..])
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use sqlx::sqlite::{
+  SqliteConnectOptions, SqlitePoolOptions
+};
+
+use common::*;
+use nyaa_notifications::{
+  set_paths, Paths
+};
+use nyaa_notifications::database::Database;
+use nyaa_notifications::web::NyaaCommentUpdateType::UNCHECKED;
+
+const LEGACY_DATA_TABLE: &str = r#"(
+  ID INTEGER,
+  Domain TEXT NOT NULL,
+  Title TEXT NOT NULL,
+  Category TEXT NOT NULL,
+  Size TEXT NOT NULL,
+  Magnet_Link TEXT NOT NULL,
+  Upload_Date_Str TEXT NOT NULL,
+  Upload_Date_Timestamp INTEGER,
+  Seeders INTEGER,
+  Leechers INTEGER,
+  Completed INTEGER,
+  Comments_Amount INTEGER,
+  Comments TEXT
+)"#;
+
+const LEGACY_COMMENTS: &str = r#"[{"user":{"anonymous":false,"role":"User","username":"fern","avatar":null,"banned":false},
+  "message":"Frieren-sama, please wake up.","old_message":null,"uploader":false,"date_timestamp":1710000000.0,"edited_timestamp":null,
+  "old_edited_timestamp":null,"direct_link":"https://nyaa.si/view/1720000#com-1","update_type":"UNCHECKED"}]"#;
+
+/// Writes the tables of a schema version 0 database the way it did, with its numbers bound as floats.
+async fn create_legacy_database(path: &std::path::Path) {
+  let pool = SqlitePoolOptions::new()
+    .connect_with(SqliteConnectOptions::new().filename(path).create_if_missing(true)).await.unwrap();
+  for table in ["_Email_0", "_Discord_123_456"] {
+    sqlx::query(&format!("CREATE TABLE {:?} {}", table, LEGACY_DATA_TABLE)).execute(&pool).await.unwrap();
+    sqlx::query(&format!("INSERT INTO {:?} VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)", table))
+      .bind(1720000.0)
+      .bind(DOMAIN)
+      .bind("[SubsPlease] Sousou no Frieren - 01 (1080p) [F02B9CEE].mkv")
+      .bind("Anime - English-translated")
+      .bind("1.4 GiB")
+      .bind("magnet:?xt=urn:btih:f02b9cee")
+      .bind("2024-03-09 16:00")
+      .bind(1710000000.0)
+      .bind(1500.0)
+      .bind(20.0)
+      .bind(9000.0)
+      .bind(1.0)
+      .bind(LEGACY_COMMENTS)
+    .execute(&pool).await.unwrap();
+  }
+  // A row version 0 could leave behind, which is skipped instead of stopping the migration.
+  sqlx::query(r#"INSERT INTO "_Email_0" (ID, Domain, Title, Category, Size, Magnet_Link, Upload_Date_Str) VALUES (NULL, ?, 'broken', '', '', '', '')"#)
+    .bind(DOMAIN)
+  .execute(&pool).await.unwrap();
+
+  sqlx::query(r#"CREATE TABLE "_Discord_123" (
+    Channel TEXT NOT NULL,
+    Feed TEXT NOT NULL,
+    Active INTEGER,
+    Comments INTEGER,
+    Uploads INTEGER,
+    Retrieve_All_Pages INTEGER,
+    Pinged_Role TEXT NOT NULL
+  )"#).execute(&pool).await.unwrap();
+  sqlx::query(r#"INSERT INTO "_Discord_123" VALUES ('456', 'https://nyaa.si/user/subsplease', 1, 1, 0, 0, '789')"#).execute(&pool).await.unwrap();
+  sqlx::query(r#"INSERT INTO "_Discord_123" VALUES ('not a channel', '', 1, 1, 1, 0, '')"#).execute(&pool).await.unwrap();
+  pool.close().await;
+}
+
+#[tokio::test]
+async fn version_0_tables_are_migrated() {
+  let scratch = Scratch::new("migration");
+  let paths = Paths::new(&scratch.path, None);
+  create_legacy_database(&paths.database()).await;
+  set_paths(paths);
+
+  let mut database = Database::new().await.unwrap();
+  for (module_type, id) in [("Email", "0"), ("Discord", "123_456")] {
+    let id = id.to_string();
+    assert!(database.subscription_indexed(module_type.to_string(), &id).await.unwrap());
+    let torrents = database.get_torrents_from_db(module_type.to_string(), &id).await.unwrap();
+    assert_eq!(torrents.len(), 1, "{}", module_type);
+    let torrent = &torrents[0];
+    assert_eq!((torrent.id, torrent.domain.as_str(), torrent.seeders, torrent.comments_amount), (1720000, DOMAIN, 1500, 1));
+    assert_eq!(torrent.title, "[SubsPlease] Sousou no Frieren - 01 (1080p) [F02B9CEE].mkv");
+    assert_eq!(torrent.release.episode, Some(1));
+    assert_eq!(torrent.comments.len(), 1);
+    assert_eq!((torrent.comments[0].user.username.as_str(), &torrent.comments[0].update_type), ("fern", &UNCHECKED));
+  }
+
+  let channels = database.get_discord_channels(&"123".to_string()).await.unwrap();
+  assert_eq!(channels.len(), 1);
+  assert_eq!(channels[0].discord_channel_id, Some(456));
+  assert_eq!(channels[0].discord_pinged_role, Some(789));
+  assert_eq!((channels[0].active, channels[0].comments, channels[0].uploads), (true, Some(true), Some(false)));
+  assert_eq!(channels[0].feeds.as_ref().unwrap()[0].url(), "https://nyaa.si/user/subsplease");
+
+  let legacy_tables: Vec<String> = sqlx::query_scalar(r#"SELECT name FROM sqlite_master WHERE type = 'table' AND name LIKE '\_%' ESCAPE '\'"#)
+  .fetch_all(&database.database).await.unwrap();
+  assert!(legacy_tables.is_empty(), "{:?}", legacy_tables);
+}
@@ -0,0 +1,445 @@
+mod common;
+
+use std::{
+  sync::Arc, time::Duration
+};
+
+use common::*;
+use nyaa_notifications::config::{
+  Config, ModuleConfig
+};
+use nyaa_notifications::notifications::Notifications;
+use nyaa_notifications::scheduler::{
+  check_module, Scheduler
+};
+use nyaa_notifications::web::NyaaCommentUpdateType::{DELETED, EDITED, NEW, UNCHECKED, UNDECIDED};
+
+const FEED_PAGE1: &str = "/user/subsplease?p=1";
+const FEED_PAGE2: &str = "/user/subsplease?p=2";
+
+fn discord_module() -> ModuleConfig {
+  module(r#"
+    module_type = "Discord"
+    active = true
+    feeds = [{ url = "https://nyaa.si/user/subsplease", mode = "Html" }]
+    comments = true
+    uploads = true
+    retrieve_all_pages = true
+    discord_bot_id = "bot"
+    discord_channel_id = 1
+  "#)
+}
+
+#[tokio::test]
+async fn polls_through_uploads_and_comment_changes() {
+  let scratch = Scratch::new("polling");
+  let nyaa = FakeServer::start().await;
+  let mut database = scratch.database().await;
+  let module = discord_module();
+  let id = "bot_1".to_string();
+
+  // 1. The first poll only indexes, nothing is announced.
+  let mut listing = without_torrent(LISTING, 1720002);
+  let mut view = VIEW_COMMENTS.to_string();
+  nyaa.serve(FEED_PAGE1, listing.clone());
+  nyaa.serve(FEED_PAGE2, LISTING_PAGE2);
+  nyaa.serve("/view/1720000", view.clone());
+  let updates = scratch.web(&nyaa).await.get_updates(&module, &id, &mut database).await.unwrap();
+  assert!(updates.is_empty());
+  assert_eq!(nyaa.take_requests(), vec![FEED_PAGE1, FEED_PAGE2, "/view/1720000"]);
+  let undecided = names(&[("himmel", UNDECIDED), ("subsplease", UNDECIDED), ("aura", UNDECIDED)]);
+  assert_eq!(stored(&mut database, &module, &id).await, vec![
+    (1710000, 0, vec![]),
+    (1720000, 3, undecided.clone()),
+    (1720001, 0, vec![])
+  ]);
+
+  // 2. A new upload, the uploader's avatar comes from the user page.
+  listing = LISTING.to_string();
+  nyaa.serve(FEED_PAGE1, listing.clone());
+  nyaa.serve("/view/1720002", VIEW_UPLOAD);
+  nyaa.serve("/user/subsplease", USER);
+  let updates = scratch.web(&nyaa).await.get_updates(&module, &id, &mut database).await.unwrap();
+  assert_eq!(summary(&updates), vec![(1720002, true, vec![])]);
+  let uploader = updates[0].torrent.uploader.clone().unwrap();
+  assert_eq!(uploader.username, "subsplease");
+  assert_eq!(uploader.avatar.as_deref(), Some("https://i.imgur.com/subsplease.png"));
+  assert_eq!(nyaa.take_requests(), vec![FEED_PAGE1, FEED_PAGE2, "/view/1720002", "/user/subsplease"]);
+  deliver(&mut database, &module, &id, &updates).await;
+  assert_eq!(stored(&mut database, &module, &id).await.iter().map(|torrent| torrent.0).collect::<Vec<u64>>(), vec![1710000, 1720000, 1720001, 1720002]);
+  let torrents = database.get_torrents_from_db(module.module_type.to_string(), &id).await.unwrap();
+  let release = &torrents.iter().find(|torrent| torrent.id == 1720002).unwrap().release;
+  assert_eq!(release, &updates[0].torrent.release);
+  assert_eq!((release.series.as_deref(), release.episode, release.crc.as_deref()), (Some("Sousou no Frieren"), Some(2), Some("8B7A61A2")));
+
+  // 3. A comment is added. It's a bit over an hour old, so that the next poll re-checks it for edits.
+  let comment_timestamp = chrono::Utc::now().timestamp() - 3630;
+  listing = with_comment_count(&listing, 1720000, 3, 4);
+  view = with_comment(&view, 4, "fern", comment_timestamp, None, "Frieren-sama, please wake up.");
+  nyaa.serve(FEED_PAGE1, listing.clone());
+  nyaa.serve("/view/1720000", view.clone());
+  let updates = scratch.web(&nyaa).await.get_updates(&module, &id, &mut database).await.unwrap();
+  assert_eq!(summary(&updates), vec![
+    (1720000, false, names(&[("fern", NEW), ("himmel", UNDECIDED), ("subsplease", UNDECIDED), ("aura", UNDECIDED)]))
+  ]);
+  deliver(&mut database, &module, &id, &updates).await;
+  assert_eq!(stored(&mut database, &module, &id).await[1], (1720000, 4, names(&[("fern", UNCHECKED), ("himmel", UNDECIDED), ("subsplease", UNDECIDED), ("aura", UNDECIDED)])));
+
+  // 4. The comment is edited, which doesn't change the comment count on the listing.
+  let edited_view = without_comment(&view, 4);
+  view = with_comment(&edited_view, 4, "fern", comment_timestamp, Some(chrono::Utc::now().timestamp() - 60), "Frieren-sama, breakfast is ready.");
+  nyaa.serve("/view/1720000", view.clone());
+  let updates = scratch.web(&nyaa).await.get_updates(&module, &id, &mut database).await.unwrap();
+  assert_eq!(summary(&updates), vec![
+    (1720000, false, names(&[("fern", EDITED), ("himmel", UNDECIDED), ("subsplease", UNDECIDED), ("aura", UNDECIDED)]))
+  ]);
+  assert_eq!(updates[0].torrent.comments[0].old_message.as_deref(), Some("Frieren-sama, please wake up."));
+  assert_eq!(updates[0].torrent.comments[0].message, "Frieren-sama, breakfast is ready.");
+  deliver(&mut database, &module, &id, &updates).await;
+
+  // 5. A comment is deleted.
+  listing = with_comment_count(&listing, 1720000, 4, 3);
+  view = without_comment(&view, 1);
+  nyaa.serve(FEED_PAGE1, listing.clone());
+  nyaa.serve("/view/1720000", view.clone());
+  let updates = scratch.web(&nyaa).await.get_updates(&module, &id, &mut database).await.unwrap();
+  assert_eq!(summary(&updates), vec![
+    (1720000, false, names(&[("himmel", DELETED), ("fern", UNCHECKED), ("subsplease", UNDECIDED), ("aura", UNDECIDED)]))
+  ]);
+  deliver(&mut database, &module, &id, &updates).await;
+  assert_eq!(stored(&mut database, &module, &id).await[1], (1720000, 3, names(&[("fern", UNCHECKED), ("subsplease", UNDECIDED), ("aura", UNDECIDED)])));
+
+  // 6. The feed shrinks to one page. Torrents which fell off are kept, and the only update is the
+  // still unchecked comment being looked at once more.
+  nyaa.serve(FEED_PAGE1, without_next_page(&listing));
+  nyaa.take_requests();
+  let updates = scratch.web(&nyaa).await.get_updates(&module, &id, &mut database).await.unwrap();
+  assert_eq!(summary(&updates), vec![
+    (1720000, false, names(&[("fern", UNCHECKED), ("subsplease", UNDECIDED), ("aura", UNDECIDED)]))
+  ]);
+  assert_eq!(nyaa.take_requests(), vec![FEED_PAGE1, "/view/1720000"]);
+  assert_eq!(stored(&mut database, &module, &id).await.len(), 4);
+}
+
+#[tokio::test]
+async fn rss_feed_is_read_like_the_listing() {
+  let scratch = Scratch::new("rss");
+  let nyaa = FakeServer::start().await;
+  let mut database = scratch.database().await;
+  let module = module(r#"
+    module_type = "Gotify"
+    active = true
+    feeds = ["https://nyaa.si/user/subsplease"]
+    feed_mode = "Rss"
+    comments = false
+    uploads = true
+    retrieve_all_pages = false
+  "#);
+  let id = "0".to_string();
+
+  let rss = RSS.to_string();
+  let first_item = rss.find("\t\t<item>").unwrap();
+  let second_item = rss[first_item+1..].find("\t\t<item>").unwrap() + first_item + 1;
+  nyaa.serve("/?page=rss&u=subsplease", format!("{}{}", &rss[..first_item], &rss[second_item..]));
+  let updates = scratch.web(&nyaa).await.get_updates(&module, &id, &mut database).await.unwrap();
+  assert!(updates.is_empty());
+
+  nyaa.serve("/?page=rss&u=subsplease", rss);
+  let updates = scratch.web(&nyaa).await.get_updates(&module, &id, &mut database).await.unwrap();
+  assert_eq!(summary(&updates), vec![(1720002, true, vec![])]);
+  let torrent = &updates[0].torrent;
+  assert_eq!(torrent.domain, DOMAIN);
+  assert_eq!(torrent.title, "[SubsPlease] Sousou no Frieren - 02 (1080p) [8B7A61A2].mkv");
+  assert_eq!(torrent.category, "Anime - English-translated");
+  assert_eq!(torrent.size, "1.3 GiB");
+  assert_eq!(torrent.seeders, 988);
+  assert!(torrent.trusted && !torrent.remake);
+  assert_eq!(torrent.upload_date_timestamp, 1696568762.0);
+  assert!(torrent.magnet_link.starts_with("magnet:?xt=urn:btih:8b7a61a2f5d5e5bd7ec3c24a1c6dcd1ea3fb6c2b&dn="));
+  assert_eq!(nyaa.take_requests(), vec!["/?page=rss&u=subsplease", "/?page=rss&u=subsplease"]);
+}
+
+#[tokio::test]
+async fn filtered_torrents_are_stored_without_being_sent() {
+  let scratch = Scratch::new("filters");
+  let nyaa = FakeServer::start().await;
+  let mut database = scratch.database().await;
+  let transport = Arc::new(RecordingTransport::default());
+  let notifications = Notifications::with_transport(transport.clone());
+  let module = module(r#"
+    module_type = "Gotify"
+    active = true
+    feeds = [{ url = "https://nyaa.si/user/subsplease", mode = "Html", title_exclude = [" - 01 "] }]
+    comments = true
+    uploads = true
+    retrieve_all_pages = false
+    title_include = ["1080p"]
+    gotify_domain = "https://gotify.example.com"
+    gotify_token = "secret"
+  "#);
+  let id = "0".to_string();
+
+  let listing = without_next_page(LISTING);
+  nyaa.serve(FEED_PAGE1, without_torrent(&without_torrent(&listing, 1720002), 1720001));
+  nyaa.serve("/view/1720000", VIEW_COMMENTS);
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &id).await.unwrap();
+
+  // Only the new 1080p episode gets through, the 720p one is stored all the same.
+  nyaa.serve(FEED_PAGE1, listing.clone());
+  nyaa.serve("/view/1720002", VIEW_UPLOAD);
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &id).await.unwrap();
+  let sent = transport.take_sent();
+  assert_eq!(sent.len(), 1);
+  assert!(sent[0].text().contains("Sousou no Frieren - 02 (1080p)"), "{}", sent[0].text());
+  assert_eq!(stored(&mut database, &module, &id).await.iter().map(|torrent| torrent.0).collect::<Vec<u64>>(), vec![1720000, 1720001, 1720002]);
+
+  // The feed's own rule holds back the comment on the first episode, and it doesn't come up again.
+  nyaa.serve(FEED_PAGE1, with_comment_count(&listing, 1720000, 3, 4));
+  nyaa.serve("/view/1720000", with_comment(VIEW_COMMENTS, 4, "fern", chrono::Utc::now().timestamp() - 60, None, "Frieren-sama, please wake up."));
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &id).await.unwrap();
+  assert!(transport.take_sent().is_empty());
+  assert_eq!(stored(&mut database, &module, &id).await[0], (1720000, 4, names(&[("fern", UNDECIDED), ("himmel", UNDECIDED), ("subsplease", UNDECIDED), ("aura", UNDECIDED)])));
+  nyaa.take_requests();
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &id).await.unwrap();
+  assert!(transport.take_sent().is_empty());
+  assert!(!nyaa.take_requests().iter().any(|request| request.starts_with("/view/")));
+}
+
+#[tokio::test]
+async fn uploads_wait_for_their_swarm() {
+  let scratch = Scratch::new("swarm");
+  let nyaa = FakeServer::start().await;
+  let mut database = scratch.database().await;
+  let transport = Arc::new(RecordingTransport::default());
+  let notifications = Notifications::with_transport(transport.clone());
+  let module = module(r#"
+    module_type = "Gotify"
+    active = true
+    feeds = [{ url = "https://nyaa.si/user/subsplease", mode = "Html" }]
+    comments = false
+    uploads = true
+    retrieve_all_pages = false
+    categories = ["1_0"]
+    min_size = "200 MiB"
+    max_size = "4 GiB"
+    min_seeders = 1000
+    swarm_wait_hours = 6
+    gotify_domain = "https://gotify.example.com"
+    gotify_token = "secret"
+  "#);
+  let id = "0".to_string();
+
+  let listing = without_next_page(LISTING);
+  nyaa.serve(FEED_PAGE1, without_torrent(&without_torrent(&listing, 1720002), 1720001));
+  nyaa.serve("/view/1720000", VIEW_COMMENTS);
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &id).await.unwrap();
+
+  // The fresh upload is short of seeders and stays unstored, the week old one is dropped.
+  let uploaded = chrono::Utc::now().timestamp() - 600;
+  nyaa.serve(FEED_PAGE1, with_swarm(&listing, 1720002, uploaded, 988));
+  nyaa.serve("/view/1720002", VIEW_UPLOAD);
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &id).await.unwrap();
+  assert!(transport.take_sent().is_empty());
+  assert_eq!(stored(&mut database, &module, &id).await.iter().map(|torrent| torrent.0).collect::<Vec<u64>>(), vec![1720000, 1720001]);
+
+  // Once it has enough, it's announced.
+  nyaa.serve(FEED_PAGE1, with_swarm(&listing, 1720002, uploaded, 1200));
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &id).await.unwrap();
+  let sent = transport.take_sent();
+  assert_eq!(sent.len(), 1);
+  assert!(sent[0].text().contains("Sousou no Frieren - 02 (1080p)"), "{}", sent[0].text());
+  assert_eq!(stored(&mut database, &module, &id).await.iter().map(|torrent| torrent.0).collect::<Vec<u64>>(), vec![1720000, 1720001, 1720002]);
+}
+
+#[tokio::test]
+async fn remakes_and_anonymous_uploads_can_be_skipped() {
+  let scratch = Scratch::new("status");
+  let nyaa = FakeServer::start().await;
+  let mut database = scratch.database().await;
+  let transport = Arc::new(RecordingTransport::default());
+  let notifications = Notifications::with_transport(transport.clone());
+  let module = module(r#"
+    module_type = "Gotify"
+    active = true
+    feeds = [{ url = "https://nyaa.si/user/subsplease", mode = "Html", skip_anonymous = true }]
+    comments = false
+    uploads = true
+    retrieve_all_pages = false
+    skip_remakes = true
+    gotify_domain = "https://gotify.example.com"
+    gotify_token = "secret"
+  "#);
+  let id = "0".to_string();
+
+  let listing = without_next_page(LISTING);
+  nyaa.serve(FEED_PAGE1, without_torrent(&without_torrent(&listing, 1720002), 1720001));
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &id).await.unwrap();
+
+  // 1720001 is a remake, and 1720002's page is read for the submitter even though gotify doesn't show it.
+  nyaa.serve(FEED_PAGE1, listing);
+  nyaa.serve("/view/1720001", VIEW_UPLOAD);
+  nyaa.serve("/view/1720002", VIEW_ANONYMOUS);
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &id).await.unwrap();
+  assert!(transport.take_sent().is_empty());
+  assert_eq!(stored(&mut database, &module, &id).await.iter().map(|torrent| torrent.0).collect::<Vec<u64>>(), vec![1720000, 1720001, 1720002]);
+}
+
+#[tokio::test]
+async fn series_alerts_come_with_their_upload() {
+  let scratch = Scratch::new("series");
+  let nyaa = FakeServer::start().await;
+  let mut database = scratch.database().await;
+  let transport = Arc::new(RecordingTransport::default());
+  let notifications = Notifications::with_transport(transport.clone());
+  let module = module(r#"
+    module_type = "Gotify"
+    active = true
+    feeds = [{ url = "https://nyaa.si/user/subsplease", mode = "Html" }]
+    comments = false
+    uploads = true
+    retrieve_all_pages = false
+    series_alerts = true
+    gotify_domain = "https://gotify.example.com"
+    gotify_token = "secret"
+  "#);
+  let id = "0".to_string();
+
+  let listing = without_next_page(LISTING).replace("Sousou no Frieren - 02 (1080p)", "Sousou no Frieren - 04 (1080p)");
+  nyaa.serve(FEED_PAGE1, without_torrent(&without_torrent(&listing, 1720002), 1720001));
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &id).await.unwrap();
+
+  // Episode 1 was indexed, so 4 skips two.
+  nyaa.serve(FEED_PAGE1, without_torrent(&listing, 1720001));
+  nyaa.serve("/view/1720002", VIEW_UPLOAD);
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &id).await.unwrap();
+  let sent = transport.take_sent();
+  assert_eq!(sent.len(), 2);
+  assert!(sent[0].text().contains("Sousou no Frieren - 04 (1080p)"), "{}", sent[0].text());
+  assert!(sent[1].text().contains("Episodes 2-3 of Sousou no Frieren by SubsPlease are missing."), "{}", sent[1].text());
+
+  // A fixed episode 4 is a duplicate, nothing is missing anymore.
+  nyaa.serve(FEED_PAGE1, listing.replace("Sousou no Frieren - 01 (720p)", "Sousou no Frieren - 04v2 (1080p)"));
+  nyaa.serve("/view/1720001", VIEW_UPLOAD);
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &id).await.unwrap();
+  let sent = transport.take_sent();
+  assert_eq!(sent.len(), 2);
+  assert!(sent[1].text().contains("Episode 4 of Sousou no Frieren by SubsPlease was re-released as v2."), "{}", sent[1].text());
+}
+
+#[tokio::test]
+async fn unchanged_pages_are_read_from_the_cache() {
+  let scratch = Scratch::new("not-modified");
+  let nyaa = FakeServer::start().await;
+  let mut database = scratch.database().await;
+  let transport = Arc::new(RecordingTransport::default());
+  let notifications = Notifications::with_transport(transport.clone());
+  let module = module(r#"
+    module_type = "Gotify"
+    active = true
+    feeds = [{ url = "https://nyaa.si/user/subsplease", mode = "Html" }]
+    comments = false
+    uploads = true
+    retrieve_all_pages = false
+    gotify_domain = "https://gotify.example.com"
+    gotify_token = "secret"
+  "#);
+  let (first, second) = ("0".to_string(), "1".to_string());
+
+  let listing = without_next_page(LISTING);
+  nyaa.serve(FEED_PAGE1, without_torrent(&listing, 1720002));
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &first).await.unwrap();
+
+  // The second subscription indexes the whole listing, which is cached along with its ETag.
+  nyaa.serve(FEED_PAGE1, listing.clone());
+  nyaa.etag(FEED_PAGE1, "\"listing\"");
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &second).await.unwrap();
+
+  // The server's copy no longer has 1720002, but it answers with a 304, so the cached page is what's read.
+  nyaa.serve(FEED_PAGE1, without_torrent(&listing, 1720002));
+  nyaa.serve("/view/1720002", VIEW_UPLOAD);
+  check_module(&scratch.web(&nyaa).await, &notifications, &mut database, &module, &first).await.unwrap();
+  let sent = transport.take_sent();
+  assert_eq!(sent.len(), 1);
+  assert!(sent[0].text().contains("Sousou no Frieren - 02 (1080p)"), "{}", sent[0].text());
+}
+
+#[tokio::test]
+async fn scheduler_runs_each_module_on_its_own() {
+  let scratch = Scratch::new("scheduler");
+  let nyaa = FakeServer::start().await;
+  let database = scratch.database().await;
+  let transport = Arc::new(RecordingTransport::default());
+  let config: Config = toml::from_str(r#"
+    update_interval = 60
+    [[module]]
+    module_type = "Gotify"
+    active = true
+    feeds = ["https://nyaa.si/user/subsplease"]
+    comments = false
+    uploads = true
+    retrieve_all_pages = false
+    gotify_domain = "https://gotify.example.com"
+    gotify_token = "secret"
+    [[module]]
+    module_type = "Gotify"
+    active = true
+    update_interval = 5
+    feeds = ["https://nyaa.si/user/erai-raws"]
+    comments = false
+    uploads = true
+    retrieve_all_pages = false
+    gotify_domain = "https://gotify.example.com"
+    gotify_token = "secret"
+  "#).unwrap();
+  nyaa.serve(FEED_PAGE1, without_next_page(LISTING));
+  nyaa.serve("/user/erai-raws?p=1", without_next_page(LISTING));
+
+  let scheduler = Scheduler::new(&config, Notifications::with_transport(transport.clone()), &database)
+    .with_web(scratch.web(&nyaa).await);
+  let modules = scheduler.modules();
+  let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+  let started = chrono::Utc::now();
+  let running = tokio::spawn(scheduler.run_until(config.module.clone(), async {
+    let _ = stopped.await;
+  }));
+  checked(&modules, "Gotify_0", started).await;
+  checked(&modules, "Gotify_1", started).await;
+  let control = |key: &str| modules.read().unwrap().get(key).unwrap().clone();
+  let (first, second) = (control("Gotify_0"), control("Gotify_1"));
+  assert_eq!(first.interval, Duration::from_secs(60 * 60));
+  assert_eq!(second.interval, Duration::from_secs(5 * 60));
+  nyaa.take_requests();
+
+  // A paused module skips the check it's woken for.
+  second.pause(true);
+  let last_check = second.status().last_check;
+  second.check_now();
+  tokio::time::sleep(Duration::from_millis(100)).await;
+  assert!(nyaa.take_requests().is_empty());
+  assert_eq!(second.status().last_check, last_check);
+
+  // Resumed, it checks right away instead of after its interval.
+  second.pause(false);
+  let triggered = chrono::Utc::now();
+  second.check_now();
+  checked(&modules, "Gotify_1", triggered).await;
+  assert!(nyaa.take_requests().contains(&"/user/erai-raws?p=1".to_string()));
+
+  // Shutting down waits for the check which is still running.
+  nyaa.delay(FEED_PAGE1, Duration::from_millis(300));
+  let triggered = chrono::Utc::now();
+  first.check_now();
+  for _ in 0..200 {
+    if first.status().checking {
+      break;
+    }
+    tokio::time::sleep(Duration::from_millis(5)).await;
+  }
+  assert!(first.status().checking);
+  stop.send(()).unwrap();
+  running.await.unwrap().unwrap();
+  let status = first.status();
+  assert!(!status.checking);
+  assert!(status.last_check.is_some_and(|last_check| last_check >= triggered));
+  assert!(status.last_error.is_none(), "{:?}", status.last_error);
+}